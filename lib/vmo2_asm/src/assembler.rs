@@ -0,0 +1,400 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use vmo2_types::bytecode::ByteCode;
+use vmo2_types::opcode::*;
+use vmo2_types::value::Value;
+
+/// An error in a `.vasm` source, located at its 1-based line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub kind: AsmErrorKind,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    /// The mnemonic needs an operand that is missing.
+    MissingOperand(String),
+    /// Text left after the instruction and its operand.
+    UnexpectedInput(String),
+    InvalidLiteral(String),
+    /// A count or address that is not a `u32`.
+    InvalidNumber(String),
+    InvalidLabel(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+}
+
+impl fmt::Display for AsmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmErrorKind::UnknownMnemonic(name) => write!(f, "unknown instruction `{name}`"),
+            AsmErrorKind::MissingOperand(name) => write!(f, "`{name}` expects an operand"),
+            AsmErrorKind::UnexpectedInput(text) => write!(f, "unexpected `{text}`"),
+            AsmErrorKind::InvalidLiteral(text) => write!(f, "invalid literal `{text}`"),
+            AsmErrorKind::InvalidNumber(text) => write!(f, "invalid number `{text}`"),
+            AsmErrorKind::InvalidLabel(name) => write!(f, "invalid label `{name}`"),
+            AsmErrorKind::DuplicateLabel(name) => write!(f, "label `{name}` is defined twice"),
+            AsmErrorKind::UndefinedLabel(name) => write!(f, "undefined label `{name}`"),
+        }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Parses the `.vasm` literal at the start of `text`, returning it with the
+/// text that follows.
+pub fn parse_literal(text: &str) -> Result<(Value, &str), AsmErrorKind> {
+    let mut cursor = Cursor::new(text);
+    let value = cursor.literal()?;
+    Ok((value, cursor.rest))
+}
+
+/// Operand of a flow instruction before labels are resolved.
+enum Target {
+    Address(u32),
+    Label(String),
+}
+
+/// A flow instruction waiting for its target address.
+struct Fixup {
+    address: usize,
+    target: Target,
+    line: usize,
+}
+
+/*
+ *  Assembles `.vasm` source (see the crate documentation) into bytecode.
+ *
+ *  The first pass parses every line, records the address of each label and
+ *  emits flow opcodes with a placeholder target. The second pass patches
+ *  those targets once every label is known, so jumps can go forward.
+ */
+pub fn assemble(source: &str) -> Result<ByteCode, AsmError> {
+    let mut bytecode = ByteCode::new();
+    let mut labels: HashMap<String, u32> = HashMap::new();
+    let mut fixups = Vec::new();
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |kind| AsmError { kind, line };
+        let mut cursor = Cursor::new(text);
+
+        // address column printed by the disassembler
+        if cursor.peek().is_some_and(|c| c.is_ascii_digit()) {
+            cursor.word();
+        }
+
+        let Some(mut name) = cursor.identifier() else {
+            cursor.end().map_err(error)?;
+            continue;
+        };
+        if cursor.eat(':') {
+            let address = bytecode.opcodes.len() as u32;
+            if labels.insert(name.to_string(), address).is_some() {
+                return Err(error(AsmErrorKind::DuplicateLabel(name.to_string())));
+            }
+            match cursor.identifier() {
+                Some(next) => name = next,
+                None => {
+                    cursor.end().map_err(error)?;
+                    continue;
+                }
+            }
+        }
+
+        let opcode = match name {
+            "push" => Opcode::Literal(cursor.literal().map_err(error)?),
+            "build_list" | "build_map" => {
+                let count = cursor.count(name).map_err(error)?;
+                match name {
+                    "build_list" => Opcode::Collection(CollectionOpcode::BuildList(count)),
+                    _ => Opcode::Collection(CollectionOpcode::BuildMap(count)),
+                }
+            }
+            "jump" | "jump_if_false" | "jump_if_true" | "call" => {
+                let target = cursor.target(name).map_err(error)?;
+                fixups.push(Fixup {
+                    address: bytecode.opcodes.len(),
+                    target,
+                    line,
+                });
+                Opcode::Flow(match name {
+                    "jump" => FlowOpcode::Jump(0),
+                    "jump_if_false" => FlowOpcode::JumpIfFalse(0),
+                    "jump_if_true" => FlowOpcode::JumpIfTrue(0),
+                    _ => FlowOpcode::Call(0),
+                })
+            }
+            _ => simple_opcode(name)
+                .ok_or_else(|| error(AsmErrorKind::UnknownMnemonic(name.to_string())))?,
+        };
+        cursor.end().map_err(error)?;
+        bytecode.add_opcode(opcode);
+    }
+
+    for fixup in fixups {
+        let address = match fixup.target {
+            Target::Address(address) => address,
+            Target::Label(name) => *labels.get(&name).ok_or(AsmError {
+                kind: AsmErrorKind::UndefinedLabel(name),
+                line: fixup.line,
+            })?,
+        };
+        if let Opcode::Flow(
+            FlowOpcode::Jump(target)
+            | FlowOpcode::JumpIfFalse(target)
+            | FlowOpcode::JumpIfTrue(target)
+            | FlowOpcode::Call(target),
+        ) = &mut bytecode.opcodes[fixup.address]
+        {
+            *target = address;
+        }
+    }
+
+    Ok(bytecode)
+}
+
+/// Opcodes without an operand.
+fn simple_opcode(name: &str) -> Option<Opcode> {
+    Some(match name {
+        "halt" => Opcode::Halt,
+        "add" => Opcode::Arithmetic(ArithmeticOpcode::Add),
+        "sub" => Opcode::Arithmetic(ArithmeticOpcode::Sub),
+        "mul" => Opcode::Arithmetic(ArithmeticOpcode::Mul),
+        "div" => Opcode::Arithmetic(ArithmeticOpcode::Div),
+        "mod" => Opcode::Arithmetic(ArithmeticOpcode::Mod),
+        "neg" => Opcode::Arithmetic(ArithmeticOpcode::Neg),
+        "and" => Opcode::Logic(LogicOpcode::And),
+        "or" => Opcode::Logic(LogicOpcode::Or),
+        "xor" => Opcode::Logic(LogicOpcode::Xor),
+        "not" => Opcode::Logic(LogicOpcode::Not),
+        "eq" => Opcode::Comparison(ComparisonOpcode::Eq),
+        "ne" => Opcode::Comparison(ComparisonOpcode::Ne),
+        "lt" => Opcode::Comparison(ComparisonOpcode::Lt),
+        "le" => Opcode::Comparison(ComparisonOpcode::Le),
+        "gt" => Opcode::Comparison(ComparisonOpcode::Gt),
+        "ge" => Opcode::Comparison(ComparisonOpcode::Ge),
+        "load" => Opcode::Memory(MemoryOpcode::Load),
+        "store" => Opcode::Memory(MemoryOpcode::Store),
+        "print" => Opcode::IO(IOOpcode::Print),
+        "scan" => Opcode::IO(IOOpcode::Scan),
+        "return" => Opcode::Flow(FlowOpcode::Return),
+        "get" => Opcode::Collection(CollectionOpcode::Get),
+        "set" => Opcode::Collection(CollectionOpcode::Set),
+        "len" => Opcode::Collection(CollectionOpcode::Len),
+        "list_push" => Opcode::Collection(CollectionOpcode::Push),
+        "list_pop" => Opcode::Collection(CollectionOpcode::Pop),
+        "keys" => Opcode::Collection(CollectionOpcode::Keys),
+        "contains" => Opcode::Collection(CollectionOpcode::Contains),
+        "dup" => Opcode::Dup,
+        "pop" => Opcode::Pop,
+        "swap" => Opcode::Swap,
+        _ => return None,
+    })
+}
+
+/// Reads one line of source, whitespace between tokens is skipped.
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(line: &'a str) -> Self {
+        Self { rest: line }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest.chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.rest = &self.rest[c.len_utf8()..];
+            return true;
+        }
+        false
+    }
+
+    /// Only whitespace or a comment may follow.
+    fn end(&mut self) -> Result<(), AsmErrorKind> {
+        match self.peek() {
+            None | Some(';') => Ok(()),
+            Some(_) => Err(AsmErrorKind::UnexpectedInput(
+                self.rest.trim_end().to_string(),
+            )),
+        }
+    }
+
+    /// Characters up to the next whitespace or delimiter.
+    fn word(&mut self) -> &'a str {
+        self.skip_whitespace();
+        let end = self
+            .rest
+            .find(|c: char| c.is_whitespace() || ",:;[]{}\"".contains(c))
+            .unwrap_or(self.rest.len());
+        let (word, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        word
+    }
+
+    fn identifier(&mut self) -> Option<&'a str> {
+        let c = self.peek()?;
+        if !(c.is_ascii_alphabetic() || c == '_' || c == '.') {
+            return None;
+        }
+        Some(self.word())
+    }
+
+    fn count(&mut self, name: &str) -> Result<u32, AsmErrorKind> {
+        match self.word() {
+            "" => Err(AsmErrorKind::MissingOperand(name.to_string())),
+            word => word
+                .parse()
+                .map_err(|_| AsmErrorKind::InvalidNumber(word.to_string())),
+        }
+    }
+
+    fn target(&mut self, name: &str) -> Result<Target, AsmErrorKind> {
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => self.count(name).map(Target::Address),
+            _ => match self.identifier() {
+                Some(label)
+                    if label
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || "_.".contains(c)) =>
+                {
+                    Ok(Target::Label(label.to_string()))
+                }
+                Some(label) => Err(AsmErrorKind::InvalidLabel(label.to_string())),
+                None => Err(AsmErrorKind::MissingOperand(name.to_string())),
+            },
+        }
+    }
+
+    fn literal(&mut self) -> Result<Value, AsmErrorKind> {
+        match self.peek() {
+            None | Some(';') => Err(AsmErrorKind::MissingOperand("push".to_string())),
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.eat('[');
+                let mut items = Vec::new();
+                if !self.eat(']') {
+                    loop {
+                        items.push(self.literal()?);
+                        if self.eat(']') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Value::List(items))
+            }
+            Some('{') => {
+                self.eat('{');
+                let mut entries = BTreeMap::new();
+                if !self.eat('}') {
+                    loop {
+                        let key = self.literal()?;
+                        let key = key
+                            .clone()
+                            .map_key()
+                            .map_err(|_| AsmErrorKind::InvalidLiteral(key.to_string()))?;
+                        self.expect(':')?;
+                        entries.insert(key, self.literal()?);
+                        if self.eat('}') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Value::Map(entries))
+            }
+            Some(_) => scalar(self.word()),
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), AsmErrorKind> {
+        if self.eat(c) {
+            return Ok(());
+        }
+        Err(AsmErrorKind::InvalidLiteral(
+            self.rest.trim_end().to_string(),
+        ))
+    }
+
+    /// A double quoted string with `\"`, `\\`, `\n`, `\r`, `\t` and
+    /// `\u{hex}` escapes.
+    fn string(&mut self) -> Result<String, AsmErrorKind> {
+        let invalid = |text: &str| AsmErrorKind::InvalidLiteral(text.to_string());
+        let start = self.rest;
+        let mut chars = self.rest.char_indices().skip(1);
+        let mut value = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[i + 1..];
+                    return Ok(value);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some('u') => {
+                        let mut hex = String::new();
+                        if chars.next().map(|(_, c)| c) != Some('{') {
+                            return Err(invalid(start));
+                        }
+                        for (_, c) in chars.by_ref() {
+                            if c == '}' {
+                                break;
+                            }
+                            hex.push(c);
+                        }
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| invalid(start))?;
+                        value.push(c);
+                    }
+                    _ => return Err(invalid(start)),
+                },
+                c => value.push(c),
+            }
+        }
+        Err(invalid(start))
+    }
+}
+
+/// A number, bool or null literal.
+fn scalar(word: &str) -> Result<Value, AsmErrorKind> {
+    let invalid = || AsmErrorKind::InvalidLiteral(word.to_string());
+    match word {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        "null" => return Ok(Value::Null),
+        "NaN" | "inf" | "-inf" => return Ok(Value::Float(word.parse().unwrap())),
+        _ => {}
+    }
+    if let Some(int) = word.strip_suffix('i') {
+        return int.parse().map(Value::Int).map_err(|_| invalid());
+    }
+    if word.contains(['.', 'e', 'E']) {
+        return word.parse().map(Value::Float).map_err(|_| invalid());
+    }
+    word.parse().map(Value::UInt).map_err(|_| invalid())
+}
@@ -0,0 +1,55 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use vmo2_serde::traits::DeserializationError;
+use vmo2_types::bytecode::ByteCode;
+
+pub use vmo2_types::format::{flow_target, format_instruction, mnemonic, write_literal};
+
+/// Label the disassembler gives to `address`.
+fn label(address: u32) -> String {
+    format!("L{address}")
+}
+
+/*
+ *  Prints `bytecode` as `.vasm`, every instruction prefixed by its address.
+ *  Each jump or call target inside the program (or right after its last
+ *  opcode) gets an `L<address>:` label that the flow opcodes refer to,
+ *  targets past that are printed as plain addresses. Assembling the output
+ *  gives back `bytecode`.
+ */
+pub fn disassemble(bytecode: &ByteCode) -> String {
+    let len = bytecode.opcodes.len() as u32;
+    let labels: BTreeSet<u32> = bytecode
+        .opcodes
+        .iter()
+        .filter_map(flow_target)
+        .filter(|target| *target <= len)
+        .collect();
+
+    let mut out = String::new();
+    for (address, opcode) in bytecode.opcodes.iter().enumerate() {
+        let address = address as u32;
+        if labels.contains(&address) {
+            writeln!(out, "{}:", label(address)).unwrap();
+        }
+
+        match flow_target(opcode) {
+            Some(target) if labels.contains(&target) => {
+                write!(out, "{address:>6}  {} {}", mnemonic(opcode), label(target)).unwrap()
+            }
+            _ => write!(out, "{address:>6}  {}", format_instruction(opcode)).unwrap(),
+        }
+        out.push('\n');
+    }
+    if labels.contains(&len) {
+        writeln!(out, "{}:", label(len)).unwrap();
+    }
+
+    out
+}
+
+/// Disassembles a serialized program, as written by `vmo2_serde`.
+pub fn disassemble_serialized(data: &[u8]) -> Result<String, DeserializationError> {
+    let bytecode = vmo2_serde::deserialize::deserialize(data)?;
+    Ok(disassemble(&bytecode))
+}
@@ -0,0 +1,28 @@
+/*
+ *  `.vasm`, the textual form of `ByteCode`.
+ *
+ *  One instruction per line, `;` starts a comment. Labels are written as
+ *  `name:` and can be used instead of an absolute address by `jump`,
+ *  `jump_if_false`, `jump_if_true` and `call`:
+ *
+ *          push 0
+ *          push "n"
+ *          store
+ *      loop:
+ *          push "n"
+ *          load
+ *          jump_if_false done   ; addresses work too: `jump 3`
+ *          ...
+ *      done:
+ *          halt
+ *
+ *  Literals follow the `Value` variants: `42` is a uint, `-42i` an int,
+ *  `1.5`, `1e10`, `NaN`, `inf` and `-inf` are floats, then `true`, `null`,
+ *  `"escaped\n"` strings, `[1, "a"]` lists and `{"a": 1, 2: null}` maps.
+ *
+ *  A line may start with the address the disassembler printed in front of
+ *  the instruction, the assembler skips it.
+ */
+pub mod assembler;
+pub mod disassembler;
+mod test;
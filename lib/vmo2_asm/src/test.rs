@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod tests {
+    use crate::assembler::*;
+    use crate::disassembler::*;
+    use quickcheck_macros::quickcheck;
+    use vmo2_serde::metadata::Version;
+    use vmo2_serde::serialize::serialize;
+    use vmo2_types::bytecode::ByteCode;
+    use vmo2_types::opcode::{Opcode::*, *};
+    use vmo2_types::value::Value;
+
+    #[test]
+    fn test_assemble_labels_and_comments() {
+        let bytecode = assemble(
+            r#"
+            ; count down from 3
+                push 3
+            loop:   dup
+                push 0
+                swap
+                gt              ; n > 0
+                jump_if_false done
+                push 1          ; n - 1
+                swap
+                sub
+                jump loop
+            done: halt
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            bytecode.opcodes,
+            vec![
+                Literal(Value::UInt(3)),
+                Dup,
+                Literal(Value::UInt(0)),
+                Swap,
+                Comparison(ComparisonOpcode::Gt),
+                Flow(FlowOpcode::JumpIfFalse(10)),
+                Literal(Value::UInt(1)),
+                Swap,
+                Arithmetic(ArithmeticOpcode::Sub),
+                Flow(FlowOpcode::Jump(1)),
+                Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_literals() {
+        let bytecode = assemble(
+            r#"
+            push 42
+            push -42i
+            push 1.5
+            push -inf
+            push "a \"quoted\"\n\u{1}; not a comment"
+            push [1, [true], null]
+            push {"b": 2, 1i: "x", "a": {}}
+            build_list 3
+            build_map 0
+            call 0
+            "#,
+        )
+        .unwrap();
+
+        let map = [
+            (Value::UInt(1), Value::String("x".to_string())),
+            (
+                Value::String("a".to_string()),
+                Value::Map(Default::default()),
+            ),
+            (Value::String("b".to_string()), Value::UInt(2)),
+        ];
+        assert_eq!(
+            bytecode.opcodes,
+            vec![
+                Literal(Value::UInt(42)),
+                Literal(Value::Int(-42)),
+                Literal(Value::Float(1.5)),
+                Literal(Value::Float(f64::NEG_INFINITY)),
+                Literal(Value::String(
+                    "a \"quoted\"\n\u{1}; not a comment".to_string()
+                )),
+                Literal(Value::List(vec![
+                    Value::UInt(1),
+                    Value::List(vec![Value::Bool(true)]),
+                    Value::Null,
+                ])),
+                Literal(Value::Map(map.into_iter().collect())),
+                Collection(CollectionOpcode::BuildList(3)),
+                Collection(CollectionOpcode::BuildMap(0)),
+                Flow(FlowOpcode::Call(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let error = |source| assemble(source).unwrap_err();
+
+        assert_eq!(
+            error("push 1\nfrobnicate"),
+            AsmError {
+                kind: AsmErrorKind::UnknownMnemonic("frobnicate".to_string()),
+                line: 2,
+            }
+        );
+        assert_eq!(
+            error("jump nowhere").kind,
+            AsmErrorKind::UndefinedLabel("nowhere".to_string())
+        );
+        assert_eq!(
+            error("a:\na: halt").kind,
+            AsmErrorKind::DuplicateLabel("a".to_string())
+        );
+        assert_eq!(
+            error("push").kind,
+            AsmErrorKind::MissingOperand("push".to_string())
+        );
+        assert_eq!(
+            error("push -1").kind,
+            AsmErrorKind::InvalidLiteral("-1".to_string())
+        );
+        assert_eq!(
+            error("push {1.5: 1}").kind,
+            AsmErrorKind::InvalidLiteral("1.5".to_string())
+        );
+        assert_eq!(
+            error("add 1").kind,
+            AsmErrorKind::UnexpectedInput("1".to_string())
+        );
+        assert_eq!(
+            error("build_list -1").to_string(),
+            "line 1: invalid number `-1`"
+        );
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let bytecode = ByteCode::from(vec![
+            Literal(Value::String("tab\there".to_string())),
+            Flow(FlowOpcode::JumpIfTrue(3)),
+            Flow(FlowOpcode::Jump(0)),
+            Flow(FlowOpcode::Call(99)),
+            Halt,
+        ]);
+
+        assert_eq!(
+            disassemble(&bytecode),
+            "L0:\n     0  push \"tab\\there\"\n     1  jump_if_true L3\n     2  jump L0\nL3:\n     3  call 99\n     4  halt\n"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_serialized() {
+        let bytecode = ByteCode::from(vec![Literal(Value::Int(-1)), Pop, Halt]);
+        let data = serialize(Version::V1, &bytecode);
+
+        assert_eq!(
+            disassemble_serialized(&data).unwrap(),
+            "     0  push -1i\n     1  pop\n     2  halt\n"
+        );
+        assert!(disassemble_serialized(&[1, 2]).is_err());
+    }
+
+    #[quickcheck]
+    fn disassemble_roundtrip(bytecode: ByteCode) -> bool {
+        assemble(&disassemble(&bytecode)) == Ok(bytecode)
+    }
+}
@@ -0,0 +1,61 @@
+use crate::ir::*;
+
+/*
+ *  Control flow between the blocks of an `IrFunction`.
+ *
+ *  A block ends at its first `Jump`, `JumpIf`, `Return` or `Halt`, anything
+ *  after it never runs. A block without one falls through to the block laid
+ *  out after it, which is the next index (see `ir_to_bytecode`).
+ *
+ *  `link` records the successors in `BasicBlock::next` and `branch`:
+ *
+ *      Jump(t)          next = t
+ *      JumpIf(t, e)     next = t, branch = e
+ *      Return, Halt     neither
+ *      (fall through)   next = index + 1
+ */
+
+/// Index of the instruction that ends `block`, if any.
+pub fn terminator(block: &BasicBlock) -> Option<usize> {
+    block.instructions.iter().position(|instruction| {
+        matches!(
+            instruction,
+            IrInstruction::Jump(_)
+                | IrInstruction::JumpIf(..)
+                | IrInstruction::Return
+                | IrInstruction::Halt
+        )
+    })
+}
+
+/// Sets `next` and `branch` of every block of `function`.
+pub fn link(function: &mut IrFunction) {
+    let count = function.blocks.len();
+    for (index, block) in function.blocks.iter_mut().enumerate() {
+        let (next, branch) = match terminator(block).map(|i| &block.instructions[i]) {
+            Some(IrInstruction::Jump(target)) => (Some(*target), None),
+            Some(IrInstruction::JumpIf(then_block, else_block)) => {
+                (Some(*then_block), Some(*else_block))
+            }
+            Some(_) => (None, None),
+            None => ((index + 1 < count).then_some(index + 1), None),
+        };
+        block.next = next;
+        block.branch = branch;
+    }
+}
+
+/// Whether each block of `function` can be reached from its entry, going
+/// by the links `link` set.
+pub fn reachable(function: &IrFunction) -> Vec<bool> {
+    let mut reached = vec![false; function.blocks.len()];
+    let mut pending = vec![function.entry_block];
+    while let Some(index) = pending.pop() {
+        if std::mem::replace(&mut reached[index], true) {
+            continue;
+        }
+        let block = &function.blocks[index];
+        pending.extend(block.next.into_iter().chain(block.branch));
+    }
+    reached
+}
@@ -0,0 +1,84 @@
+use crate::error::CompileError;
+use crate::types::Span;
+use std::fmt;
+
+/// A compile error together with the file it was found in, ready to be shown
+/// to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(file: impl Into<String>, error: &CompileError) -> Self {
+        Self {
+            file: file.into(),
+            span: error.span,
+            message: error.kind.to_string(),
+        }
+    }
+
+    /*
+     *  Renders the message followed by the offending line of `source`, the
+     *  text the span covers underlined with carets (clipped to the first line
+     *  of the span):
+     *
+     *      unknown operator `**`
+     *       --> main.oxy:3:1
+     *        |
+     *      3 | x = 2 ** 3;
+     *        | ^^^^^^^^^^^
+     *
+     *  The severity is left to the caller, which prints `error: ` in front.
+     *  Only the first two lines are produced when the span does not point
+     *  into `source`.
+     */
+    pub fn render(&self, source: &str) -> String {
+        let gutter = self.span.line.to_string().len();
+        let mut lines = vec![
+            self.message.clone(),
+            format!(
+                "{:gutter$}--> {}:{}:{}",
+                "", self.file, self.span.line, self.span.column
+            ),
+        ];
+
+        let start = self.span.start;
+        let (Some(before), Some(after)) = (source.get(..start), source.get(start..)) else {
+            return lines.join("\n");
+        };
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line_end = start + after.find('\n').unwrap_or(after.len());
+        let line = source[line_start..line_end].trim_end_matches('\r');
+
+        // keep tabs so the carets line up with the source
+        let indent: String = source[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let underlined = source
+            .get(start..self.span.end.clamp(start, line_end))
+            .map_or(0, |text| text.trim_end_matches('\r').chars().count());
+
+        lines.push(format!("{:gutter$} |", ""));
+        lines.push(format!("{} | {line}", self.span.line));
+        lines.push(format!(
+            "{:gutter$} | {indent}{}",
+            "",
+            "^".repeat(underlined.max(1))
+        ));
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.span.line, self.span.column, self.message
+        )
+    }
+}
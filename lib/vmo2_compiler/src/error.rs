@@ -0,0 +1,114 @@
+use crate::type_checker::Type;
+use crate::types::Span;
+use std::fmt;
+
+/// An error that stops compilation, located at the statement that caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub kind: CompileErrorKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileErrorKind {
+    /// The source does not match the grammar.
+    Syntax(String),
+    /// A whole number literal too large for an `i64`.
+    IntegerOverflow(String),
+    UnknownOperator(String),
+    UndefinedFunction(String),
+    /// A function defined more than once.
+    DuplicateFunction(String),
+    /// A function named like a builtin, which calls would never reach.
+    BuiltinRedefinition(String),
+    /// A variable read before any assignment to it.
+    UndefinedVariable(String),
+    /// An operator that fails for every type its operands can have.
+    TypeMismatch {
+        operation: String,
+        lhs: Type,
+        rhs: Option<Type>,
+    },
+    /// A condition, key or builtin argument of the wrong type.
+    ExpectedType {
+        expected: Type,
+        found: Type,
+    },
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    /// A builtin or function called with the wrong number of arguments.
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// `push` and `pop` update a variable in place.
+    ExpectedVariable(String),
+}
+
+impl CompileError {
+    pub fn new(kind: CompileErrorKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl fmt::Display for CompileErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileErrorKind::Syntax(message) => write!(f, "{message}"),
+            CompileErrorKind::IntegerOverflow(literal) => {
+                write!(f, "integer literal `{literal}` is too large")
+            }
+            CompileErrorKind::UnknownOperator(operator) => {
+                write!(f, "unknown operator `{operator}`")
+            }
+            CompileErrorKind::UndefinedFunction(name) => {
+                write!(f, "call to undefined function `{name}`")
+            }
+            CompileErrorKind::DuplicateFunction(name) => {
+                write!(f, "function `{name}` is defined more than once")
+            }
+            CompileErrorKind::BuiltinRedefinition(name) => {
+                write!(f, "`{name}` is a builtin and cannot be redefined")
+            }
+            CompileErrorKind::UndefinedVariable(name) => {
+                write!(f, "variable `{name}` is used before it is assigned")
+            }
+            CompileErrorKind::TypeMismatch {
+                operation,
+                lhs,
+                rhs: Some(rhs),
+            } => write!(f, "cannot apply `{operation}` to {lhs} and {rhs}"),
+            CompileErrorKind::TypeMismatch {
+                operation,
+                lhs,
+                rhs: None,
+            } => write!(f, "cannot apply `{operation}` to {lhs}"),
+            CompileErrorKind::ExpectedType { expected, found } => {
+                write!(f, "expected {expected}, found {found}")
+            }
+            CompileErrorKind::BreakOutsideLoop => write!(f, "`break` outside of a loop"),
+            CompileErrorKind::ContinueOutsideLoop => write!(f, "`continue` outside of a loop"),
+            CompileErrorKind::ArgumentCount {
+                name,
+                expected,
+                found,
+            } => write!(f, "`{name}` expects {expected} argument(s), got {found}"),
+            CompileErrorKind::ExpectedVariable(name) => {
+                write!(f, "the first argument of `{name}` must be a variable")
+            }
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.kind, self.span.line, self.span.column
+        )
+    }
+}
+
+impl std::error::Error for CompileError {}
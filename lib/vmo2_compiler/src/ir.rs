@@ -1,15 +1,41 @@
 use vmo2_types::value::Value;
 
+use crate::type_checker::Type;
 use crate::types::*;
 use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
 pub struct BasicBlock {
     pub instructions: Vec<IrInstruction>,
+    /// Source span of each instruction, parallel to `instructions`.
+    pub spans: Vec<Option<Span>>,
+    /// Types the operands of each operator instruction are statically known
+    /// to have between them, parallel to `instructions`. A single type means
+    /// every operand has it. Only set when the IR is built from a type
+    /// checked program.
+    pub types: Vec<Option<Type>>,
     pub next: Option<usize>,
     pub branch: Option<usize>,
 }
 
+impl Default for BasicBlock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BasicBlock {
+    pub fn new() -> Self {
+        BasicBlock {
+            instructions: Vec::new(),
+            spans: Vec::new(),
+            types: Vec::new(),
+            next: None,
+            branch: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct IrFunction {
     pub name: String,
@@ -35,6 +61,13 @@ pub enum IrInstruction {
     Sub,
     Mul,
     Div,
+    Mod,
+
+    // Logic operations
+    And,
+    Or,
+    Xor,
+    Not,
 
     // Comparison operations
     Eq,
@@ -47,13 +80,26 @@ pub enum IrInstruction {
     // Control flow
     Jump(usize),
     JumpIf(usize, usize),
+    Halt,
 
     // Function operations
     Call(String),
     Return,
 
+    // Collection operations
+    BuildList(u32),
+    Get,
+    Set,
+    Len,
+    ListPush,
+    ListPop,
+    BuildMap(u32),
+    Keys,
+    Contains,
+
     // Other
     Print,
+    Scan,
     NoOp,
     Neg,
 }
@@ -63,6 +109,14 @@ pub struct IrProgram {
     pub functions: HashMap<String, IrFunction>,
     pub current_function: Option<String>,
     pub current_block: usize,
+    /// Span attached to instructions added from now on.
+    pub current_span: Option<Span>,
+}
+
+impl Default for IrProgram {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl IrProgram {
@@ -71,15 +125,12 @@ impl IrProgram {
             functions: HashMap::new(),
             current_function: None,
             current_block: 0,
+            current_span: None,
         }
     }
 
     pub fn add_function(&mut self, name: String, parameters: Vec<String>) {
-        let entry_block = BasicBlock {
-            instructions: Vec::new(),
-            next: None,
-            branch: None,
-        };
+        let entry_block = BasicBlock::new();
 
         let function = IrFunction {
             name: name.clone(),
@@ -94,93 +145,38 @@ impl IrProgram {
     }
 
     pub fn add_instruction(&mut self, instruction: IrInstruction) {
+        self.add_typed_instruction(instruction, None);
+    }
+
+    /// Adds an instruction annotated with the type of its operands.
+    pub fn add_typed_instruction(&mut self, instruction: IrInstruction, ty: Option<Type>) {
         let function_name = self.current_function.as_ref().unwrap();
         let function = self.functions.get_mut(function_name).unwrap();
         let block = function.blocks.get_mut(self.current_block).unwrap();
 
         block.instructions.push(instruction);
+        block.spans.push(self.current_span);
+        block.types.push(ty);
     }
 
     pub fn add_block(&mut self) -> usize {
-        if let Some(function_name) = &self.current_function {
-            if let Some(function) = self.functions.get_mut(function_name) {
-                let new_block = BasicBlock {
-                    instructions: Vec::new(),
-                    next: None,
-                    branch: None,
-                };
-                let block_index = function.blocks.len();
-                function.blocks.push(new_block);
-                return block_index;
-            }
+        if let Some(function_name) = &self.current_function
+            && let Some(function) = self.functions.get_mut(function_name)
+        {
+            let new_block = BasicBlock::new();
+            let block_index = function.blocks.len();
+            function.blocks.push(new_block);
+            return block_index;
         }
         0
     }
 
     pub fn link_blocks(&mut self, from: usize, to: usize) {
-        if let Some(function_name) = &self.current_function {
-            if let Some(function) = self.functions.get_mut(function_name) {
-                if let Some(block) = function.blocks.get_mut(from) {
-                    block.next = Some(to);
-                }
-            }
-        }
-    }
-}
-
-pub fn expression_to_ir(expression: &AstExpression) -> Vec<IrInstruction> {
-    match expression {
-        AstExpression::Literal(literal) => {
-            let value = match literal {
-                AstLiteral::UInt(n) => Value::UInt(*n),
-                AstLiteral::String(s) => Value::String(s.clone()),
-                AstLiteral::Bool(b) => Value::Bool(*b),
-                AstLiteral::Null => Value::Null,
-            };
-            vec![IrInstruction::Push(value)]
-        }
-        AstExpression::Variable(name) => {
-            vec![IrInstruction::Load(name.clone())]
-        }
-        AstExpression::UnaryOperation(op, expr) => {
-            let mut instructions = Vec::new();
-            instructions.extend(expression_to_ir(expr));
-            let op_instruction = match op.as_str() {
-                "-" => IrInstruction::Neg,
-                _ => unreachable!(),
-            };
-            instructions.push(op_instruction);
-            instructions
-        }
-        AstExpression::BinaryOperation(op, left, right) => {
-            let mut instructions = Vec::new();
-            instructions.extend(expression_to_ir(right));
-            instructions.extend(expression_to_ir(left));
-
-            let op_instruction = match op.as_str() {
-                "+" => IrInstruction::Add,
-                "-" => IrInstruction::Sub,
-                "*" => IrInstruction::Mul,
-                "/" => IrInstruction::Div,
-                "==" => IrInstruction::Eq,
-                "!=" => IrInstruction::Ne,
-                "<" => IrInstruction::Lt,
-                ">" => IrInstruction::Gt,
-                "<=" => IrInstruction::Le,
-                ">=" => IrInstruction::Ge,
-                _ => unreachable!(),
-            };
-
-            instructions.push(op_instruction);
-            instructions
-        }
-        AstExpression::FunctionCall(name, args) => {
-            let mut instructions = Vec::new();
-            for arg in args {
-                instructions.extend(expression_to_ir(arg));
-            }
-            instructions.push(IrInstruction::Call(name.clone()));
-            instructions
+        if let Some(function_name) = &self.current_function
+            && let Some(function) = self.functions.get_mut(function_name)
+            && let Some(block) = function.blocks.get_mut(from)
+        {
+            block.next = Some(to);
         }
     }
 }
@@ -1,9 +1,29 @@
+use crate::error::*;
 use crate::ir::*;
+use crate::type_checker::{Type, TypeInfo};
 use crate::types::*;
+use vmo2_types::value::Value;
+
+/// Jump targets of the innermost enclosing loop.
+struct LoopTargets {
+    continue_block: usize,
+    break_block: usize,
+}
+
+/// A call to a user function, checked once every function is known.
+struct CallSite {
+    name: String,
+    arguments: usize,
+    span: Span,
+}
 
 pub struct IrBuilder<'a> {
     ir: &'a mut IrProgram,
     current_fn: String,
+    loops: Vec<LoopTargets>,
+    calls: Vec<CallSite>,
+    /// Inferred types, used to annotate operators.
+    types: Option<&'a TypeInfo>,
 }
 
 impl<'a> IrBuilder<'a> {
@@ -12,26 +32,296 @@ impl<'a> IrBuilder<'a> {
         Self {
             ir,
             current_fn: function_name.to_string(),
+            loops: Vec::new(),
+            calls: Vec::new(),
+            types: None,
+        }
+    }
+
+    /// Annotates the operators with the operand types from `types`.
+    pub fn with_types(mut self, types: &'a TypeInfo) -> Self {
+        self.types = Some(types);
+        self
+    }
+
+    /// Checks that every call made so far names a defined function and
+    /// passes it as many arguments as it has parameters.
+    pub fn check_calls(&self) -> Result<(), CompileError> {
+        for call in &self.calls {
+            let Some(function) = self.ir.functions.get(&call.name) else {
+                return Err(CompileError::new(
+                    CompileErrorKind::UndefinedFunction(call.name.clone()),
+                    call.span,
+                ));
+            };
+            if function.parameters.len() != call.arguments {
+                return Err(CompileError::new(
+                    CompileErrorKind::ArgumentCount {
+                        name: call.name.clone(),
+                        expected: function.parameters.len(),
+                        found: call.arguments,
+                    },
+                    call.span,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_arity(
+        name: &str,
+        args: &[AstExpression],
+        expected: usize,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        if args.len() != expected {
+            return Err(CompileError::new(
+                CompileErrorKind::ArgumentCount {
+                    name: name.to_string(),
+                    expected,
+                    found: args.len(),
+                },
+                span,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Name of the variable `push` and `pop` write the updated list back to.
+    fn builtin_target(name: &str, arg: &AstExpression, span: Span) -> Result<String, CompileError> {
+        match arg {
+            AstExpression::Variable(variable) => Ok(variable.clone()),
+            _ => Err(CompileError::new(
+                CompileErrorKind::ExpectedVariable(name.to_string()),
+                span,
+            )),
         }
     }
 
-    pub fn emit_expr(&mut self, expr: &AstExpression) {
-        let instructions = expression_to_ir(expr);
-        for instr in instructions {
-            self.ir.add_instruction(instr);
+    pub fn emit_expr(&mut self, expr: &AstExpression) -> Result<(), CompileError> {
+        match expr {
+            AstExpression::Literal(literal) => {
+                let value = match literal {
+                    AstLiteral::UInt(n) => Value::UInt(*n),
+                    AstLiteral::Int(n) => Value::Int(*n),
+                    AstLiteral::Float(n) => Value::Float(*n),
+                    AstLiteral::String(s) => Value::String(s.clone()),
+                    AstLiteral::Bool(b) => Value::Bool(*b),
+                    AstLiteral::Null => Value::Null,
+                };
+                self.ir.add_instruction(IrInstruction::Push(value));
+            }
+            AstExpression::Variable(name) => {
+                self.ir.add_instruction(IrInstruction::Load(name.clone()));
+            }
+            AstExpression::UnaryOperation(op, expr, span) => {
+                self.emit_expr(expr)?;
+                let ty = self
+                    .types
+                    .map(|types| types.expression_type(&self.current_fn, expr))
+                    .filter(|ty| *ty != Type::ANY && !ty.is_empty());
+                match op.as_str() {
+                    "+" => {}
+                    "-" => self.ir.add_typed_instruction(IrInstruction::Neg, ty),
+                    "!" => self.ir.add_typed_instruction(IrInstruction::Not, ty),
+                    _ => {
+                        return Err(CompileError::new(
+                            CompileErrorKind::UnknownOperator(op.clone()),
+                            *span,
+                        ));
+                    }
+                }
+            }
+            AstExpression::BinaryOperation(op, left, right, _) if op == "&&" || op == "||" => {
+                /*
+                    `left` is kept on the stack and decides whether `right`
+                    runs at all. If it does, both are combined with the
+                    logic instruction so that `right` is checked to be a
+                    boolean as well:
+
+                        left; Dup; JumpIf(rhs_block, after_block)    (&&)
+                        left; Dup; JumpIf(after_block, rhs_block)    (||)
+                        rhs_block:   right; And | Or; Jump(after_block)
+                        after_block: ...
+                */
+                let rhs_block = self.ir.add_block();
+                let after_block = self.ir.add_block();
+
+                self.emit_expr(left)?;
+                self.ir.add_instruction(IrInstruction::Dup);
+                let (jump, combine) = if op == "&&" {
+                    (
+                        IrInstruction::JumpIf(rhs_block, after_block),
+                        IrInstruction::And,
+                    )
+                } else {
+                    (
+                        IrInstruction::JumpIf(after_block, rhs_block),
+                        IrInstruction::Or,
+                    )
+                };
+                self.ir.add_instruction(jump);
+
+                self.ir.current_block = rhs_block;
+                self.emit_expr(right)?;
+                self.ir.add_instruction(combine);
+                self.ir.add_instruction(IrInstruction::Jump(after_block));
+
+                self.ir.current_block = after_block;
+            }
+            AstExpression::BinaryOperation(op, left, right, span) => {
+                self.emit_expr(right)?;
+                self.emit_expr(left)?;
+
+                let op_instruction = match op.as_str() {
+                    "+" => IrInstruction::Add,
+                    "-" => IrInstruction::Sub,
+                    "*" => IrInstruction::Mul,
+                    "/" => IrInstruction::Div,
+                    "%" => IrInstruction::Mod,
+                    "^" => IrInstruction::Xor,
+                    "==" => IrInstruction::Eq,
+                    "!=" => IrInstruction::Ne,
+                    "<" => IrInstruction::Lt,
+                    ">" => IrInstruction::Gt,
+                    "<=" => IrInstruction::Le,
+                    ">=" => IrInstruction::Ge,
+                    _ => {
+                        return Err(CompileError::new(
+                            CompileErrorKind::UnknownOperator(op.clone()),
+                            *span,
+                        ));
+                    }
+                };
+
+                let ty = self
+                    .types
+                    .and_then(|types| types.operand_type(&self.current_fn, left, right));
+                self.ir.add_typed_instruction(op_instruction, ty);
+            }
+            AstExpression::FunctionCall(name, args, span) => match name.as_str() {
+                // builtins map straight onto the IO opcodes
+                "print" => {
+                    for arg in args {
+                        self.emit_expr(arg)?;
+                        self.ir.add_instruction(IrInstruction::Print);
+                    }
+                    self.ir.add_instruction(IrInstruction::Push(Value::Null));
+                }
+                "scan" => {
+                    Self::check_arity(name, args, 0, *span)?;
+                    self.ir.add_instruction(IrInstruction::Scan);
+                }
+                "len" => {
+                    Self::check_arity(name, args, 1, *span)?;
+                    self.emit_expr(&args[0])?;
+                    self.ir.add_instruction(IrInstruction::Len);
+                }
+                "keys" => {
+                    Self::check_arity(name, args, 1, *span)?;
+                    self.emit_expr(&args[0])?;
+                    self.ir.add_instruction(IrInstruction::Keys);
+                }
+                "contains" => {
+                    Self::check_arity(name, args, 2, *span)?;
+                    self.emit_expr(&args[0])?;
+                    self.emit_expr(&args[1])?;
+                    self.ir.add_instruction(IrInstruction::Contains);
+                }
+                // `push` and `pop` store the updated list back into the variable
+                "push" => {
+                    Self::check_arity(name, args, 2, *span)?;
+                    let target = Self::builtin_target(name, &args[0], *span)?;
+                    self.ir.add_instruction(IrInstruction::Load(target.clone()));
+                    self.emit_expr(&args[1])?;
+                    self.ir.add_instruction(IrInstruction::ListPush);
+                    self.ir.add_instruction(IrInstruction::Store(target));
+                    self.ir.add_instruction(IrInstruction::Push(Value::Null));
+                }
+                "pop" => {
+                    Self::check_arity(name, args, 1, *span)?;
+                    let target = Self::builtin_target(name, &args[0], *span)?;
+                    self.ir.add_instruction(IrInstruction::Load(target.clone()));
+                    self.ir.add_instruction(IrInstruction::ListPop);
+                    self.ir.add_instruction(IrInstruction::Swap);
+                    self.ir.add_instruction(IrInstruction::Store(target));
+                }
+                _ => {
+                    for arg in args {
+                        self.emit_expr(arg)?;
+                    }
+                    self.calls.push(CallSite {
+                        name: name.clone(),
+                        arguments: args.len(),
+                        span: *span,
+                    });
+                    self.ir.add_instruction(IrInstruction::Call(name.clone()));
+                }
+            },
+            AstExpression::List(items) => {
+                for item in items {
+                    self.emit_expr(item)?;
+                }
+                self.ir
+                    .add_instruction(IrInstruction::BuildList(items.len() as u32));
+            }
+            AstExpression::Map(entries) => {
+                for (key, value) in entries {
+                    self.emit_expr(key)?;
+                    self.emit_expr(value)?;
+                }
+                self.ir
+                    .add_instruction(IrInstruction::BuildMap(entries.len() as u32));
+            }
+            AstExpression::Index(collection, index) => {
+                self.emit_expr(collection)?;
+                self.emit_expr(index)?;
+                self.ir.add_instruction(IrInstruction::Get);
+            }
         }
+
+        Ok(())
+    }
+
+    pub fn emit_stmt(&mut self, stmt: &AstStatement) -> Result<(), CompileError> {
+        let outer_span = self.ir.current_span.replace(stmt.span());
+        let result = self.lower_stmt(stmt);
+        self.ir.current_span = outer_span;
+        result
     }
 
-    pub fn emit_stmt(&mut self, stmt: &AstStatement) {
+    fn lower_stmt(&mut self, stmt: &AstStatement) -> Result<(), CompileError> {
         match stmt {
-            AstStatement::Assignment(name, expr) => {
-                self.emit_expr(expr);
+            AstStatement::Assignment(name, expr, _) => {
+                self.emit_expr(expr)?;
                 self.ir.add_instruction(IrInstruction::Store(name.clone()));
             }
-            AstStatement::Expression(expr) => {
-                self.emit_expr(expr);
+            AstStatement::IndexAssignment(name, index, expr, _) => {
+                self.ir.add_instruction(IrInstruction::Load(name.clone()));
+                self.emit_expr(index)?;
+                self.emit_expr(expr)?;
+                self.ir.add_instruction(IrInstruction::Set);
+                self.ir.add_instruction(IrInstruction::Store(name.clone()));
+            }
+            AstStatement::Expression(expr, _) => {
+                // every expression leaves a value, statements discard it
+                self.emit_expr(expr)?;
+                self.ir.add_instruction(IrInstruction::Pop);
+            }
+            AstStatement::Return(value, _) => {
+                match value {
+                    Some(expr) => self.emit_expr(expr)?,
+                    None => self.ir.add_instruction(IrInstruction::Push(Value::Null)),
+                }
+                // returning from the top level ends the program
+                let instr = if self.current_fn == "main" {
+                    IrInstruction::Halt
+                } else {
+                    IrInstruction::Return
+                };
+                self.ir.add_instruction(instr);
             }
-            AstStatement::While(cond, body) => {
+            AstStatement::While(cond, body, span) => {
                 /*
                              +--------------------+
                              |    Entry Block     |
@@ -63,35 +353,156 @@ impl<'a> IrBuilder<'a> {
                 self.ir.add_instruction(IrInstruction::Jump(cond_block));
 
                 self.ir.current_block = cond_block;
-                self.emit_expr(cond);
+                self.emit_expr(cond)?;
                 self.ir
                     .add_instruction(IrInstruction::JumpIf(body_block, after_block));
 
                 self.ir.current_block = body_block;
+                self.loops.push(LoopTargets {
+                    continue_block: cond_block,
+                    break_block: after_block,
+                });
                 for stmt in body {
-                    self.emit_stmt(stmt);
+                    self.emit_stmt(stmt)?;
                 }
+                self.loops.pop();
+                self.ir.current_span = Some(*span);
                 self.ir.add_instruction(IrInstruction::Jump(cond_block));
 
                 self.ir.current_block = after_block;
             }
-            AstStatement::FunctionDefinition(name, body) => {
+            AstStatement::For(init, cond, step, body, span) => {
+                /*
+                    Same shape as `While`, with `init` emitted before the
+                    loop and a step_block between the body and the jump back
+                    to cond_block. `continue` jumps to the step, a missing
+                    condition loops until `break`.
+                */
+                if let Some(init) = init {
+                    self.emit_stmt(init)?;
+                }
+
+                let cond_block = self.ir.add_block();
+                let body_block = self.ir.add_block();
+                let step_block = self.ir.add_block();
+                let after_block = self.ir.add_block();
+
+                self.ir.add_instruction(IrInstruction::Jump(cond_block));
+
+                self.ir.current_block = cond_block;
+                match cond {
+                    Some(cond) => {
+                        self.emit_expr(cond)?;
+                        self.ir
+                            .add_instruction(IrInstruction::JumpIf(body_block, after_block));
+                    }
+                    None => self.ir.add_instruction(IrInstruction::Jump(body_block)),
+                }
+
+                self.ir.current_block = body_block;
+                self.loops.push(LoopTargets {
+                    continue_block: step_block,
+                    break_block: after_block,
+                });
+                for stmt in body {
+                    self.emit_stmt(stmt)?;
+                }
+                self.loops.pop();
+                self.ir.current_span = Some(*span);
+                self.ir.add_instruction(IrInstruction::Jump(step_block));
+
+                self.ir.current_block = step_block;
+                if let Some(step) = step {
+                    self.emit_stmt(step)?;
+                }
+                self.ir.add_instruction(IrInstruction::Jump(cond_block));
+
+                self.ir.current_block = after_block;
+            }
+            AstStatement::Break(span) => {
+                let Some(targets) = self.loops.last() else {
+                    return Err(CompileError::new(CompileErrorKind::BreakOutsideLoop, *span));
+                };
+                self.ir
+                    .add_instruction(IrInstruction::Jump(targets.break_block));
+            }
+            AstStatement::Continue(span) => {
+                let Some(targets) = self.loops.last() else {
+                    return Err(CompileError::new(
+                        CompileErrorKind::ContinueOutsideLoop,
+                        *span,
+                    ));
+                };
+                self.ir
+                    .add_instruction(IrInstruction::Jump(targets.continue_block));
+            }
+            AstStatement::If(cond, then_body, else_body, span) => {
+                /*
+                    evaluate `cond`, JumpIf(then_block, else_block), both
+                    branches jump to after_block. Without an else branch the
+                    condition jumps straight to after_block.
+                */
+                let then_block = self.ir.add_block();
+                let else_block = else_body.as_ref().map(|_| self.ir.add_block());
+                let after_block = self.ir.add_block();
+
+                self.emit_expr(cond)?;
+                self.ir.add_instruction(IrInstruction::JumpIf(
+                    then_block,
+                    else_block.unwrap_or(after_block),
+                ));
+
+                self.ir.current_block = then_block;
+                for stmt in then_body {
+                    self.emit_stmt(stmt)?;
+                }
+                self.ir.current_span = Some(*span);
+                self.ir.add_instruction(IrInstruction::Jump(after_block));
+
+                if let (Some(else_block), Some(else_body)) = (else_block, else_body) {
+                    self.ir.current_block = else_block;
+                    for stmt in else_body {
+                        self.emit_stmt(stmt)?;
+                    }
+                    self.ir.current_span = Some(*span);
+                    self.ir.add_instruction(IrInstruction::Jump(after_block));
+                }
+
+                self.ir.current_block = after_block;
+            }
+            AstStatement::FunctionDefinition(name, parameters, body, span) => {
                 let old_function = self.current_fn.clone();
                 let old_block = self.ir.current_block;
+                // loops around a definition do not extend into its body
+                let old_loops = std::mem::take(&mut self.loops);
 
-                self.ir.add_function(name.clone(), vec![]);
+                self.ir.add_function(name.clone(), parameters.clone());
                 self.current_fn = name.clone();
                 self.ir.current_block = 0;
 
+                // the caller pushed the arguments in order, so the last one
+                // is on top of the stack
+                for parameter in parameters.iter().rev() {
+                    self.ir
+                        .add_instruction(IrInstruction::Store(parameter.clone()));
+                }
+
                 for stmt in body {
-                    self.emit_stmt(stmt);
+                    self.emit_stmt(stmt)?;
                 }
 
+                // falling off the end returns null
+                self.ir.current_span = Some(*span);
+                self.ir.add_instruction(IrInstruction::Push(Value::Null));
                 self.ir.add_instruction(IrInstruction::Return);
 
+                self.ir.current_function = Some(old_function.clone());
                 self.current_fn = old_function;
                 self.ir.current_block = old_block;
+                self.loops = old_loops;
             }
         }
+
+        Ok(())
     }
 }
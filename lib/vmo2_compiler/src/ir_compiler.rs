@@ -1,27 +1,122 @@
+use crate::error::{CompileError, CompileErrorKind};
 use crate::ir::*;
 use crate::ir_builder::*;
+use crate::type_checker::TypeInfo;
 use crate::types::*;
+use crate::verifier::debug_verify;
+use std::collections::HashMap;
 use vmo2_types::bytecode::*;
 use vmo2_types::opcode::*;
+use vmo2_types::source_map::{SourceLocation, SourceMap};
 use vmo2_types::value::Value;
 
-pub fn compile_to_ir(program: AstProgram) -> IrProgram {
+pub fn compile_to_ir(program: AstProgram) -> Result<IrProgram, CompileError> {
+    lower(program, None)
+}
+
+/// Like `compile_to_ir`, annotating the operators with the operand types
+/// `type_checker::check` inferred for `program`.
+pub fn compile_to_typed_ir(
+    program: AstProgram,
+    types: &TypeInfo,
+) -> Result<IrProgram, CompileError> {
+    lower(program, Some(types))
+}
+
+fn lower(program: AstProgram, types: Option<&TypeInfo>) -> Result<IrProgram, CompileError> {
     let mut ir = IrProgram::new();
     let mut builder = IrBuilder::new(&mut ir, "main");
+    if let Some(types) = types {
+        builder = builder.with_types(types);
+    }
 
     for statement in program.statements {
-        builder.emit_stmt(&statement);
+        builder.emit_stmt(&statement)?;
     }
+    builder.check_calls()?;
+
+    // falling off the end of `main` ends the program
+    ir.add_instruction(IrInstruction::Halt);
 
-    ir
+    debug_verify(&ir, "lowering");
+    Ok(ir)
+}
+
+/// Returns the functions of `ir` in the order they are laid out in the
+/// bytecode: `main` first so that execution starts at address 0, then every
+/// other function sorted by name so the output does not depend on the
+/// `HashMap` iteration order.
+pub fn layout_order(ir: &IrProgram) -> Vec<&IrFunction> {
+    let mut functions: Vec<&IrFunction> = ir.functions.values().collect();
+    functions.sort_by(|a, b| (a.name != "main", &a.name).cmp(&(b.name != "main", &b.name)));
+    functions
+}
+
+/// Number of opcodes `instr` expands to when it sits in block `block` of its
+/// function. A `JumpIf` needs an extra `Jump` unless its `then` target is the
+/// block laid out right after it.
+fn instruction_size(instr: &IrInstruction, block: usize) -> usize {
+    match instr {
+        IrInstruction::Load(_) | IrInstruction::Store(_) => 2,
+        IrInstruction::JumpIf(then_block, _) if *then_block != block + 1 => 2,
+        IrInstruction::NoOp => 0,
+        _ => 1,
+    }
 }
 
-pub fn ir_to_bytecode(ir: IrProgram) -> ByteCode {
+/*
+ *  Links the IR into a flat opcode stream.
+ *
+ *  Functions are laid out one after another (see `layout_order`) and the
+ *  blocks of a function in index order. A first pass assigns an absolute
+ *  address to every block, the second one emits the opcodes and resolves
+ *  block ids and function names into `FlowOpcode` addresses. A call to a
+ *  function `ir` does not define is reported at the call.
+ */
+pub fn ir_to_bytecode(ir: IrProgram) -> Result<ByteCode, CompileError> {
+    ir_to_bytecode_with_source_map(ir).map(|(bytecode, _)| bytecode)
+}
+
+/// Like `ir_to_bytecode`, also mapping every emitted opcode back to the
+/// statement it was lowered from.
+pub fn ir_to_bytecode_with_source_map(
+    ir: IrProgram,
+) -> Result<(ByteCode, SourceMap), CompileError> {
+    let functions = layout_order(&ir);
+
+    let mut block_addresses: HashMap<&str, Vec<u32>> = HashMap::new();
+    let mut address = 0;
+    for function in &functions {
+        let mut addresses = Vec::with_capacity(function.blocks.len());
+        for (index, block) in function.blocks.iter().enumerate() {
+            addresses.push(address as u32);
+            address += block
+                .instructions
+                .iter()
+                .map(|instr| instruction_size(instr, index))
+                .sum::<usize>();
+        }
+        block_addresses.insert(&function.name, addresses);
+    }
+
+    let function_address = |name: &str, span: Option<Span>| -> Result<u32, CompileError> {
+        let Some(function) = ir.functions.get(name) else {
+            return Err(CompileError::new(
+                CompileErrorKind::UndefinedFunction(name.to_string()),
+                span.unwrap_or_default(),
+            ));
+        };
+        Ok(block_addresses[name][function.entry_block])
+    };
+
     let mut bytecode = ByteCode::new();
+    let mut source_map = SourceMap::new();
+
+    for function in &functions {
+        let addresses = &block_addresses[function.name.as_str()];
 
-    for function in ir.functions.values() {
-        for block in &function.blocks {
-            for instr in &block.instructions {
+        for (index, block) in function.blocks.iter().enumerate() {
+            for (position, instr) in block.instructions.iter().enumerate() {
                 match instr {
                     IrInstruction::Push(val) => {
                         bytecode.add_opcode(Opcode::Literal(val.clone()));
@@ -55,6 +150,24 @@ pub fn ir_to_bytecode(ir: IrProgram) -> ByteCode {
                     IrInstruction::Div => {
                         bytecode.add_opcode(Opcode::Arithmetic(ArithmeticOpcode::Div));
                     }
+                    IrInstruction::Mod => {
+                        bytecode.add_opcode(Opcode::Arithmetic(ArithmeticOpcode::Mod));
+                    }
+                    IrInstruction::Neg => {
+                        bytecode.add_opcode(Opcode::Arithmetic(ArithmeticOpcode::Neg));
+                    }
+                    IrInstruction::And => {
+                        bytecode.add_opcode(Opcode::Logic(LogicOpcode::And));
+                    }
+                    IrInstruction::Or => {
+                        bytecode.add_opcode(Opcode::Logic(LogicOpcode::Or));
+                    }
+                    IrInstruction::Xor => {
+                        bytecode.add_opcode(Opcode::Logic(LogicOpcode::Xor));
+                    }
+                    IrInstruction::Not => {
+                        bytecode.add_opcode(Opcode::Logic(LogicOpcode::Not));
+                    }
                     IrInstruction::Eq => {
                         bytecode.add_opcode(Opcode::Comparison(ComparisonOpcode::Eq));
                     }
@@ -73,14 +186,81 @@ pub fn ir_to_bytecode(ir: IrProgram) -> ByteCode {
                     IrInstruction::Ge => {
                         bytecode.add_opcode(Opcode::Comparison(ComparisonOpcode::Ge));
                     }
+                    IrInstruction::Jump(target) => {
+                        bytecode.add_opcode(Opcode::Flow(FlowOpcode::Jump(addresses[*target])));
+                    }
+                    IrInstruction::JumpIf(then_block, else_block) => {
+                        bytecode.add_opcode(Opcode::Flow(FlowOpcode::JumpIfFalse(
+                            addresses[*else_block],
+                        )));
+                        if *then_block != index + 1 {
+                            bytecode
+                                .add_opcode(Opcode::Flow(FlowOpcode::Jump(addresses[*then_block])));
+                        }
+                    }
+                    IrInstruction::Call(name) => {
+                        let span = block.spans.get(position).copied().flatten();
+                        let address = function_address(name, span)?;
+                        bytecode.add_opcode(Opcode::Flow(FlowOpcode::Call(address)));
+                    }
+                    IrInstruction::Return => {
+                        bytecode.add_opcode(Opcode::Flow(FlowOpcode::Return));
+                    }
+                    IrInstruction::Halt => {
+                        bytecode.add_opcode(Opcode::Halt);
+                    }
                     IrInstruction::Print => {
                         bytecode.add_opcode(Opcode::IO(IOOpcode::Print));
                     }
-                    _ => unreachable!(),
+                    IrInstruction::Scan => {
+                        bytecode.add_opcode(Opcode::IO(IOOpcode::Scan));
+                    }
+                    IrInstruction::BuildList(len) => {
+                        bytecode.add_opcode(Opcode::Collection(CollectionOpcode::BuildList(*len)));
+                    }
+                    IrInstruction::Get => {
+                        bytecode.add_opcode(Opcode::Collection(CollectionOpcode::Get));
+                    }
+                    IrInstruction::Set => {
+                        bytecode.add_opcode(Opcode::Collection(CollectionOpcode::Set));
+                    }
+                    IrInstruction::Len => {
+                        bytecode.add_opcode(Opcode::Collection(CollectionOpcode::Len));
+                    }
+                    IrInstruction::ListPush => {
+                        bytecode.add_opcode(Opcode::Collection(CollectionOpcode::Push));
+                    }
+                    IrInstruction::ListPop => {
+                        bytecode.add_opcode(Opcode::Collection(CollectionOpcode::Pop));
+                    }
+                    IrInstruction::BuildMap(len) => {
+                        bytecode.add_opcode(Opcode::Collection(CollectionOpcode::BuildMap(*len)));
+                    }
+                    IrInstruction::Keys => {
+                        bytecode.add_opcode(Opcode::Collection(CollectionOpcode::Keys));
+                    }
+                    IrInstruction::Contains => {
+                        bytecode.add_opcode(Opcode::Collection(CollectionOpcode::Contains));
+                    }
+                    IrInstruction::NoOp => {}
                 }
+
+                let location =
+                    block
+                        .spans
+                        .get(position)
+                        .copied()
+                        .flatten()
+                        .map(|span| SourceLocation {
+                            line: span.line,
+                            column: span.column,
+                        });
+                source_map
+                    .locations
+                    .resize(bytecode.opcodes.len(), location);
             }
         }
     }
 
-    bytecode
+    Ok((bytecode, source_map))
 }
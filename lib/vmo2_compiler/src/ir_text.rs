@@ -0,0 +1,362 @@
+use crate::ir::*;
+use crate::ir_compiler::layout_order;
+use crate::type_checker::Type;
+use std::fmt::{self, Write};
+use vmo2_asm::assembler::parse_literal;
+use vmo2_asm::disassembler::write_literal;
+
+/*
+ *  Textual form of `IrProgram`.
+ *
+ *  Functions come in bytecode layout order (see `layout_order`), each block
+ *  starts with its `bb<index>:` label and holds one instruction per line.
+ *  An operator annotated by the type checker is followed by `: ` and the
+ *  operand types, and `;` starts a comment:
+ *
+ *      func main() {
+ *      bb0:
+ *          push 1
+ *          store x
+ *          load x
+ *          jump_if bb1, bb2
+ *      bb1:
+ *          push 2
+ *          load x
+ *          add : uint
+ *          ...
+ *      }
+ *
+ *      func inc(n) entry bb0 {
+ *          ...
+ *      }
+ *
+ *  `entry` is only written when the entry block is not `bb0`. Literals use
+ *  the `.vasm` syntax. Spans and the `next`/`branch` links are left out,
+ *  `cfg::link` recomputes the latter from the terminators.
+ */
+
+/// An error in textual IR, located at its 1-based line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for IrParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for IrParseError {}
+
+/// Mnemonic of the instructions that take no operand.
+fn mnemonic(instruction: &IrInstruction) -> Option<&'static str> {
+    use IrInstruction::*;
+
+    Some(match instruction {
+        Pop => "pop",
+        Dup => "dup",
+        Swap => "swap",
+        Add => "add",
+        Sub => "sub",
+        Mul => "mul",
+        Div => "div",
+        Mod => "mod",
+        And => "and",
+        Or => "or",
+        Xor => "xor",
+        Not => "not",
+        Eq => "eq",
+        Ne => "ne",
+        Lt => "lt",
+        Gt => "gt",
+        Le => "le",
+        Ge => "ge",
+        Halt => "halt",
+        Return => "return",
+        Get => "get",
+        Set => "set",
+        Len => "len",
+        ListPush => "list_push",
+        ListPop => "list_pop",
+        Keys => "keys",
+        Contains => "contains",
+        Print => "print",
+        Scan => "scan",
+        NoOp => "nop",
+        Neg => "neg",
+        Push(_) | Load(_) | Store(_) | Jump(_) | JumpIf(..) | Call(_) | BuildList(_)
+        | BuildMap(_) => return None,
+    })
+}
+
+/// Instruction without operand written as `name`.
+fn simple_instruction(name: &str) -> Option<IrInstruction> {
+    use IrInstruction::*;
+
+    [
+        Pop, Dup, Swap, Add, Sub, Mul, Div, Mod, And, Or, Xor, Not, Eq, Ne, Lt, Gt, Le, Ge, Halt,
+        Return, Get, Set, Len, ListPush, ListPop, Keys, Contains, Print, Scan, NoOp, Neg,
+    ]
+    .into_iter()
+    .find(|instruction| mnemonic(instruction) == Some(name))
+}
+
+fn write_instruction(out: &mut String, instruction: &IrInstruction) {
+    use IrInstruction::*;
+
+    if let Some(name) = mnemonic(instruction) {
+        out.push_str(name);
+        return;
+    }
+    match instruction {
+        Push(value) => {
+            out.push_str("push ");
+            write_literal(out, value);
+        }
+        Load(name) => write!(out, "load {name}").unwrap(),
+        Store(name) => write!(out, "store {name}").unwrap(),
+        Jump(target) => write!(out, "jump bb{target}").unwrap(),
+        JumpIf(then, otherwise) => write!(out, "jump_if bb{then}, bb{otherwise}").unwrap(),
+        Call(name) => write!(out, "call {name}").unwrap(),
+        BuildList(count) => write!(out, "build_list {count}").unwrap(),
+        BuildMap(count) => write!(out, "build_map {count}").unwrap(),
+        _ => unreachable!(),
+    }
+}
+
+/// Prints `ir` in the textual form `parse` reads back.
+pub fn print(ir: &IrProgram) -> String {
+    let mut out = String::new();
+    for (index, function) in layout_order(ir).into_iter().enumerate() {
+        if index > 0 {
+            out.push('\n');
+        }
+        write!(
+            out,
+            "func {}({})",
+            function.name,
+            function.parameters.join(", ")
+        )
+        .unwrap();
+        if function.entry_block != 0 {
+            write!(out, " entry bb{}", function.entry_block).unwrap();
+        }
+        out.push_str(" {\n");
+
+        for (index, block) in function.blocks.iter().enumerate() {
+            writeln!(out, "bb{index}:").unwrap();
+            for (instruction, ty) in block.instructions.iter().zip(&block.types) {
+                out.push_str("    ");
+                write_instruction(&mut out, instruction);
+                if let Some(ty) = ty {
+                    write!(out, " : {ty}").unwrap();
+                }
+                out.push('\n');
+            }
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+/// Splits the first word of `text` off, up to whitespace or one of `delimiters`.
+fn word<'a>(text: &'a str, delimiters: &str) -> (&'a str, &'a str) {
+    let text = text.trim_start();
+    let end = text
+        .find(|c: char| c.is_whitespace() || delimiters.contains(c))
+        .unwrap_or(text.len());
+    text.split_at(end)
+}
+
+fn identifier(text: &str) -> Option<&str> {
+    let mut chars = text.chars();
+    let first = chars.next()?;
+    ((first.is_alphabetic() || first == '_') && chars.all(|c| c.is_alphanumeric() || c == '_'))
+        .then_some(text)
+}
+
+fn block_index(text: &str) -> Option<usize> {
+    text.strip_prefix("bb")?.parse().ok()
+}
+
+/// `text` without a trailing comment, `;` inside string literals excepted.
+fn strip_comment(text: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &text[..i],
+            _ => {}
+        }
+    }
+    text
+}
+
+/// `func name(a, b) [entry bbN] {`
+fn parse_header(text: &str) -> Result<IrFunction, String> {
+    let invalid = || format!("invalid function header `{text}`");
+
+    let rest = text.strip_prefix("func").ok_or_else(invalid)?;
+    let (name, rest) = rest.split_once('(').ok_or_else(invalid)?;
+    let name = identifier(name.trim()).ok_or_else(invalid)?;
+    let (parameters, rest) = rest.split_once(')').ok_or_else(invalid)?;
+    let parameters = match parameters.trim() {
+        "" => vec![],
+        parameters => parameters
+            .split(',')
+            .map(|parameter| identifier(parameter.trim()).map(str::to_string))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?,
+    };
+    let rest = rest.trim().strip_suffix('{').ok_or_else(invalid)?.trim();
+    let entry_block = match rest.strip_prefix("entry") {
+        None if rest.is_empty() => 0,
+        Some(entry) => block_index(entry.trim()).ok_or_else(invalid)?,
+        None => return Err(invalid()),
+    };
+
+    Ok(IrFunction {
+        name: name.to_string(),
+        parameters,
+        blocks: vec![],
+        entry_block,
+    })
+}
+
+/// One instruction line with its optional type annotation.
+fn parse_instruction(text: &str) -> Result<(IrInstruction, Option<Type>), String> {
+    use IrInstruction::*;
+
+    let (name, rest) = word(text, ":");
+    let expects = |what: &str| format!("`{name}` expects {what}");
+    let (instruction, rest) = match name {
+        "push" => {
+            let (value, rest) = parse_literal(rest).map_err(|error| error.to_string())?;
+            (Push(value), rest)
+        }
+        "load" | "store" | "call" => {
+            let (operand_name, rest) = word(rest, ":");
+            let Some(operand_name) = identifier(operand_name) else {
+                return Err(expects("a name"));
+            };
+            let operand_name = operand_name.to_string();
+            let instruction = match name {
+                "load" => Load(operand_name),
+                "store" => Store(operand_name),
+                _ => Call(operand_name),
+            };
+            (instruction, rest)
+        }
+        "jump" => {
+            let (target, rest) = word(rest, ":");
+            match block_index(target) {
+                Some(target) => (Jump(target), rest),
+                None => return Err(expects("a block")),
+            }
+        }
+        "jump_if" => {
+            let (then, rest) = word(rest, ",:");
+            let (comma, rest) = rest.trim_start().split_at_checked(1).unwrap_or(("", ""));
+            let (otherwise, rest) = word(rest, ":");
+            match (block_index(then), comma, block_index(otherwise)) {
+                (Some(then), ",", Some(otherwise)) => (JumpIf(then, otherwise), rest),
+                _ => return Err(expects("two blocks")),
+            }
+        }
+        "build_list" | "build_map" => {
+            let (count, rest) = word(rest, ":");
+            let Ok(count) = count.parse() else {
+                return Err(expects("a count"));
+            };
+            match name {
+                "build_list" => (BuildList(count), rest),
+                _ => (BuildMap(count), rest),
+            }
+        }
+        _ => (
+            simple_instruction(name).ok_or_else(|| format!("unknown instruction `{name}`"))?,
+            rest,
+        ),
+    };
+
+    let rest = rest.trim();
+    let ty = match rest.strip_prefix(':') {
+        Some(ty) => {
+            Some(Type::from_name(ty).ok_or_else(|| format!("invalid type `{}`", ty.trim()))?)
+        }
+        None if rest.is_empty() => None,
+        None => return Err(format!("unexpected `{rest}`")),
+    };
+    Ok((instruction, ty))
+}
+
+/// Parses the textual IR written by `print`.
+pub fn parse(text: &str) -> Result<IrProgram, IrParseError> {
+    let mut ir = IrProgram::new();
+    let mut function: Option<IrFunction> = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let error = |message| IrParseError {
+            line: index + 1,
+            message,
+        };
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some(current) = &mut function else {
+            let header = parse_header(line).map_err(error)?;
+            if ir.functions.contains_key(&header.name) {
+                return Err(error(format!(
+                    "function `{}` is defined twice",
+                    header.name
+                )));
+            }
+            function = Some(header);
+            continue;
+        };
+
+        if line == "}" {
+            let current = function.take().unwrap();
+            if current.entry_block >= current.blocks.len() {
+                return Err(error(format!(
+                    "function `{}` has no block bb{}",
+                    current.name, current.entry_block
+                )));
+            }
+            ir.functions.insert(current.name.clone(), current);
+        } else if let Some(label) = line.strip_suffix(':')
+            && let Some(label) = block_index(label)
+        {
+            if label != current.blocks.len() {
+                return Err(error(format!(
+                    "expected block bb{}, found bb{label}",
+                    current.blocks.len()
+                )));
+            }
+            current.blocks.push(BasicBlock::new());
+        } else {
+            let (instruction, ty) = parse_instruction(line).map_err(error)?;
+            let Some(block) = current.blocks.last_mut() else {
+                return Err(error("instruction outside of a block".to_string()));
+            };
+            block.instructions.push(instruction);
+            block.spans.push(None);
+            block.types.push(ty);
+        }
+    }
+
+    match function {
+        Some(function) => Err(IrParseError {
+            line: text.lines().count(),
+            message: format!("function `{}` is not closed", function.name),
+        }),
+        None => Ok(ir),
+    }
+}
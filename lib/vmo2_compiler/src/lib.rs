@@ -1,6 +1,15 @@
+pub mod cfg;
+pub mod diagnostic;
+pub mod error;
 pub mod ir;
 pub mod ir_builder;
 pub mod ir_compiler;
+pub mod ir_text;
+pub mod optimizer;
 pub mod parser;
+pub mod peephole;
+pub mod resolver;
 mod tests;
+pub mod type_checker;
 pub mod types;
+pub mod verifier;
@@ -0,0 +1,349 @@
+use crate::cfg;
+use crate::ir::*;
+use crate::type_checker::Type;
+use crate::types::Span;
+use crate::verifier::debug_verify;
+use std::collections::{HashMap, HashSet};
+use vmo2_types::value::{Value, ValueError};
+
+/// Strings longer than this are left to be built at runtime rather than
+/// stored in the bytecode.
+const MAX_FOLDED_STRING: usize = 1 << 12;
+
+/// An instruction with its span and type annotation.
+type Entry = (IrInstruction, Option<Span>, Option<Type>);
+
+/// Runs the optimizations enabled at `level`, 0 leaving `ir` untouched.
+/// Level 1 folds constants, level 2 also removes dead code.
+pub fn optimize(ir: &mut IrProgram, level: u8) {
+    if level >= 1 {
+        fold_constants(ir);
+        debug_verify(ir, "constant folding");
+    }
+    if level >= 2 {
+        remove_unreachable_code(ir);
+        debug_verify(ir, "unreachable code removal");
+        remove_dead_stores(ir);
+        debug_verify(ir, "dead store removal");
+        // a dead store leaves `Push(v), Pop` behind
+        fold_constants(ir);
+        debug_verify(ir, "constant folding");
+    }
+}
+
+/*
+ *  Constant folding and algebraic simplification, block by block.
+ *
+ *  Instructions are moved one at a time onto a new list whose tail is then
+ *  rewritten for as long as a rule applies, so that folds cascade:
+ *
+ *      Push(r), Push(l), <binary>    ->  Push(l <binary> r)
+ *      Push(v), Neg | Not            ->  Push(-v | !v)
+ *      Push(v), Dup                  ->  Push(v), Push(v)
+ *      Push(v), Pop                  ->  (nothing)
+ *      Push(Bool(b)), JumpIf(t, e)   ->  Jump(t | e)
+ *
+ *  The operators are evaluated with `Value`'s own implementations, exactly
+ *  as the VM would. An operation that fails is kept so that the error still
+ *  happens at runtime.
+ *
+ *  `x + 0`, `x - 0`, `x * 1`, `x / 1`, `0 + x` and `1 * x` are reduced to
+ *  `x` when the operands are known to be integers, so this only applies to
+ *  IR built from a type checked program: `"a" + 0` is an error and
+ *  `-0.0 + 0` is `0.0`.
+ */
+pub fn fold_constants(ir: &mut IrProgram) {
+    let arities: HashMap<String, usize> = ir
+        .functions
+        .values()
+        .map(|function| (function.name.clone(), function.parameters.len()))
+        .collect();
+
+    for function in ir.functions.values_mut() {
+        for block in &mut function.blocks {
+            let entries = std::mem::take(&mut block.instructions)
+                .into_iter()
+                .zip(std::mem::take(&mut block.spans))
+                .zip(std::mem::take(&mut block.types))
+                .map(|((instruction, span), ty)| (instruction, span, ty));
+
+            let mut folded: Vec<Entry> = Vec::new();
+            for entry in entries {
+                folded.push(entry);
+                while fold_tail(&mut folded, &arities) {}
+            }
+
+            for (instruction, span, ty) in folded {
+                block.instructions.push(instruction);
+                block.spans.push(span);
+                block.types.push(ty);
+            }
+        }
+    }
+}
+
+/*
+ *  Removes code that can never run: instructions after the end of their
+ *  block, blocks not reachable from the entry of their function (a `while`
+ *  whose condition folded to `false`, the exit of a `while (true)` without
+ *  `break`) and functions never called from `main`, directly or not.
+ *
+ *  The remaining blocks keep their order, so a block falling through still
+ *  falls into the same one, and jumps are renumbered.
+ */
+pub fn remove_unreachable_code(ir: &mut IrProgram) {
+    for function in ir.functions.values_mut() {
+        for block in &mut function.blocks {
+            if let Some(end) = cfg::terminator(block) {
+                block.instructions.truncate(end + 1);
+                block.spans.truncate(end + 1);
+                block.types.truncate(end + 1);
+            }
+        }
+
+        cfg::link(function);
+        let reached = cfg::reachable(function);
+        let mut renumbered = Vec::new();
+        let mut index = 0;
+        for &reached in &reached {
+            renumbered.push(index);
+            index += usize::from(reached);
+        }
+
+        let blocks = std::mem::take(&mut function.blocks);
+        function.blocks = blocks
+            .into_iter()
+            .zip(reached)
+            .filter_map(|(block, reached)| reached.then_some(block))
+            .collect();
+        for instruction in function.blocks.iter_mut().flat_map(|b| &mut b.instructions) {
+            match instruction {
+                IrInstruction::Jump(target) => *target = renumbered[*target],
+                IrInstruction::JumpIf(then_block, else_block) => {
+                    *then_block = renumbered[*then_block];
+                    *else_block = renumbered[*else_block];
+                }
+                _ => {}
+            }
+        }
+        function.entry_block = renumbered[function.entry_block];
+        cfg::link(function);
+    }
+
+    let mut called = HashSet::from(["main".to_string()]);
+    let mut pending = vec!["main".to_string()];
+    while let Some(name) = pending.pop() {
+        let Some(function) = ir.functions.get(&name) else {
+            continue;
+        };
+        for instruction in function.blocks.iter().flat_map(|b| &b.instructions) {
+            if let IrInstruction::Call(callee) = instruction
+                && called.insert(callee.clone())
+            {
+                pending.push(callee.clone());
+            }
+        }
+    }
+    ir.functions.retain(|name, _| called.contains(name));
+}
+
+/// Turns the stores to variables no function ever loads into `Pop`s.
+/// Variables are looked up by name across functions, a function reading a
+/// global, so a load anywhere keeps every store to that name.
+pub fn remove_dead_stores(ir: &mut IrProgram) {
+    let loaded: HashSet<String> = ir
+        .functions
+        .values()
+        .flat_map(|function| &function.blocks)
+        .flat_map(|block| &block.instructions)
+        .filter_map(|instruction| match instruction {
+            IrInstruction::Load(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let instructions = ir
+        .functions
+        .values_mut()
+        .flat_map(|function| &mut function.blocks)
+        .flat_map(|block| &mut block.instructions);
+    for instruction in instructions {
+        if let IrInstruction::Store(name) = instruction
+            && !loaded.contains(name)
+        {
+            *instruction = IrInstruction::Pop;
+        }
+    }
+}
+
+/// Applies one rule to the end of `entries`, returning whether one did.
+fn fold_tail(entries: &mut Vec<Entry>, arities: &HashMap<String, usize>) -> bool {
+    let len = entries.len();
+    let Some((last, span, ty)) = entries.last().cloned() else {
+        return false;
+    };
+    let constant = |index: usize| match entries.get(index) {
+        Some((IrInstruction::Push(value), _, _)) => Some(value.clone()),
+        _ => None,
+    };
+
+    if len >= 2
+        && let Some(value) = constant(len - 2)
+    {
+        let replacement = match &last {
+            IrInstruction::Neg => (-value.clone()).ok().map(|v| vec![IrInstruction::Push(v)]),
+            IrInstruction::Not => (!value.clone()).ok().map(|v| vec![IrInstruction::Push(v)]),
+            IrInstruction::Dup => Some(vec![
+                IrInstruction::Push(value.clone()),
+                IrInstruction::Push(value.clone()),
+            ]),
+            IrInstruction::Pop => Some(vec![]),
+            IrInstruction::JumpIf(then_block, else_block) => match value {
+                Value::Bool(true) => Some(vec![IrInstruction::Jump(*then_block)]),
+                Value::Bool(false) => Some(vec![IrInstruction::Jump(*else_block)]),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(replacement) = replacement {
+            entries.truncate(len - 2);
+            entries.extend(replacement.into_iter().map(|i| (i, span, None)));
+            return true;
+        }
+    }
+
+    if len >= 3
+        && let (Some(rhs), Some(lhs)) = (constant(len - 3), constant(len - 2))
+        && let Some(Ok(result)) = evaluate(&last, lhs, rhs)
+    {
+        entries.truncate(len - 3);
+        entries.push((IrInstruction::Push(result), span, None));
+        return true;
+    }
+
+    // identities, see `fold_constants`
+    if !ty.is_some_and(|ty| ty.is_subset(Type::UINT.union(Type::INT))) {
+        return false;
+    }
+    // a uint keeps the type of the other operand, an int would turn a uint
+    // into an int
+    let is = |value: Option<Value>, n: u32| value == Some(Value::UInt(n));
+    let (lhs_identity, rhs_identity) = match last {
+        IrInstruction::Add => (Some(0), Some(0)),
+        IrInstruction::Mul => (Some(1), Some(1)),
+        IrInstruction::Sub => (None, Some(0)),
+        IrInstruction::Div => (None, Some(1)),
+        _ => return false,
+    };
+    // the lhs is pushed last, right before the operator
+    if len >= 2 && lhs_identity.is_some_and(|n| is(constant(len - 2), n)) {
+        entries.truncate(len - 2);
+        return true;
+    }
+    if let Some(start) = operand_start(&entries[..len - 1], arities)
+        && start > 0
+        && rhs_identity.is_some_and(|n| is(constant(start - 1), n))
+    {
+        entries.pop();
+        entries.remove(start - 1);
+        return true;
+    }
+    false
+}
+
+/// Index of the first instruction of the code computing the value on top
+/// of the stack at the end of `entries`, when it can be told.
+fn operand_start(entries: &[Entry], arities: &HashMap<String, usize>) -> Option<usize> {
+    let mut needed = 1;
+    for (index, (instruction, _, _)) in entries.iter().enumerate().rev() {
+        let (pops, pushes) = stack_effect(instruction, arities)?;
+        if pushes > needed {
+            return None;
+        }
+        needed = needed - pushes + pops;
+        if needed == 0 {
+            return Some(index);
+        }
+    }
+    None
+}
+
+/// Values `instruction` pops and pushes, `None` for control flow.
+pub(crate) fn stack_effect(
+    instruction: &IrInstruction,
+    arities: &HashMap<String, usize>,
+) -> Option<(usize, usize)> {
+    Some(match instruction {
+        IrInstruction::Push(_) | IrInstruction::Load(_) | IrInstruction::Scan => (0, 1),
+        IrInstruction::Pop | IrInstruction::Store(_) | IrInstruction::Print => (1, 0),
+        IrInstruction::Dup => (1, 2),
+        IrInstruction::Swap => (2, 2),
+        IrInstruction::Add
+        | IrInstruction::Sub
+        | IrInstruction::Mul
+        | IrInstruction::Div
+        | IrInstruction::Mod
+        | IrInstruction::And
+        | IrInstruction::Or
+        | IrInstruction::Xor
+        | IrInstruction::Eq
+        | IrInstruction::Ne
+        | IrInstruction::Lt
+        | IrInstruction::Gt
+        | IrInstruction::Le
+        | IrInstruction::Ge
+        | IrInstruction::Get
+        | IrInstruction::ListPush
+        | IrInstruction::Contains => (2, 1),
+        IrInstruction::Not | IrInstruction::Neg | IrInstruction::Len | IrInstruction::Keys => {
+            (1, 1)
+        }
+        IrInstruction::Set => (3, 1),
+        IrInstruction::ListPop => (1, 2),
+        IrInstruction::BuildList(n) => (*n as usize, 1),
+        IrInstruction::BuildMap(n) => (2 * *n as usize, 1),
+        IrInstruction::Call(name) => (*arities.get(name)?, 1),
+        IrInstruction::NoOp => (0, 0),
+        IrInstruction::Jump(_)
+        | IrInstruction::JumpIf(..)
+        | IrInstruction::Halt
+        | IrInstruction::Return => return None,
+    })
+}
+
+/// Result of binary `instruction` on constants, as the VM computes it.
+/// `None` for instructions that are not binary operators or results not
+/// worth folding.
+fn evaluate(
+    instruction: &IrInstruction,
+    lhs: Value,
+    rhs: Value,
+) -> Option<Result<Value, ValueError>> {
+    if let (IrInstruction::Mul, Value::String(s), Value::UInt(n))
+    | (IrInstruction::Mul, Value::UInt(n), Value::String(s)) = (instruction, &lhs, &rhs)
+        && s.len().saturating_mul(*n as usize) > MAX_FOLDED_STRING
+    {
+        return None;
+    }
+    let ordering = |test: fn(std::cmp::Ordering) -> bool| {
+        lhs.compare(&rhs)
+            .map(|ordering| Value::Bool(ordering.is_some_and(test)))
+    };
+    Some(match instruction {
+        IrInstruction::Add => lhs + rhs,
+        IrInstruction::Sub => lhs - rhs,
+        IrInstruction::Mul => lhs * rhs,
+        IrInstruction::Div => lhs / rhs,
+        IrInstruction::Mod => lhs % rhs,
+        IrInstruction::And => lhs.and(rhs),
+        IrInstruction::Or => lhs.or(rhs),
+        IrInstruction::Xor => lhs.xor(rhs),
+        IrInstruction::Eq => Ok(Value::Bool(lhs.equals(&rhs))),
+        IrInstruction::Ne => Ok(Value::Bool(!lhs.equals(&rhs))),
+        IrInstruction::Lt => ordering(std::cmp::Ordering::is_lt),
+        IrInstruction::Le => ordering(std::cmp::Ordering::is_le),
+        IrInstruction::Gt => ordering(std::cmp::Ordering::is_gt),
+        IrInstruction::Ge => ordering(std::cmp::Ordering::is_ge),
+        _ => return None,
+    })
+}
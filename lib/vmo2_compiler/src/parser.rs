@@ -1,98 +1,261 @@
+use crate::error::*;
 use crate::types::*;
-use pest::iterators::Pair;
-
-pub fn parse_program(pair: Pair<Rule>) -> AstProgram {
-    AstProgram {
-        statements: pair
-            .into_inner()
-            .filter(|p| p.as_rule() != Rule::EOI)
-            .map(parse_statements)
-            .flatten()
-            .collect(),
+use pest::Parser as _;
+use pest::iterators::{Pair, Pairs};
+
+/*
+ *  The functions below return a `CompileError` rather than panicking when a
+ *  pair does not have the shape the grammar promises, so that a grammar
+ *  change shows up as a located error instead of a crash.
+ */
+
+/// Parses a whole Oxyde program, reporting syntax errors where they occur.
+pub fn parse_source(source: &str) -> Result<AstProgram, CompileError> {
+    let mut pairs = OxydeParser::parse(Rule::program, source).map_err(syntax_error)?;
+    let span = Span {
+        line: 1,
+        column: 1,
+        ..Span::default()
+    };
+    parse_program(next(&mut pairs, span)?)
+}
+
+/// Converts a pest error, located where parsing stopped.
+fn syntax_error(error: pest::error::Error<Rule>) -> CompileError {
+    let (start, end) = match error.location {
+        pest::error::InputLocation::Pos(pos) => (pos, pos),
+        pest::error::InputLocation::Span(span) => span,
+    };
+    let (line, column) = match error.line_col {
+        pest::error::LineColLocation::Pos(pos) => pos,
+        pest::error::LineColLocation::Span(start, _) => start,
+    };
+    CompileError::new(
+        CompileErrorKind::Syntax(error.variant.message().into_owned()),
+        Span {
+            start,
+            end,
+            line,
+            column,
+        },
+    )
+}
+
+/// Error for a pair the grammar does not allow where it was found.
+fn unexpected(pair: &Pair<Rule>) -> CompileError {
+    CompileError::new(
+        CompileErrorKind::Syntax(format!("unexpected {:?}", pair.as_rule())),
+        pair.as_span().into(),
+    )
+}
+
+/// Span of an expression `pair`, without the whitespace pest skips after a
+/// trailing repetition such as `primary_expr ~ index*`.
+fn expression_span(pair: &Pair<Rule>) -> Span {
+    let span = pair.as_span();
+    Span {
+        end: span.start() + span.as_str().trim_end().len(),
+        ..span.into()
     }
 }
 
-pub fn parse_statements(pair: Pair<Rule>) -> Vec<AstStatement> {
+/// Next pair of `pairs`, `span` locates the error when there is none.
+fn next<'i>(pairs: &mut Pairs<'i, Rule>, span: Span) -> Result<Pair<'i, Rule>, CompileError> {
+    pairs.next().ok_or_else(|| {
+        CompileError::new(
+            CompileErrorKind::Syntax("incomplete syntax tree".to_string()),
+            span,
+        )
+    })
+}
+
+/// First inner pair of `pair`.
+fn first(pair: Pair<Rule>) -> Result<Pair<Rule>, CompileError> {
+    let span = pair.as_span().into();
+    next(&mut pair.into_inner(), span)
+}
+
+pub fn parse_program(pair: Pair<Rule>) -> Result<AstProgram, CompileError> {
+    let mut statements = Vec::new();
+    for pair in pair.into_inner().filter(|p| p.as_rule() != Rule::EOI) {
+        statements.extend(parse_statements(pair)?);
+    }
+    Ok(AstProgram { statements })
+}
+
+pub fn parse_statements(pair: Pair<Rule>) -> Result<Vec<AstStatement>, CompileError> {
     match pair.as_rule() {
         Rule::statements => pair.into_inner().map(parse_statement).collect(),
-        _ => unreachable!(),
+        _ => Err(unexpected(&pair)),
     }
 }
 
-pub fn parse_statement(pair: Pair<Rule>) -> AstStatement {
+pub fn parse_statement(pair: Pair<Rule>) -> Result<AstStatement, CompileError> {
+    let span: Span = pair.as_span().into();
     match pair.as_rule() {
-        Rule::statement => parse_statement(pair.into_inner().next().unwrap()),
+        Rule::statement | Rule::assignment_stmt => parse_statement(first(pair)?),
         Rule::assignment => {
             let mut inner = pair.into_inner();
-            let identifier = inner.next().unwrap().as_str().trim().to_string();
-            let expr = parse_expression(inner.next().unwrap());
-            AstStatement::Assignment(identifier, expr)
+            let identifier = next(&mut inner, span)?.as_str().trim().to_string();
+            let value = next(&mut inner, span)?;
+            if value.as_rule() == Rule::index {
+                let index = parse_expression(first(value)?)?;
+                let expr = parse_expression(next(&mut inner, span)?)?;
+                return Ok(AstStatement::IndexAssignment(identifier, index, expr, span));
+            }
+            let expr = parse_expression(value)?;
+            Ok(AstStatement::Assignment(identifier, expr, span))
         }
-        Rule::assignment_stmt => parse_statement(pair.into_inner().next().unwrap()),
         Rule::while_statement => {
             let mut inner = pair.into_inner();
-            let condition = parse_expression(inner.next().unwrap());
-            let body = inner
-                .next()
-                .unwrap()
-                .into_inner()
-                .map(parse_statement)
-                .collect();
-            AstStatement::While(condition, body)
+            let condition = parse_expression(next(&mut inner, span)?)?;
+            let body = parse_statements(next(&mut inner, span)?)?;
+            Ok(AstStatement::While(condition, body, span))
+        }
+        Rule::for_statement => {
+            let mut init = None;
+            let mut condition = None;
+            let mut step = None;
+            let mut body = Vec::new();
+            for part in pair.into_inner() {
+                match part.as_rule() {
+                    Rule::for_init => init = Some(Box::new(parse_for_clause(part)?)),
+                    Rule::for_condition => condition = Some(parse_expression(first(part)?)?),
+                    Rule::for_step => step = Some(Box::new(parse_for_clause(part)?)),
+                    Rule::statements => body = parse_statements(part)?,
+                    _ => return Err(unexpected(&part)),
+                }
+            }
+            Ok(AstStatement::For(init, condition, step, body, span))
+        }
+        Rule::break_statement => Ok(AstStatement::Break(span)),
+        Rule::continue_statement => Ok(AstStatement::Continue(span)),
+        Rule::if_statement => {
+            let mut inner = pair.into_inner();
+            let condition = parse_expression(next(&mut inner, span)?)?;
+            let then_body = parse_statements(next(&mut inner, span)?)?;
+            let else_body = match inner.next() {
+                Some(else_clause) => {
+                    let branch = first(else_clause)?;
+                    Some(match branch.as_rule() {
+                        Rule::if_statement => vec![parse_statement(branch)?],
+                        Rule::statements => parse_statements(branch)?,
+                        _ => return Err(unexpected(&branch)),
+                    })
+                }
+                None => None,
+            };
+            Ok(AstStatement::If(condition, then_body, else_body, span))
+        }
+        Rule::function_definition => {
+            let mut inner = pair.into_inner();
+            let name = next(&mut inner, span)?.as_str().to_string();
+
+            let mut parameters = Vec::new();
+            let mut body = Vec::new();
+            for part in inner {
+                match part.as_rule() {
+                    Rule::parameters => {
+                        parameters = part.into_inner().map(|p| p.as_str().to_string()).collect();
+                    }
+                    Rule::statements => body = parse_statements(part)?,
+                    _ => return Err(unexpected(&part)),
+                }
+            }
+            Ok(AstStatement::FunctionDefinition(
+                name, parameters, body, span,
+            ))
+        }
+        Rule::return_statement => {
+            let value = pair.into_inner().next().map(parse_expression).transpose()?;
+            Ok(AstStatement::Return(value, span))
         }
         Rule::expression_stmt => {
-            let expr = parse_expression(pair.into_inner().next().unwrap());
-            AstStatement::Expression(expr)
+            let expr = parse_expression(first(pair)?)?;
+            Ok(AstStatement::Expression(expr, span))
         }
-        _ => unreachable!(),
+        _ => Err(unexpected(&pair)),
+    }
+}
+
+/// Parses the init or step of a `for` header into a statement.
+pub fn parse_for_clause(pair: Pair<Rule>) -> Result<AstStatement, CompileError> {
+    let span = pair.as_span().into();
+    let inner = first(pair)?;
+    match inner.as_rule() {
+        Rule::assignment => parse_statement(inner),
+        _ => Ok(AstStatement::Expression(parse_expression(inner)?, span)),
     }
 }
 
-pub fn parse_expression(pair: Pair<Rule>) -> AstExpression {
+pub fn parse_expression(pair: Pair<Rule>) -> Result<AstExpression, CompileError> {
     match pair.as_rule() {
-        Rule::expression => parse_expression(pair.into_inner().next().unwrap()),
-        Rule::identifier => AstExpression::Variable(pair.as_str().to_string()),
-        Rule::string | Rule::inner => {
-            AstExpression::Literal(parse_literal(pair.into_inner().next().unwrap()))
-        }
-        Rule::equality_expr
+        Rule::expression => parse_expression(first(pair)?),
+        Rule::identifier => Ok(AstExpression::Variable(pair.as_str().to_string())),
+        Rule::string | Rule::inner => Ok(AstExpression::Literal(parse_literal(first(pair)?)?)),
+        Rule::or_expr
+        | Rule::and_expr
+        | Rule::xor_expr
+        | Rule::equality_expr
         | Rule::relational_expr
         | Rule::additive_expr
         | Rule::multiplicative_expr => parse_binary_expression(pair),
         Rule::unary_expr => parse_unary_expression(pair),
-        _ => unreachable!(),
+        _ => Err(unexpected(&pair)),
     }
 }
 
-pub fn parse_unary_expression(pair: Pair<Rule>) -> AstExpression {
+pub fn parse_unary_expression(pair: Pair<Rule>) -> Result<AstExpression, CompileError> {
     match pair.as_rule() {
         Rule::unary_expr => {
+            let span = expression_span(&pair);
             let mut inner = pair.into_inner();
-            let first = inner.next().unwrap();
+            let first = next(&mut inner, span)?;
 
-            // if it's a primary_expr, just parse it directly
-            if first.as_rule() == Rule::primary_expr {
-                return parse_primary_expression(first);
+            // if it's a postfix_expr, just parse it directly
+            if first.as_rule() == Rule::postfix_expr {
+                return parse_postfix_expression(first);
             }
 
-            // otherwise it's a unary operation
+            // otherwise it's a unary operation, possibly nested (`!!x`)
             let operator = first.as_str().to_string();
-            let expr = parse_primary_expression(inner.next().unwrap());
-            AstExpression::UnaryOperation(operator, Box::new(expr))
+            let expr = parse_unary_expression(next(&mut inner, span)?)?;
+            Ok(AstExpression::UnaryOperation(
+                operator,
+                Box::new(expr),
+                span,
+            ))
         }
+        Rule::postfix_expr => parse_postfix_expression(pair),
         Rule::primary_expr => parse_primary_expression(pair),
-        _ => unreachable!(),
+        _ => Err(unexpected(&pair)),
     }
 }
 
-pub fn parse_binary_expression(pair: Pair<Rule>) -> AstExpression {
+/// Parses a primary expression followed by any number of `[index]`.
+pub fn parse_postfix_expression(pair: Pair<Rule>) -> Result<AstExpression, CompileError> {
+    let span = pair.as_span().into();
+    let mut inner = pair.into_inner();
+    let mut expr = parse_primary_expression(next(&mut inner, span)?)?;
+    for index in inner {
+        let index = parse_expression(first(index)?)?;
+        expr = AstExpression::Index(Box::new(expr), Box::new(index));
+    }
+    Ok(expr)
+}
+
+pub fn parse_binary_expression(pair: Pair<Rule>) -> Result<AstExpression, CompileError> {
     /*
      *  parses binary and unary expressions with the following precedence:
-     *    equality_expr > relational_expr > additive_expr > multiplicative_expr > unary_expr
+     *    or_expr > and_expr > xor_expr > equality_expr > relational_expr > additive_expr
+     *    > multiplicative_expr > unary_expr
      */
 
     match pair.as_rule() {
-        Rule::equality_expr
+        Rule::or_expr
+        | Rule::and_expr
+        | Rule::xor_expr
+        | Rule::equality_expr
         | Rule::relational_expr
         | Rule::additive_expr
         | Rule::multiplicative_expr => {
@@ -100,67 +263,99 @@ pub fn parse_binary_expression(pair: Pair<Rule>) -> AstExpression {
              * Parse first expr as left side, then combine with any additional
              * operator-expr pairs into binary operations, building left-to-right.
              */
+            let span = pair.as_span().into();
             let mut inner = pair.into_inner();
-            let mut left = parse_expression(inner.next().unwrap());
+            let mut left = parse_expression(next(&mut inner, span)?)?;
 
             while let Some(op_pair) = inner.next() {
                 let operator = op_pair.as_str().to_string();
-                let right = parse_expression(inner.next().unwrap());
-                left = AstExpression::BinaryOperation(operator, Box::new(left), Box::new(right));
+                let right_pair = next(&mut inner, span)?;
+                // each operation spans from the first operand to its right one
+                let span = Span {
+                    end: expression_span(&right_pair).end,
+                    ..span
+                };
+                let right = parse_expression(right_pair)?;
+                left =
+                    AstExpression::BinaryOperation(operator, Box::new(left), Box::new(right), span);
             }
 
-            left
+            Ok(left)
         }
-        Rule::unary_expr => parse_unary_expression(pair.into_inner().next().unwrap()),
-        _ => unreachable!(),
+        Rule::unary_expr => parse_unary_expression(first(pair)?),
+        _ => Err(unexpected(&pair)),
     }
 }
 
-pub fn parse_arguments(pair: Pair<Rule>) -> Vec<AstExpression> {
+pub fn parse_arguments(pair: Pair<Rule>) -> Result<Vec<AstExpression>, CompileError> {
     match pair.as_rule() {
-        Rule::arguments => {
-            let mut args = Vec::new();
-            let mut inner = pair.into_inner();
-
-            while let Some(arg) = inner.next() {
-                args.push(parse_expression(arg));
-            }
-
-            args
-        }
-        _ => unreachable!(),
+        Rule::arguments => pair.into_inner().map(parse_expression).collect(),
+        _ => Err(unexpected(&pair)),
     }
 }
 
-pub fn parse_primary_expression(pair: Pair<Rule>) -> AstExpression {
+pub fn parse_primary_expression(pair: Pair<Rule>) -> Result<AstExpression, CompileError> {
     match pair.as_rule() {
-        Rule::identifier => AstExpression::Variable(pair.as_str().to_string()),
-        Rule::literal => AstExpression::Literal(parse_literal(pair.into_inner().next().unwrap())),
+        Rule::identifier => Ok(AstExpression::Variable(pair.as_str().to_string())),
+        Rule::literal => Ok(AstExpression::Literal(parse_literal(first(pair)?)?)),
         Rule::function_call => {
+            let span = pair.as_span().into();
             let mut inner = pair.into_inner();
-            let name = inner.next().unwrap().as_str().to_string();
-            let args = if let Some(args_pair) = inner.next() {
-                parse_arguments(args_pair)
-            } else {
-                Vec::new()
+            let name = next(&mut inner, span)?.as_str().to_string();
+            let args = match inner.next() {
+                Some(args_pair) => parse_arguments(args_pair)?,
+                None => Vec::new(),
             };
-            AstExpression::FunctionCall(name, args)
+            Ok(AstExpression::FunctionCall(name, args, span))
+        }
+        Rule::list_literal => Ok(AstExpression::List(
+            pair.into_inner()
+                .map(parse_expression)
+                .collect::<Result<_, _>>()?,
+        )),
+        Rule::map_literal => {
+            let mut entries = Vec::new();
+            for entry in pair.into_inner() {
+                let span = entry.as_span().into();
+                let mut inner = entry.into_inner();
+                let key = parse_expression(next(&mut inner, span)?)?;
+                let value = parse_expression(next(&mut inner, span)?)?;
+                entries.push((key, value));
+            }
+            Ok(AstExpression::Map(entries))
         }
-        Rule::primary_expr => parse_primary_expression(pair.into_inner().next().unwrap()),
-        _ => unreachable!(),
+        Rule::primary_expr => parse_primary_expression(first(pair)?),
+        // parenthesized
+        Rule::expression => parse_expression(pair),
+        _ => Err(unexpected(&pair)),
     }
 }
 
-pub fn parse_literal(pair: Pair<Rule>) -> AstLiteral {
+/// Whole numbers are `UInt`s when they fit and `Int`s otherwise, anything
+/// with a fraction or an exponent is a `Float`. `None` for a whole number
+/// too large for an `i64`.
+pub fn parse_number(number: &str) -> Option<AstLiteral> {
+    if number.contains(['.', 'e', 'E']) {
+        return number.parse().ok().map(AstLiteral::Float);
+    }
+    if let Ok(n) = number.parse() {
+        return Some(AstLiteral::UInt(n));
+    }
+    number.parse().ok().map(AstLiteral::Int)
+}
+
+pub fn parse_literal(pair: Pair<Rule>) -> Result<AstLiteral, CompileError> {
     match pair.as_rule() {
-        Rule::number => AstLiteral::UInt(pair.as_str().parse().unwrap()),
-        Rule::bool => AstLiteral::Bool(pair.as_str().parse().unwrap()),
-        Rule::null => AstLiteral::Null,
-        Rule::string => {
-            let inner = pair.into_inner().next().unwrap();
-            AstLiteral::String(inner.as_str().to_string())
-        }
-        Rule::inner => AstLiteral::String(pair.as_str().to_string()),
-        _ => unreachable!(),
+        Rule::number => parse_number(pair.as_str()).ok_or_else(|| {
+            CompileError::new(
+                CompileErrorKind::IntegerOverflow(pair.as_str().to_string()),
+                pair.as_span().into(),
+            )
+        }),
+        Rule::bool => Ok(AstLiteral::Bool(pair.as_str() == "true")),
+        Rule::null => Ok(AstLiteral::Null),
+        Rule::string => Ok(AstLiteral::String(first(pair)?.as_str().to_string())),
+        Rule::inner => Ok(AstLiteral::String(pair.as_str().to_string())),
+        _ => Err(unexpected(&pair)),
     }
 }
@@ -0,0 +1,180 @@
+use std::collections::HashSet;
+use vmo2_types::bytecode::ByteCode;
+use vmo2_types::opcode::*;
+use vmo2_types::source_map::{SourceLocation, SourceMap};
+use vmo2_types::value::Value;
+
+/*
+ *  Peephole optimizer over linked bytecode.
+ *
+ *  Each pass first threads jumps: a flow opcode whose target is a `Jump`
+ *  goes straight to where that jump leads. It then rewrites these windows:
+ *
+ *      Dup, Pop                         ->  (nothing)
+ *      Swap, Swap                       ->  (nothing)
+ *      Literal(v), Pop                  ->  (nothing)
+ *      Jump(next address)               ->  (nothing)
+ *      Literal(k), Store, Literal(k), Load
+ *                                       ->  Dup, Literal(k), Store
+ *
+ *  A window is only rewritten when none of its opcodes but the first is a
+ *  jump or call target, since control entering it halfway would see a
+ *  different program. Every flow opcode is then re-targeted to the new
+ *  address of its target, a removed target standing for the opcode that
+ *  followed it. Passes run until nothing changes.
+ *
+ *  Removing `Dup, Pop` or `Swap, Swap` hides a stack underflow, which is
+ *  fine for compiled programs as they never underflow.
+ */
+
+/// Opcodes with the location each came from.
+type Code = Vec<(Opcode, Option<SourceLocation>)>;
+
+pub fn optimize(bytecode: ByteCode) -> ByteCode {
+    let len = bytecode.opcodes.len();
+    let source_map = SourceMap {
+        locations: vec![None; len],
+    };
+    optimize_with_source_map(bytecode, source_map).0
+}
+
+/// Like `optimize`, keeping `source_map` in step with the opcodes.
+pub fn optimize_with_source_map(
+    bytecode: ByteCode,
+    mut source_map: SourceMap,
+) -> (ByteCode, SourceMap) {
+    source_map.locations.resize(bytecode.opcodes.len(), None);
+    let mut code: Code = bytecode
+        .opcodes
+        .into_iter()
+        .zip(source_map.locations)
+        .collect();
+
+    loop {
+        let threaded = thread_jumps(&mut code);
+        if !rewrite(&mut code) && !threaded {
+            break;
+        }
+    }
+
+    let (opcodes, locations) = code.into_iter().unzip();
+    (ByteCode { opcodes }, SourceMap { locations })
+}
+
+/// Target of a flow opcode, mutably.
+fn target_mut(opcode: &mut Opcode) -> Option<&mut u32> {
+    match opcode {
+        Opcode::Flow(
+            FlowOpcode::JumpIfFalse(target)
+            | FlowOpcode::JumpIfTrue(target)
+            | FlowOpcode::Jump(target)
+            | FlowOpcode::Call(target),
+        ) => Some(target),
+        _ => None,
+    }
+}
+
+/// Points every flow opcode past the jumps its target leads through,
+/// returning whether any changed.
+fn thread_jumps(code: &mut Code) -> bool {
+    let jump_at = |code: &Code, address: u32| match code.get(address as usize) {
+        Some((Opcode::Flow(FlowOpcode::Jump(next)), _)) => Some(*next),
+        _ => None,
+    };
+
+    let mut changed = false;
+    for index in 0..code.len() {
+        let Some(mut target) = target_mut(&mut code[index].0).map(|target| *target) else {
+            continue;
+        };
+        // a cycle of jumps stays a loop
+        let mut seen = HashSet::from([target]);
+        while let Some(next) = jump_at(code, target)
+            && seen.insert(next)
+        {
+            target = next;
+        }
+        let current = target_mut(&mut code[index].0).unwrap();
+        changed |= *current != target;
+        *current = target;
+    }
+    changed
+}
+
+/// Replacement for the window of `code` starting at `index`, with the
+/// number of opcodes it replaces.
+fn window(code: &Code, index: usize) -> Option<(usize, Vec<Opcode>)> {
+    use Opcode::*;
+
+    let opcode = |offset: usize| code.get(index + offset).map(|(opcode, _)| opcode);
+    match (opcode(0)?, opcode(1)) {
+        (Dup, Some(Pop)) | (Swap, Some(Swap)) | (Literal(_), Some(Pop)) => Some((2, vec![])),
+        (Flow(FlowOpcode::Jump(target)), _) if *target as usize == index + 1 => Some((1, vec![])),
+        (Literal(Value::String(key)), Some(Memory(MemoryOpcode::Store))) => {
+            match (opcode(2)?, opcode(3)?) {
+                (Literal(Value::String(loaded)), Memory(MemoryOpcode::Load)) if loaded == key => {
+                    Some((
+                        4,
+                        vec![
+                            Dup,
+                            Literal(Value::String(key.clone())),
+                            Memory(MemoryOpcode::Store),
+                        ],
+                    ))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Rewrites the windows of one pass, returning whether any was.
+fn rewrite(code: &mut Code) -> bool {
+    let targets: HashSet<usize> = code
+        .iter_mut()
+        .filter_map(|(opcode, _)| target_mut(opcode).map(|target| *target as usize))
+        .collect();
+
+    // `addresses[i]` is the new address of old opcode `i`, or of the first
+    // one kept after it when it is removed
+    let mut addresses = Vec::with_capacity(code.len() + 1);
+    let mut rewritten: Code = Vec::with_capacity(code.len());
+    let mut index = 0;
+    while index < code.len() {
+        let replacement = window(code, index)
+            .filter(|(width, _)| (index + 1..index + width).all(|inner| !targets.contains(&inner)));
+        match replacement {
+            Some((width, opcodes)) => {
+                let location = code[index].1;
+                addresses.extend(std::iter::repeat_n(rewritten.len(), width));
+                rewritten.extend(opcodes.into_iter().map(|opcode| (opcode, location)));
+                index += width;
+            }
+            None => {
+                addresses.push(rewritten.len());
+                rewritten.push(code[index].clone());
+                index += 1;
+            }
+        }
+    }
+    addresses.push(rewritten.len());
+
+    if rewritten.len() == code.len() && rewritten == *code {
+        return false;
+    }
+
+    // targets past the end keep their distance to it
+    let (old_len, new_len) = (code.len(), rewritten.len());
+    for (opcode, _) in &mut rewritten {
+        if let Some(target) = target_mut(opcode) {
+            let old = *target as usize;
+            *target = match addresses.get(old) {
+                Some(&new) => new as u32,
+                None => (old - old_len + new_len) as u32,
+            };
+        }
+    }
+    *code = rewritten;
+    true
+}
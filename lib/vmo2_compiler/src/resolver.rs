@@ -0,0 +1,395 @@
+use crate::error::*;
+use crate::types::*;
+use std::collections::{HashMap, HashSet};
+
+/// Functions `IrBuilder` lowers inline rather than calling.
+pub const BUILTINS: [&str; 7] = ["print", "scan", "len", "keys", "contains", "push", "pop"];
+
+/// Argument count of a builtin, `None` for `print`, which takes any number.
+fn builtin_arity(name: &str) -> Option<usize> {
+    match name {
+        "scan" => Some(0),
+        "len" | "keys" | "pop" => Some(1),
+        "contains" | "push" => Some(2),
+        _ => None,
+    }
+}
+
+/*
+ *  Checks the names used by a program before it is lowered.
+ *
+ *  Functions live in one global namespace and can be called before their
+ *  definition, so they are all declared first. Variables are resolved in
+ *  source order and a read is accepted once the variable is definitely
+ *  assigned: on every path to it, as far as the resolver can tell without
+ *  evaluating conditions. An assignment inside an `if` only counts after it
+ *  when both branches make it (or the other one always leaves through
+ *  `return`, `break` or `continue`), and one inside a loop body never counts
+ *  after the loop, as the body may not run.
+ *
+ *  Inside a function, parameters and its own assignments are in scope, and
+ *  so are the globals definitely assigned at every call of the function, as
+ *  the VM falls back to the global heap when a name is not a local. Calls
+ *  from the top level are where these come from, a function called by
+ *  another one inherits what its caller sees. A function the top level never
+ *  reaches does not run, it is only checked against every global the top
+ *  level assigns anywhere.
+ */
+struct Resolver<'a> {
+    /// Parameters and body of every function.
+    functions: HashMap<&'a str, (&'a [String], &'a [AstStatement])>,
+    /// Function names in definition order, to resolve them deterministically.
+    order: Vec<&'a str>,
+    /// Globals definitely assigned at every call of each function the top
+    /// level calls directly.
+    entry_globals: HashMap<&'a str, HashSet<String>>,
+    /// Globals visible in the function being resolved.
+    globals: HashSet<String>,
+    /// Variables definitely assigned at this point of the function (or the
+    /// top level) being resolved.
+    scope: HashSet<String>,
+    in_function: bool,
+    span: Span,
+}
+
+/// Resolves `program`, reporting the first name that does not resolve.
+pub fn resolve(program: &AstProgram) -> Result<(), CompileError> {
+    let mut resolver = Resolver {
+        functions: HashMap::new(),
+        order: Vec::new(),
+        entry_globals: HashMap::new(),
+        globals: HashSet::new(),
+        scope: HashSet::new(),
+        in_function: false,
+        span: Span::default(),
+    };
+    resolver.declare_functions(&program.statements)?;
+    resolver.resolve_block(&program.statements)?;
+    resolver.propagate_entry_globals();
+
+    let mut all_globals = HashSet::new();
+    collect_globals(&program.statements, &mut all_globals);
+    for name in resolver.order.clone() {
+        let (parameters, body) = resolver.functions[name];
+        resolver.globals = match resolver.entry_globals.get(name) {
+            Some(globals) => globals.clone(),
+            None => all_globals.clone(),
+        };
+        resolver.scope = parameters.iter().cloned().collect();
+        resolver.in_function = true;
+        resolver.resolve_block(body)?;
+    }
+    Ok(())
+}
+
+/// Adds every variable `statements` assign to `globals`, without looking
+/// into function bodies.
+fn collect_globals(statements: &[AstStatement], globals: &mut HashSet<String>) {
+    for statement in statements {
+        match statement {
+            AstStatement::Assignment(name, _, _) => {
+                globals.insert(name.clone());
+            }
+            AstStatement::While(_, body, _) => collect_globals(body, globals),
+            AstStatement::For(init, _, step, body, _) => {
+                for clause in [init, step].into_iter().flatten() {
+                    collect_globals(std::slice::from_ref(clause), globals);
+                }
+                collect_globals(body, globals);
+            }
+            AstStatement::If(_, then_body, else_body, _) => {
+                collect_globals(then_body, globals);
+                if let Some(else_body) = else_body {
+                    collect_globals(else_body, globals);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Adds the name of every function `statements` call to `calls`, without
+/// looking into nested function definitions.
+fn collect_calls<'a>(statements: &'a [AstStatement], calls: &mut Vec<&'a str>) {
+    for statement in statements {
+        match statement {
+            AstStatement::Assignment(_, expr, _)
+            | AstStatement::Expression(expr, _)
+            | AstStatement::Return(Some(expr), _) => collect_expr_calls(expr, calls),
+            AstStatement::IndexAssignment(_, index, expr, _) => {
+                collect_expr_calls(index, calls);
+                collect_expr_calls(expr, calls);
+            }
+            AstStatement::While(condition, body, _) => {
+                collect_expr_calls(condition, calls);
+                collect_calls(body, calls);
+            }
+            AstStatement::For(init, condition, step, body, _) => {
+                for clause in [init, step].into_iter().flatten() {
+                    collect_calls(std::slice::from_ref(clause), calls);
+                }
+                if let Some(condition) = condition {
+                    collect_expr_calls(condition, calls);
+                }
+                collect_calls(body, calls);
+            }
+            AstStatement::If(condition, then_body, else_body, _) => {
+                collect_expr_calls(condition, calls);
+                collect_calls(then_body, calls);
+                if let Some(else_body) = else_body {
+                    collect_calls(else_body, calls);
+                }
+            }
+            AstStatement::FunctionDefinition(..)
+            | AstStatement::Return(None, _)
+            | AstStatement::Break(_)
+            | AstStatement::Continue(_) => {}
+        }
+    }
+}
+
+fn collect_expr_calls<'a>(expr: &'a AstExpression, calls: &mut Vec<&'a str>) {
+    match expr {
+        AstExpression::Literal(_) | AstExpression::Variable(_) => {}
+        AstExpression::UnaryOperation(_, expr, _) => collect_expr_calls(expr, calls),
+        AstExpression::BinaryOperation(_, left, right, _) | AstExpression::Index(left, right) => {
+            collect_expr_calls(left, calls);
+            collect_expr_calls(right, calls);
+        }
+        AstExpression::List(items) => {
+            for item in items {
+                collect_expr_calls(item, calls);
+            }
+        }
+        AstExpression::Map(entries) => {
+            for (key, value) in entries {
+                collect_expr_calls(key, calls);
+                collect_expr_calls(value, calls);
+            }
+        }
+        AstExpression::FunctionCall(name, args, _) => {
+            calls.push(name);
+            for arg in args {
+                collect_expr_calls(arg, calls);
+            }
+        }
+    }
+}
+
+impl<'a> Resolver<'a> {
+    fn error(&self, kind: CompileErrorKind) -> CompileError {
+        CompileError::new(kind, self.span)
+    }
+
+    /// Records every function defined in `statements`, nested ones included.
+    fn declare_functions(&mut self, statements: &'a [AstStatement]) -> Result<(), CompileError> {
+        for statement in statements {
+            self.span = statement.span();
+            match statement {
+                AstStatement::FunctionDefinition(name, parameters, body, _) => {
+                    if BUILTINS.contains(&name.as_str()) {
+                        return Err(self.error(CompileErrorKind::BuiltinRedefinition(name.clone())));
+                    }
+                    if self.functions.contains_key(name.as_str()) {
+                        return Err(self.error(CompileErrorKind::DuplicateFunction(name.clone())));
+                    }
+                    self.functions.insert(name, (parameters, body));
+                    self.order.push(name);
+                    self.declare_functions(body)?;
+                }
+                AstStatement::While(_, body, _) | AstStatement::For(_, _, _, body, _) => {
+                    self.declare_functions(body)?;
+                }
+                AstStatement::If(_, then_body, else_body, _) => {
+                    self.declare_functions(then_body)?;
+                    if let Some(else_body) = else_body {
+                        self.declare_functions(else_body)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Narrows the globals a function sees to those of every function that
+    /// calls it, until nothing changes.
+    fn propagate_entry_globals(&mut self) {
+        let mut pending: Vec<&'a str> = self.entry_globals.keys().copied().collect();
+        while let Some(caller) = pending.pop() {
+            let mut callees = Vec::new();
+            collect_calls(self.functions[caller].1, &mut callees);
+            let globals = self.entry_globals[caller].clone();
+            for callee in callees {
+                let Some((&callee, _)) = self.functions.get_key_value(callee) else {
+                    continue;
+                };
+                let changed = match self.entry_globals.get_mut(callee) {
+                    Some(entry) => {
+                        let before = entry.len();
+                        entry.retain(|name| globals.contains(name));
+                        entry.len() != before
+                    }
+                    None => {
+                        self.entry_globals.insert(callee, globals.clone());
+                        true
+                    }
+                };
+                if changed {
+                    pending.push(callee);
+                }
+            }
+        }
+    }
+
+    /// Returns whether `statements` always leave through `return`, `break`
+    /// or `continue`.
+    fn resolve_block(&mut self, statements: &[AstStatement]) -> Result<bool, CompileError> {
+        let mut leaves = false;
+        for statement in statements {
+            leaves |= self.resolve_stmt(statement)?;
+        }
+        Ok(leaves)
+    }
+
+    fn resolve_stmt(&mut self, stmt: &AstStatement) -> Result<bool, CompileError> {
+        let outer_span = std::mem::replace(&mut self.span, stmt.span());
+        let leaves = match stmt {
+            AstStatement::Assignment(name, expr, _) => {
+                self.resolve_expr(expr)?;
+                self.scope.insert(name.clone());
+                false
+            }
+            AstStatement::IndexAssignment(name, index, expr, _) => {
+                self.resolve_variable(name)?;
+                self.resolve_expr(index)?;
+                self.resolve_expr(expr)?;
+                false
+            }
+            // bodies are resolved on their own once the top level is done
+            AstStatement::FunctionDefinition(..) => false,
+            AstStatement::While(condition, body, _) => {
+                self.resolve_expr(condition)?;
+                // the body may not run at all
+                let before = self.scope.clone();
+                self.resolve_block(body)?;
+                self.scope = before;
+                false
+            }
+            AstStatement::For(init, condition, step, body, _) => {
+                if let Some(init) = init {
+                    self.resolve_stmt(init)?;
+                }
+                if let Some(condition) = condition {
+                    self.resolve_expr(condition)?;
+                }
+                let before = self.scope.clone();
+                self.resolve_block(body)?;
+                // `continue` skips the rest of the body before the step
+                self.scope = before.clone();
+                if let Some(step) = step {
+                    self.resolve_stmt(step)?;
+                }
+                self.scope = before;
+                false
+            }
+            AstStatement::If(condition, then_body, else_body, _) => {
+                self.resolve_expr(condition)?;
+                let before = self.scope.clone();
+                let then_leaves = self.resolve_block(then_body)?;
+                let then_scope = std::mem::replace(&mut self.scope, before);
+                let else_leaves = match else_body {
+                    Some(else_body) => self.resolve_block(else_body)?,
+                    None => false,
+                };
+                // a branch that always leaves does not reach what follows
+                match (then_leaves, else_leaves) {
+                    (false, false) => self.scope.retain(|name| then_scope.contains(name)),
+                    (false, true) => self.scope = then_scope,
+                    (true, _) => {}
+                }
+                then_leaves && else_leaves
+            }
+            AstStatement::Expression(expr, _) => {
+                self.resolve_expr(expr)?;
+                false
+            }
+            AstStatement::Return(expr, _) => {
+                if let Some(expr) = expr {
+                    self.resolve_expr(expr)?;
+                }
+                true
+            }
+            AstStatement::Break(_) | AstStatement::Continue(_) => true,
+        };
+        self.span = outer_span;
+        Ok(leaves)
+    }
+
+    fn resolve_variable(&self, name: &str) -> Result<(), CompileError> {
+        if self.scope.contains(name) || (self.in_function && self.globals.contains(name)) {
+            return Ok(());
+        }
+        Err(self.error(CompileErrorKind::UndefinedVariable(name.to_string())))
+    }
+
+    fn resolve_expr(&mut self, expr: &AstExpression) -> Result<(), CompileError> {
+        match expr {
+            AstExpression::Literal(_) => Ok(()),
+            AstExpression::Variable(name) => self.resolve_variable(name),
+            AstExpression::UnaryOperation(_, expr, _) => self.resolve_expr(expr),
+            AstExpression::BinaryOperation(_, left, right, _)
+            | AstExpression::Index(left, right) => {
+                self.resolve_expr(left)?;
+                self.resolve_expr(right)
+            }
+            AstExpression::List(items) => items.iter().try_for_each(|item| self.resolve_expr(item)),
+            AstExpression::Map(entries) => entries.iter().try_for_each(|(key, value)| {
+                self.resolve_expr(key)?;
+                self.resolve_expr(value)
+            }),
+            AstExpression::FunctionCall(name, args, span) => {
+                for arg in args {
+                    self.resolve_expr(arg)?;
+                }
+                let expected = if BUILTINS.contains(&name.as_str()) {
+                    let Some(expected) = builtin_arity(name) else {
+                        return Ok(());
+                    };
+                    expected
+                } else {
+                    let Some((&function, (parameters, _))) =
+                        self.functions.get_key_value(name.as_str())
+                    else {
+                        return Err(CompileError::new(
+                            CompileErrorKind::UndefinedFunction(name.clone()),
+                            *span,
+                        ));
+                    };
+                    let expected = parameters.len();
+                    // the function sees the globals assigned at all of its calls
+                    if !self.in_function {
+                        match self.entry_globals.get_mut(function) {
+                            Some(globals) => globals.retain(|name| self.scope.contains(name)),
+                            None => {
+                                self.entry_globals.insert(function, self.scope.clone());
+                            }
+                        }
+                    }
+                    expected
+                };
+                if expected != args.len() {
+                    return Err(CompileError::new(
+                        CompileErrorKind::ArgumentCount {
+                            name: name.clone(),
+                            expected,
+                            found: args.len(),
+                        },
+                        *span,
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::Diagnostic;
+    use crate::error::{CompileError, CompileErrorKind};
+    use crate::ir_compiler::compile_to_ir;
+    use crate::parser::parse_source;
+    use crate::resolver::resolve;
+    use crate::type_checker::check;
+    use crate::types::*;
+
+    fn compile(code: &str) -> Result<(), CompileError> {
+        let program = parse_source(code)?;
+        resolve(&program)?;
+        compile_to_ir(program).map(|_| ())
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let error = compile("x = 1;\ny = (2 + ;").unwrap_err();
+        assert!(matches!(error.kind, CompileErrorKind::Syntax(_)));
+        assert_eq!((error.span.line, error.span.column), (2, 10));
+    }
+
+    #[test]
+    fn test_integer_overflow() {
+        let error = compile("x = 1;\ny = 99999999999999999999;").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::IntegerOverflow("99999999999999999999".to_owned())
+        );
+        assert_eq!((error.span.line, error.span.column), (2, 5));
+
+        assert!(compile("x = 9223372036854775807;").is_ok());
+    }
+
+    #[test]
+    fn test_undefined_function() {
+        let error = compile("x = 1;\nf(x);").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::UndefinedFunction("f".to_owned())
+        );
+        assert_eq!(error.span.line, 2);
+
+        // calls may come before the definition
+        assert!(compile("f(1);\nfunc f(n) { return n; }").is_ok());
+    }
+
+    #[test]
+    fn test_argument_count() {
+        let error = compile("func f(a, b) { return a; }\nx = f(1);").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::ArgumentCount {
+                name: "f".to_owned(),
+                expected: 2,
+                found: 1,
+            }
+        );
+        assert_eq!(error.span.line, 2);
+    }
+
+    #[test]
+    fn test_unknown_operator() {
+        let power = AstExpression::BinaryOperation(
+            "**".to_owned(),
+            Box::new(AstExpression::Literal(AstLiteral::UInt(2))),
+            Box::new(AstExpression::Literal(AstLiteral::UInt(3))),
+            Span::default(),
+        );
+        let program = AstProgram {
+            statements: vec![AstStatement::Expression(power, Span::default())],
+        };
+
+        let error = compile_to_ir(program).unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::UnknownOperator("**".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "x = 1;\n\ty = f(x);\n";
+        let error = compile(source).unwrap_err();
+        let diagnostic = Diagnostic::new("main.oxy", &error);
+
+        assert_eq!(
+            diagnostic.to_string(),
+            "main.oxy:2:6: call to undefined function `f`"
+        );
+        assert_eq!(
+            diagnostic.render(source),
+            "call to undefined function `f`
+ --> main.oxy:2:6
+  |
+2 | \ty = f(x);
+  | \t    ^^^^"
+        );
+    }
+
+    #[test]
+    fn test_render_operator() {
+        let source = "x = 1;\nx = true && x;\n";
+        let program = parse_source(source).unwrap();
+        let error = check(&program).unwrap_err();
+
+        assert_eq!(
+            Diagnostic::new("main.oxy", &error).render(source),
+            "cannot apply `&&` to bool and uint
+ --> main.oxy:2:5
+  |
+2 | x = true && x;
+  |     ^^^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn test_render_syntax_error() {
+        let source = "x = (1;";
+        let error = compile(source).unwrap_err();
+        let rendered = Diagnostic::new("main.oxy", &error).render(source);
+
+        assert!(
+            rendered.ends_with("\n1 | x = (1;\n  |       ^"),
+            "{rendered}"
+        );
+    }
+
+    #[test]
+    fn test_render_outside_source() {
+        let error = CompileError::new(
+            CompileErrorKind::BreakOutsideLoop,
+            Span {
+                start: 100,
+                end: 105,
+                line: 12,
+                column: 3,
+            },
+        );
+
+        assert_eq!(
+            Diagnostic::new("main.oxy", &error).render(""),
+            "`break` outside of a loop\n  --> main.oxy:12:3"
+        );
+    }
+}
@@ -0,0 +1,462 @@
+#[cfg(test)]
+mod tests {
+    use crate::ir_compiler::*;
+    use crate::parser::*;
+    use crate::peephole;
+    use crate::resolver::resolve;
+    use crate::type_checker::check;
+    use crate::types::*;
+    use pest::Parser;
+    use quickcheck_macros::quickcheck;
+    use vmo2_types::value::Value;
+    use vmo2_vm::io::BufferIo;
+    use vmo2_vm::verifier::verify;
+    use vmo2_vm::vm::{VM, VMErrorKind};
+
+    fn run(code: &str) -> VM {
+        run_with_input(code, &[]).0
+    }
+
+    fn run_with_input(code: &str, input: &[&str]) -> (VM, Vec<String>) {
+        let program_pair = OxydeParser::parse(Rule::program, code)
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let program = parse_program(program_pair).unwrap();
+        resolve(&program).unwrap();
+        let types = check(&program).unwrap();
+        let bytecode = ir_to_bytecode(compile_to_typed_ir(program, &types).unwrap()).unwrap();
+        assert_eq!(verify(&bytecode), Ok(()));
+        assert_eq!(verify(&peephole::optimize(bytecode.clone())), Ok(()));
+
+        let io = BufferIo::with_input(input.iter().copied());
+        let mut vm = VM::new(bytecode).with_io(io.clone());
+        vm.run().unwrap();
+        (vm, io.output())
+    }
+
+    #[test]
+    fn test_while_loop() {
+        let vm = run(r#"
+            i = 0;
+            sum = 0;
+            while (i < 5) {
+                sum = sum + i;
+                i = i + 1;
+            }
+        "#);
+
+        assert_eq!(vm.heap["i"], Value::UInt(5));
+        assert_eq!(vm.heap["sum"], Value::UInt(10));
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_nested_while_loops() {
+        let vm = run(r#"
+            i = 0;
+            total = 0;
+            while (i < 3) {
+                j = 0;
+                while (j < 4) {
+                    total = total + 1;
+                    j = j + 1;
+                }
+                i = i + 1;
+            }
+            done = 1;
+        "#);
+
+        assert_eq!(vm.heap["total"], Value::UInt(12));
+        assert_eq!(vm.heap["done"], Value::UInt(1));
+    }
+
+    #[test]
+    fn test_function_calls() {
+        let vm = run(r#"
+            func double(n) {
+                return n * 2;
+            }
+            x = 1;
+            while (x < 100) {
+                x = double(x);
+            }
+        "#);
+
+        assert_eq!(vm.heap["x"], Value::UInt(128));
+        assert!(!vm.heap.contains_key("n"));
+        assert!(vm.call_stack.is_empty());
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_locals_do_not_leak() {
+        let vm = run(r#"
+            x = 1;
+            func shadow(y) {
+                x = y;
+                return x;
+            }
+            func no_return() {
+                z = 3;
+            }
+            result = shadow(5);
+            nothing = no_return();
+        "#);
+
+        // `x` inside `shadow` is a local, globals are only read through
+        assert_eq!(vm.heap["x"], Value::UInt(1));
+        assert_eq!(vm.heap["result"], Value::UInt(5));
+        assert_eq!(vm.heap["nothing"], Value::Null);
+        assert!(!vm.heap.contains_key("y"));
+        assert!(!vm.heap.contains_key("z"));
+    }
+
+    #[test]
+    fn test_recursive_factorial() {
+        let vm = run(r#"
+            func factorial(n) {
+                result = 1;
+                while (n > 1) {
+                    result = result * n;
+                    n = n - 1;
+                }
+                return result;
+            }
+            func factorial_rec(n) {
+                if (n < 2) {
+                    return 1;
+                }
+                return n * factorial_rec(n - 1);
+            }
+            a = factorial(10);
+            b = factorial_rec(10);
+        "#);
+
+        assert_eq!(vm.heap["a"], Value::UInt(3628800));
+        assert_eq!(vm.heap["b"], Value::UInt(3628800));
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_recursive_fibonacci() {
+        let (vm, output) = run_with_input(
+            r#"
+            func fib(n) {
+                if (n < 2) {
+                    return n;
+                }
+                a = fib(n - 1);
+                b = fib(n - 2);
+                return a + b;
+            }
+            i = 0;
+            while (i < 10) {
+                print(fib(i));
+                i = i + 1;
+            }
+            total = fib(15);
+        "#,
+            &[],
+        );
+
+        assert_eq!(
+            output,
+            vec!["0", "1", "1", "2", "3", "5", "8", "13", "21", "34"]
+        );
+        assert_eq!(vm.heap["total"], Value::UInt(610));
+        assert!(vm.call_stack.is_empty());
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_if_else_chains() {
+        let (_, output) = run_with_input(
+            r#"
+            func classify(n) {
+                if (n < 10) {
+                    return "small";
+                } else if (n < 100) {
+                    return "medium";
+                } else if (n < 1000) {
+                    return "large";
+                } else {
+                    return "huge";
+                }
+            }
+            print(classify(1), classify(50), classify(500), classify(5000));
+
+            i = 0;
+            evens = 0;
+            odds = 0;
+            while (i < 10) {
+                if (i / 2 * 2 == i) {
+                    evens = evens + 1;
+                } else {
+                    odds = odds + 1;
+                }
+                if (i == 3) {
+                    print("three");
+                }
+                i = i + 1;
+            }
+            print(evens, odds);
+        "#,
+            &[],
+        );
+
+        assert_eq!(
+            output,
+            vec!["small", "medium", "large", "huge", "three", "5", "5"]
+        );
+    }
+
+    #[test]
+    fn test_for_break_continue() {
+        let (vm, output) = run_with_input(
+            r#"
+            sum = 0;
+            for (i = 0; i < 10; i = i + 1) {
+                if (i == 2) {
+                    continue;
+                }
+                if (i == 6) {
+                    break;
+                }
+                sum = sum + i;
+            }
+
+            n = 0;
+            for (;;) {
+                n = n + 1;
+                if (n == 3) {
+                    break;
+                }
+            }
+
+            j = 0;
+            while (j < 5) {
+                j = j + 1;
+                if (j / 2 * 2 == j) {
+                    continue;
+                }
+                for (k = 0; k < 3; k = k + 1) {
+                    if (k == 1) {
+                        break;
+                    }
+                    print(j);
+                }
+            }
+        "#,
+            &[],
+        );
+
+        assert_eq!(vm.heap["sum"], Value::UInt(13));
+        assert_eq!(vm.heap["i"], Value::UInt(6));
+        assert_eq!(vm.heap["n"], Value::UInt(3));
+        assert_eq!(output, vec!["1", "3", "5"]);
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_logic_operators() {
+        let (vm, output) = run_with_input(
+            r#"
+            func loud(name, value) {
+                print(name);
+                return value;
+            }
+            a = loud("a", false) && loud("b", true);
+            b = loud("c", true) || loud("d", true);
+            c = loud("e", true) && loud("f", false);
+            d = loud("g", false) || loud("h", true);
+            e = !false && !!true;
+            f = true ^ true || false ^ true;
+            g = !(1 < 2) || 3 == 3 && 4 != 4;
+        "#,
+            &[],
+        );
+
+        // the right operand only runs when the left one does not decide
+        assert_eq!(output, vec!["a", "c", "e", "f", "g", "h"]);
+        assert_eq!(vm.heap["a"], Value::Bool(false));
+        assert_eq!(vm.heap["b"], Value::Bool(true));
+        assert_eq!(vm.heap["c"], Value::Bool(false));
+        assert_eq!(vm.heap["d"], Value::Bool(true));
+        assert_eq!(vm.heap["e"], Value::Bool(true));
+        assert_eq!(vm.heap["f"], Value::Bool(true));
+        assert_eq!(vm.heap["g"], Value::Bool(false));
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_relational_operators() {
+        let vm = run("a = 3 >= 3;\nb = 2 <= 1;\nc = 1 < 2;\nd = 1 > 2;");
+
+        assert_eq!(vm.heap["a"], Value::Bool(true));
+        assert_eq!(vm.heap["b"], Value::Bool(false));
+        assert_eq!(vm.heap["c"], Value::Bool(true));
+        assert_eq!(vm.heap["d"], Value::Bool(false));
+    }
+
+    #[test]
+    fn test_modulo() {
+        let vm = run(r#"
+            a = 17 % 5;
+            b = 2 + 10 % 4 * 3;
+            evens = 0;
+            for (i = 0; i < 10; i = i + 1) {
+                if (i % 2 == 0) {
+                    evens = evens + 1;
+                }
+            }
+        "#);
+
+        assert_eq!(vm.heap["a"], Value::UInt(2));
+        assert_eq!(vm.heap["b"], Value::UInt(8));
+        assert_eq!(vm.heap["evens"], Value::UInt(5));
+    }
+
+    #[quickcheck]
+    fn modulo_matches_rust(a: u32, b: u32) -> bool {
+        let code = format!("x = {a} % {b};");
+        let program_pair = OxydeParser::parse(Rule::program, &code)
+            .unwrap()
+            .next()
+            .unwrap();
+        let bytecode =
+            ir_to_bytecode(compile_to_ir(parse_program(program_pair).unwrap()).unwrap()).unwrap();
+        let mut vm = VM::new(bytecode);
+
+        match (vm.run(), b) {
+            (Err(error), 0) => error.kind == VMErrorKind::DivisionByZero,
+            (Ok(_), _) => vm.heap["x"] == Value::UInt(a % b),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_signed_and_float_arithmetic() {
+        let (vm, output) = run_with_input(
+            r#"
+            a = 1 - 2;
+            b = -a * 10;
+            c = 7 / 2.0;
+            d = -(3 + 4);
+            e = 2.5e1 + 1;
+            f = 1 == 1.0;
+            g = -0.5 < 0;
+            h = 5000000000;
+            print(a, b, c, d, e);
+        "#,
+            &[],
+        );
+
+        assert_eq!(vm.heap["a"], Value::Int(-1));
+        assert_eq!(vm.heap["b"], Value::Int(10));
+        assert_eq!(vm.heap["c"], Value::Float(3.5));
+        assert_eq!(vm.heap["d"], Value::Int(-7));
+        assert_eq!(vm.heap["e"], Value::Float(26.0));
+        assert_eq!(vm.heap["f"], Value::Bool(true));
+        assert_eq!(vm.heap["g"], Value::Bool(true));
+        assert_eq!(vm.heap["h"], Value::Int(5_000_000_000));
+        assert_eq!(output, vec!["-1", "10", "3.5", "-7", "26.0"]);
+    }
+
+    #[test]
+    fn test_lists() {
+        let (vm, output) = run_with_input(
+            r#"
+            xs = [1, 2, 3];
+            xs[0] = xs[1] * 10;
+            push(xs, 4);
+            last = pop(xs);
+            n = len(xs);
+            grid = [[1, 2], [3, 4]];
+            corner = grid[1][1];
+            total = 0;
+            for (i = 0; i < len(xs); i = i + 1) {
+                total = total + xs[i];
+            }
+            print(xs, len("abc"), []);
+        "#,
+            &[],
+        );
+
+        assert_eq!(
+            vm.heap["xs"],
+            Value::List(vec![Value::UInt(20), Value::UInt(2), Value::UInt(3)])
+        );
+        assert_eq!(vm.heap["last"], Value::UInt(4));
+        assert_eq!(vm.heap["n"], Value::UInt(3));
+        assert_eq!(vm.heap["corner"], Value::UInt(4));
+        assert_eq!(vm.heap["total"], Value::UInt(25));
+        assert_eq!(output, vec!["[20, 2, 3]", "3", "[]"]);
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_maps() {
+        let (vm, output) = run_with_input(
+            r#"
+            point = {"x": 1, "y": 2};
+            point["z"] = point["x"] + point["y"];
+            point["x"] = 10;
+            squares = {};
+            for (i = 3; i > 0; i = i - 1) {
+                squares[i] = i * i;
+            }
+            has_y = contains(point, "y");
+            has_w = contains(point, "w");
+            print(point, keys(squares), len(squares), squares[2]);
+        "#,
+            &[],
+        );
+
+        assert_eq!(vm.heap["has_y"], Value::Bool(true));
+        assert_eq!(vm.heap["has_w"], Value::Bool(false));
+        assert_eq!(
+            output,
+            vec![r#"{"x": 10, "y": 2, "z": 3}"#, "[1, 2, 3]", "3", "4"]
+        );
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_list_index_out_of_bounds() {
+        let code = "xs = [1, 2];\nx = xs[2];";
+        let program_pair = OxydeParser::parse(Rule::program, code)
+            .unwrap()
+            .next()
+            .unwrap();
+        let bytecode =
+            ir_to_bytecode(compile_to_ir(parse_program(program_pair).unwrap()).unwrap()).unwrap();
+        let mut vm = VM::new(bytecode);
+
+        let error = vm.run().unwrap_err();
+        assert_eq!(
+            error.kind,
+            VMErrorKind::IndexOutOfBounds { index: 2, len: 2 }
+        );
+    }
+
+    #[test]
+    fn test_print_and_scan() {
+        let (_, output) = run_with_input(
+            r#"
+            name = scan();
+            print("hello " + name);
+            i = 0;
+            while (i < 3) {
+                print(i);
+                i = i + 1;
+            }
+            print(true, null);
+        "#,
+            &["oxyde"],
+        );
+
+        assert_eq!(output, vec!["hello oxyde", "0", "1", "2", "true", "null"]);
+    }
+}
@@ -2,10 +2,13 @@
 mod tests {
     use crate::ir::IrInstruction;
     use crate::ir_compiler::*;
+    use crate::ir_text;
     use crate::parser::*;
+    use crate::type_checker::Type;
     use crate::types::*;
     use pest::Parser;
-    use vmo2_types::opcode::Opcode;
+    use std::path::Path;
+    use vmo2_types::opcode::{ArithmeticOpcode, ComparisonOpcode, FlowOpcode, LogicOpcode, Opcode};
     use vmo2_types::{opcode::MemoryOpcode, value::Value};
 
     #[test]
@@ -17,29 +20,31 @@ mod tests {
             .next()
             .unwrap();
 
-        let program = parse_program(program_pair);
+        let program = parse_program(program_pair).unwrap();
 
-        let ir = compile_to_ir(program);
-        let bytecode = ir_to_bytecode(ir.clone());
+        let ir = compile_to_ir(program).unwrap();
+        let bytecode = ir_to_bytecode(ir.clone()).unwrap();
 
         assert_eq!(ir.functions.len(), 1);
         assert_eq!(ir.functions["main"].blocks.len(), 1);
-        assert_eq!(ir.functions["main"].blocks[0].instructions.len(), 2);
+        assert_eq!(ir.functions["main"].blocks[0].instructions.len(), 3);
         assert_eq!(
             ir.functions["main"].blocks[0].instructions,
             vec![
                 IrInstruction::Push(Value::UInt(1)),
-                IrInstruction::Store("x".to_string())
+                IrInstruction::Store("x".to_string()),
+                IrInstruction::Halt,
             ]
         );
 
-        assert_eq!(bytecode.opcodes.len(), 3);
+        assert_eq!(bytecode.opcodes.len(), 4);
         assert_eq!(
             bytecode.opcodes,
             vec![
                 Opcode::Literal(Value::UInt(1)),
                 Opcode::Literal(Value::String("x".to_string())),
                 Opcode::Memory(MemoryOpcode::Store),
+                Opcode::Halt,
             ]
         );
     }
@@ -57,13 +62,13 @@ mod tests {
             .next()
             .unwrap();
 
-        let program = parse_program(program_pair);
-        let ir = compile_to_ir(program);
-        let bytecode = ir_to_bytecode(ir.clone());
+        let program = parse_program(program_pair).unwrap();
+        let ir = compile_to_ir(program).unwrap();
+        let bytecode = ir_to_bytecode(ir.clone()).unwrap();
 
         assert_eq!(ir.functions.len(), 1);
         assert_eq!(ir.functions["main"].blocks.len(), 1);
-        assert_eq!(ir.functions["main"].blocks[0].instructions.len(), 6);
+        assert_eq!(ir.functions["main"].blocks[0].instructions.len(), 7);
         assert_eq!(
             ir.functions["main"].blocks[0].instructions,
             vec![
@@ -73,6 +78,7 @@ mod tests {
                 IrInstruction::Store("y".to_string()),
                 IrInstruction::Load("x".to_string()),
                 IrInstruction::Store("z".to_string()),
+                IrInstruction::Halt,
             ]
         );
 
@@ -89,7 +95,541 @@ mod tests {
                 Opcode::Memory(MemoryOpcode::Load),
                 Opcode::Literal(Value::String("z".to_string())),
                 Opcode::Memory(MemoryOpcode::Store),
+                Opcode::Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_while_jump_targets() {
+        let code = r#"
+            x = 0;
+            while (x < 3) {
+                x = x + 1;
+            }
+        "#;
+
+        let program_pair = OxydeParser::parse(Rule::program, code)
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let program = parse_program(program_pair).unwrap();
+        let ir = compile_to_ir(program).unwrap();
+        let bytecode = ir_to_bytecode(ir.clone()).unwrap();
+
+        assert_eq!(ir.functions["main"].blocks.len(), 4);
+        assert_eq!(
+            bytecode.opcodes,
+            vec![
+                // entry block
+                Opcode::Literal(Value::UInt(0)),
+                Opcode::Literal(Value::String("x".to_string())),
+                Opcode::Memory(MemoryOpcode::Store),
+                Opcode::Flow(FlowOpcode::Jump(4)),
+                // cond block, falls through into the body
+                Opcode::Literal(Value::UInt(3)),
+                Opcode::Literal(Value::String("x".to_string())),
+                Opcode::Memory(MemoryOpcode::Load),
+                Opcode::Comparison(ComparisonOpcode::Lt),
+                Opcode::Flow(FlowOpcode::JumpIfFalse(16)),
+                // body block
+                Opcode::Literal(Value::UInt(1)),
+                Opcode::Literal(Value::String("x".to_string())),
+                Opcode::Memory(MemoryOpcode::Load),
+                Opcode::Arithmetic(ArithmeticOpcode::Add),
+                Opcode::Literal(Value::String("x".to_string())),
+                Opcode::Memory(MemoryOpcode::Store),
+                Opcode::Flow(FlowOpcode::Jump(4)),
+                // after block
+                Opcode::Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_if_else_jump_targets() {
+        let code = r#"
+            if (x) {
+                y = 1;
+            } else {
+                y = 2;
+            }
+        "#;
+
+        let program_pair = OxydeParser::parse(Rule::program, code)
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let program = parse_program(program_pair).unwrap();
+        let ir = compile_to_ir(program).unwrap();
+        let bytecode = ir_to_bytecode(ir.clone()).unwrap();
+
+        assert_eq!(ir.functions["main"].blocks.len(), 4);
+        assert_eq!(
+            ir.functions["main"].blocks[0].instructions,
+            vec![
+                IrInstruction::Load("x".to_string()),
+                IrInstruction::JumpIf(1, 2),
             ]
         );
+        assert_eq!(
+            bytecode.opcodes,
+            vec![
+                // condition, falls through into the then block
+                Opcode::Literal(Value::String("x".to_string())),
+                Opcode::Memory(MemoryOpcode::Load),
+                Opcode::Flow(FlowOpcode::JumpIfFalse(7)),
+                // then block
+                Opcode::Literal(Value::UInt(1)),
+                Opcode::Literal(Value::String("y".to_string())),
+                Opcode::Memory(MemoryOpcode::Store),
+                Opcode::Flow(FlowOpcode::Jump(11)),
+                // else block
+                Opcode::Literal(Value::UInt(2)),
+                Opcode::Literal(Value::String("y".to_string())),
+                Opcode::Memory(MemoryOpcode::Store),
+                Opcode::Flow(FlowOpcode::Jump(11)),
+                // after block
+                Opcode::Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_short_circuit_blocks() {
+        let program_pair = OxydeParser::parse(Rule::program, "x = a && b;")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let program = parse_program(program_pair).unwrap();
+        let ir = compile_to_ir(program).unwrap();
+        let bytecode = ir_to_bytecode(ir.clone()).unwrap();
+
+        let blocks = &ir.functions["main"].blocks;
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(
+            blocks[0].instructions,
+            vec![
+                IrInstruction::Load("a".to_string()),
+                IrInstruction::Dup,
+                IrInstruction::JumpIf(1, 2),
+            ]
+        );
+        assert_eq!(
+            blocks[1].instructions,
+            vec![
+                IrInstruction::Load("b".to_string()),
+                IrInstruction::And,
+                IrInstruction::Jump(2),
+            ]
+        );
+        assert_eq!(
+            bytecode.opcodes,
+            vec![
+                Opcode::Literal(Value::String("a".to_string())),
+                Opcode::Memory(MemoryOpcode::Load),
+                Opcode::Dup,
+                Opcode::Flow(FlowOpcode::JumpIfFalse(8)),
+                Opcode::Literal(Value::String("b".to_string())),
+                Opcode::Memory(MemoryOpcode::Load),
+                Opcode::Logic(LogicOpcode::And),
+                Opcode::Flow(FlowOpcode::Jump(8)),
+                Opcode::Literal(Value::String("x".to_string())),
+                Opcode::Memory(MemoryOpcode::Store),
+                Opcode::Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_function_layout() {
+        let program = AstProgram {
+            statements: vec![
+                AstStatement::FunctionDefinition(
+                    "b".to_owned(),
+                    vec![],
+                    vec![AstStatement::Assignment(
+                        "x".to_owned(),
+                        AstExpression::Literal(AstLiteral::UInt(2)),
+                        Span::default(),
+                    )],
+                    Span::default(),
+                ),
+                AstStatement::FunctionDefinition(
+                    "a".to_owned(),
+                    vec![],
+                    vec![AstStatement::Expression(
+                        AstExpression::FunctionCall("b".to_owned(), vec![], Span::default()),
+                        Span::default(),
+                    )],
+                    Span::default(),
+                ),
+                AstStatement::Expression(
+                    AstExpression::FunctionCall("a".to_owned(), vec![], Span::default()),
+                    Span::default(),
+                ),
+            ],
+        };
+
+        let ir = compile_to_ir(program).unwrap();
+        let bytecode = ir_to_bytecode(ir.clone()).unwrap();
+
+        assert_eq!(ir.functions.len(), 3);
+        assert_eq!(
+            ir.functions["main"].blocks[0].instructions,
+            vec![
+                IrInstruction::Call("a".to_owned()),
+                IrInstruction::Pop,
+                IrInstruction::Halt
+            ]
+        );
+
+        // main, then `a` and `b` in name order
+        assert_eq!(
+            bytecode.opcodes,
+            vec![
+                Opcode::Flow(FlowOpcode::Call(3)),
+                Opcode::Pop,
+                Opcode::Halt,
+                Opcode::Flow(FlowOpcode::Call(7)),
+                Opcode::Pop,
+                Opcode::Literal(Value::Null),
+                Opcode::Flow(FlowOpcode::Return),
+                Opcode::Literal(Value::UInt(2)),
+                Opcode::Literal(Value::String("x".to_string())),
+                Opcode::Memory(MemoryOpcode::Store),
+                Opcode::Literal(Value::Null),
+                Opcode::Flow(FlowOpcode::Return),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameters_and_return() {
+        let code = r#"
+            func add(a, b) {
+                return a + b;
+            }
+            x = add(1, 2);
+        "#;
+
+        let program_pair = OxydeParser::parse(Rule::program, code)
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let program = parse_program(program_pair).unwrap();
+        let ir = compile_to_ir(program).unwrap();
+
+        assert_eq!(ir.functions["add"].parameters, vec!["a", "b"]);
+        assert_eq!(
+            ir.functions["add"].blocks[0].instructions,
+            vec![
+                // the arguments are bound last to first
+                IrInstruction::Store("b".to_owned()),
+                IrInstruction::Store("a".to_owned()),
+                IrInstruction::Load("b".to_owned()),
+                IrInstruction::Load("a".to_owned()),
+                IrInstruction::Add,
+                IrInstruction::Return,
+                IrInstruction::Push(Value::Null),
+                IrInstruction::Return,
+            ]
+        );
+        assert_eq!(
+            ir.functions["main"].blocks[0].instructions,
+            vec![
+                IrInstruction::Push(Value::UInt(1)),
+                IrInstruction::Push(Value::UInt(2)),
+                IrInstruction::Call("add".to_owned()),
+                IrInstruction::Store("x".to_owned()),
+                IrInstruction::Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_source_map() {
+        let code = "x = 0;\nwhile (x < 3) {\n    x = x + 1;\n}\n";
+
+        let program_pair = OxydeParser::parse(Rule::program, code)
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let program = parse_program(program_pair).unwrap();
+        let (bytecode, source_map) =
+            ir_to_bytecode_with_source_map(compile_to_ir(program).unwrap()).unwrap();
+
+        assert_eq!(source_map.locations.len(), bytecode.opcodes.len());
+        let lines: Vec<Option<usize>> = source_map
+            .locations
+            .iter()
+            .map(|location| location.map(|l| l.line))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                vec![Some(1); 3],
+                vec![Some(2); 6],
+                vec![Some(3); 6],
+                vec![Some(2)],
+                vec![None],
+            ]
+            .concat()
+        );
+        assert_eq!(source_map.line_starts(2), vec![3, 15]);
+        assert_eq!(source_map.location(9).unwrap().column, 5);
+    }
+
+    #[test]
+    fn test_break_continue_outside_loop() {
+        use crate::error::CompileErrorKind;
+
+        let compile = |code| {
+            let program_pair = OxydeParser::parse(Rule::program, code)
+                .unwrap()
+                .next()
+                .unwrap();
+            compile_to_ir(parse_program(program_pair).unwrap())
+        };
+
+        let error = compile("x = 1;\nbreak;").unwrap_err();
+        assert_eq!(error.kind, CompileErrorKind::BreakOutsideLoop);
+        assert_eq!((error.span.line, error.span.column), (2, 1));
+
+        let error = compile("if (x) {\n    continue;\n}").unwrap_err();
+        assert_eq!(error.kind, CompileErrorKind::ContinueOutsideLoop);
+        assert_eq!(
+            error.to_string(),
+            "`continue` outside of a loop at line 2, column 5"
+        );
+
+        // a function body does not see the loop around its definition
+        let error = compile("while (x) {\n    func f() {\n        break;\n    }\n}").unwrap_err();
+        assert_eq!(error.kind, CompileErrorKind::BreakOutsideLoop);
+
+        assert!(compile("for (;;) { while (x) { continue; } break; }").is_ok());
+    }
+
+    #[test]
+    fn test_builtin_errors() {
+        use crate::error::CompileErrorKind;
+
+        let compile = |code| {
+            let program_pair = OxydeParser::parse(Rule::program, code)
+                .unwrap()
+                .next()
+                .unwrap();
+            compile_to_ir(parse_program(program_pair).unwrap())
+        };
+
+        let error = compile("x = len(a, b);").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::ArgumentCount {
+                name: "len".to_owned(),
+                expected: 1,
+                found: 2,
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "`len` expects 1 argument(s), got 2 at line 1, column 5"
+        );
+
+        let error = compile("x = scan(1, 2);").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::ArgumentCount {
+                name: "scan".to_owned(),
+                expected: 0,
+                found: 2,
+            }
+        );
+
+        let error = compile(
+            "x = 1;
+push([1], 2);",
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::ExpectedVariable("push".to_owned())
+        );
+        assert_eq!(error.span.line, 2);
+    }
+
+    #[test]
+    fn test_call_errors() {
+        use crate::error::CompileErrorKind;
+
+        // lowering checks calls on its own, without the resolver
+        let error = compile_to_ir(parse_source("x = 1;\nx = foo(1);").unwrap()).unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::UndefinedFunction("foo".to_owned())
+        );
+        assert_eq!(error.span.line, 2);
+
+        let error =
+            compile_to_ir(parse_source("func f(a) { return a; }\nf();").unwrap()).unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::ArgumentCount {
+                name: "f".to_owned(),
+                expected: 1,
+                found: 0,
+            }
+        );
+
+        // and linking reports IR that calls an unknown function
+        let ir = ir_text::parse("func main() {\nbb0:\n    call f\n    halt\n}").unwrap();
+        assert_eq!(
+            ir_to_bytecode(ir).unwrap_err().kind,
+            CompileErrorKind::UndefinedFunction("f".to_owned())
+        );
+    }
+
+    /// IR of `code`, type annotated when it type checks.
+    fn compile_source(code: &str) -> crate::ir::IrProgram {
+        let program = parse_source(code).unwrap();
+        match crate::type_checker::check(&program) {
+            Ok(types) => compile_to_typed_ir(program, &types).unwrap(),
+            Err(_) => compile_to_ir(program).unwrap(),
+        }
+    }
+
+    /*
+     *  Every `tests/ir/<name>.oxy` is compiled and its printed IR compared
+     *  with `tests/ir/<name>.ir`. Run with `UPDATE_IR_SNAPSHOTS=1` to write
+     *  the snapshots instead. Each snapshot must also parse back into the
+     *  same IR.
+     */
+    #[test]
+    fn test_ir_snapshots() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests/ir");
+        let update = std::env::var_os("UPDATE_IR_SNAPSHOTS").is_some();
+
+        let mut sources: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "oxy"))
+            .collect();
+        sources.sort();
+        assert!(!sources.is_empty());
+
+        for source in sources {
+            let ir = compile_source(&std::fs::read_to_string(&source).unwrap());
+            let printed = ir_text::print(&ir);
+            let snapshot = source.with_extension("ir");
+            if update {
+                std::fs::write(&snapshot, &printed).unwrap();
+            }
+
+            let expected = std::fs::read_to_string(&snapshot).unwrap();
+            assert_eq!(printed, expected, "{} is out of date", snapshot.display());
+
+            let parsed = ir_text::parse(&expected).unwrap();
+            assert_eq!(ir_text::print(&parsed), expected);
+            assert_eq!(ir_to_bytecode(parsed).unwrap(), ir_to_bytecode(ir).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_parse_ir_text() {
+        let ir = ir_text::parse(
+            r#"
+            ; comments and blank lines are skipped
+            func f(a, b_2) entry bb1 {
+            bb0:
+                push "x; y" ; not part of the literal
+                store a
+            bb1:
+                load a : uint | string
+                jump_if bb0, bb1
+                build_map 0
+                call f
+                add : any
+                return
+            }
+            "#,
+        )
+        .unwrap();
+
+        let function = &ir.functions["f"];
+        assert_eq!(function.parameters, vec!["a", "b_2"]);
+        assert_eq!(function.entry_block, 1);
+        assert_eq!(
+            function.blocks[0].instructions,
+            vec![
+                IrInstruction::Push(Value::String("x; y".to_owned())),
+                IrInstruction::Store("a".to_owned()),
+            ]
+        );
+        assert_eq!(
+            function.blocks[1].instructions,
+            vec![
+                IrInstruction::Load("a".to_owned()),
+                IrInstruction::JumpIf(0, 1),
+                IrInstruction::BuildMap(0),
+                IrInstruction::Call("f".to_owned()),
+                IrInstruction::Add,
+                IrInstruction::Return,
+            ]
+        );
+        assert_eq!(
+            function.blocks[1].types,
+            vec![
+                Some(Type::UINT.union(Type::STRING)),
+                None,
+                None,
+                None,
+                Some(Type::ANY),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_ir_text_errors() {
+        let error = |text| ir_text::parse(text).unwrap_err().to_string();
+
+        assert_eq!(error("bb0:"), "line 1: invalid function header `bb0:`");
+        assert_eq!(
+            error("func main() {\n    halt\n}"),
+            "line 2: instruction outside of a block"
+        );
+        assert_eq!(
+            error("func main() {\nbb1:\n}"),
+            "line 2: expected block bb0, found bb1"
+        );
+        assert_eq!(
+            error("func main() {\nbb0:\n    frobnicate\n}"),
+            "line 3: unknown instruction `frobnicate`"
+        );
+        assert_eq!(
+            error("func main() {\nbb0:\n    jump_if bb1\n}"),
+            "line 3: `jump_if` expects two blocks"
+        );
+        assert_eq!(
+            error("func main() {\nbb0:\n    add : number\n}"),
+            "line 3: invalid type `number`"
+        );
+        assert_eq!(
+            error("func main() {\nbb0:\n    push\n}"),
+            "line 3: `push` expects an operand"
+        );
+        assert_eq!(
+            error("func main() entry bb1 {\nbb0:\n}"),
+            "line 3: function `main` has no block bb1"
+        );
+        assert_eq!(
+            error("func main() {\nbb0:\n    halt"),
+            "line 3: function `main` is not closed"
+        );
     }
 }
@@ -1,2 +1,9 @@
+mod diagnostic_test;
+mod e2e_test;
 mod ir_test;
+mod optimizer_test;
 mod parser_test;
+mod peephole_test;
+mod resolver_test;
+mod type_checker_test;
+mod verifier_test;
@@ -0,0 +1,311 @@
+#[cfg(test)]
+mod tests {
+    use crate::ir::{IrInstruction, IrProgram};
+    use crate::ir_compiler::*;
+    use crate::optimizer::*;
+    use crate::parser::parse_source;
+    use crate::type_checker::check;
+    use quickcheck::{Arbitrary, Gen};
+    use quickcheck_macros::quickcheck;
+    use vmo2_types::value::Value;
+    use vmo2_vm::io::BufferIo;
+    use vmo2_vm::vm::{VM, VMErrorKind};
+
+    /// IR of `code`, type annotated when it type checks.
+    fn compile(code: &str) -> IrProgram {
+        let program = parse_source(code).unwrap();
+        match check(&program) {
+            Ok(types) => compile_to_typed_ir(program, &types).unwrap(),
+            Err(_) => compile_to_ir(program).unwrap(),
+        }
+    }
+
+    fn optimized(code: &str) -> Vec<IrInstruction> {
+        optimized_at(code, 1)
+    }
+
+    /// Instructions of the first block of `main` once optimized at `level`.
+    fn optimized_at(code: &str, level: u8) -> Vec<IrInstruction> {
+        let mut ir = compile(code);
+        optimize(&mut ir, level);
+        let main = &ir.functions["main"];
+        assert_eq!(
+            main.blocks[0].instructions.len(),
+            main.blocks[0].spans.len()
+        );
+        assert_eq!(
+            main.blocks[0].instructions.len(),
+            main.blocks[0].types.len()
+        );
+        main.blocks[0].instructions.clone()
+    }
+
+    fn run(ir: IrProgram) -> (Vec<String>, Option<VMErrorKind>) {
+        let io = BufferIo::new();
+        let mut vm = VM::new(ir_to_bytecode(ir).unwrap()).with_io(io.clone());
+        let error = vm.run().err().map(|error| error.kind);
+        (io.output(), error)
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        use IrInstruction::*;
+
+        assert_eq!(
+            optimized("x = 2 * 3 + 1;"),
+            vec![Push(Value::UInt(7)), Store("x".to_owned()), Halt]
+        );
+        assert_eq!(
+            optimized("x = \"a\" + \"b\" * 2;"),
+            vec![
+                Push(Value::String("abb".to_owned())),
+                Store("x".to_owned()),
+                Halt
+            ]
+        );
+        assert_eq!(
+            optimized("x = !(1 < 2.5) ^ (3 == 3.0);"),
+            vec![Push(Value::Bool(true)), Store("x".to_owned()), Halt]
+        );
+        assert_eq!(
+            optimized("x = -(1 - 3);"),
+            vec![Push(Value::Int(2)), Store("x".to_owned()), Halt]
+        );
+        // the statement's value is discarded
+        assert_eq!(optimized("1 + 2;"), vec![Halt]);
+    }
+
+    #[test]
+    fn test_failing_operations_are_kept() {
+        use IrInstruction::*;
+
+        assert_eq!(
+            optimized("x = 1 / 0;"),
+            vec![
+                Push(Value::UInt(0)),
+                Push(Value::UInt(1)),
+                Div,
+                Store("x".to_owned()),
+                Halt
+            ]
+        );
+        assert!(optimized("x = true + 1;").contains(&Add));
+        // too large to be worth storing
+        assert!(optimized("x = \"ab\" * 10000;").contains(&Mul));
+    }
+
+    #[test]
+    fn test_fold_jumps() {
+        let mut ir = compile("if (1 > 2) { print(1); } else { print(2); }");
+        optimize(&mut ir, 1);
+        let entry = &ir.functions["main"].blocks[0].instructions;
+        assert!(matches!(entry[..], [IrInstruction::Jump(_)]), "{entry:?}");
+    }
+
+    #[test]
+    fn test_identities() {
+        use IrInstruction::*;
+
+        let x = || Load("x".to_owned());
+        let y = || Store("y".to_owned());
+        let program = |expression: &str| format!("x = 1 - 2;\ny = {expression};");
+        for expression in [
+            "x + 0",
+            "0 + x",
+            "x * 1",
+            "1 * x",
+            "x - 0",
+            "x / 1",
+            "(x + 0) * 1",
+        ] {
+            assert_eq!(
+                optimized(&program(expression))[2..],
+                [x(), y(), Halt],
+                "{expression}"
+            );
+        }
+
+        // `0 - x` is not `x`, an int zero would change the type of a uint
+        assert!(optimized(&program("0 - x")).contains(&Sub));
+        assert!(optimized("x = 3;\ny = x + 0 - 0;").ends_with(&[x(), y(), Halt]));
+        assert!(optimized("x = 3;\ny = x + (0 - 1 + 1);").contains(&Add));
+
+        // only for integers
+        assert!(optimized("x = 1.5;\ny = x + 0;").contains(&Add));
+        assert!(optimized("x = \"a\";\ny = x * 1;").contains(&Mul));
+        assert!(optimized("x = scan();\ny = x + 0;").contains(&Add));
+        // nor without types
+        let mut ir = compile_to_ir(parse_source("x = 3;\ny = x + 0;").unwrap()).unwrap();
+        optimize(&mut ir, 1);
+        assert!(ir.functions["main"].blocks[0].instructions.contains(&Add));
+    }
+
+    #[test]
+    fn test_level_zero() {
+        let mut ir = compile("x = 1 + 2;");
+        optimize(&mut ir, 0);
+        assert!(
+            ir.functions["main"].blocks[0]
+                .instructions
+                .contains(&IrInstruction::Add)
+        );
+    }
+
+    #[test]
+    fn test_output_unchanged() {
+        let code = r#"
+            func fib(n) {
+                if (n < 2 * 1) { return n + 0; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            total = 0;
+            for (i = 0; i < 10 + 0; i = i + 1) {
+                total = total * 1 + fib(i);
+                if (true && i % 2 == 0) { print("even " + "i", i); }
+            }
+            print(total, 2 * 3.5, -(2 - 5), "ab" * 3, [1 + 1, 2 * 2], {"k": 1 > 0});
+        "#;
+        let reference = run(compile_to_ir(parse_source(code).unwrap()).unwrap());
+        for level in [1, 2] {
+            let mut ir = compile(code);
+            optimize(&mut ir, level);
+            assert_eq!(run(ir), reference, "level {level}");
+        }
+        assert_eq!(reference.1, None);
+    }
+
+    #[test]
+    fn test_cfg_links() {
+        let mut ir = compile("i = 0;\nwhile (i < 3) {\n    i = i + 1;\n}\nprint(i);");
+        let main = ir.functions.get_mut("main").unwrap();
+        crate::cfg::link(main);
+
+        // entry -> cond -> body | after, body -> cond
+        let links: Vec<_> = main.blocks.iter().map(|b| (b.next, b.branch)).collect();
+        assert_eq!(
+            links,
+            vec![
+                (Some(1), None),
+                (Some(2), Some(3)),
+                (Some(1), None),
+                (None, None)
+            ]
+        );
+        assert_eq!(crate::cfg::reachable(main), vec![true; 4]);
+    }
+
+    #[test]
+    fn test_remove_unreachable_code() {
+        use IrInstruction::*;
+
+        let mut ir = compile(
+            "func f() {\n    return 1;\n    print(2);\n}\nwhile (false) {\n    print(3);\n}\nprint(f());",
+        );
+        optimize(&mut ir, 2);
+
+        let main = &ir.functions["main"];
+        let instructions: Vec<_> = main.blocks.iter().flat_map(|b| &b.instructions).collect();
+        assert!(!instructions.contains(&&Push(Value::UInt(3))));
+        assert!(matches!(instructions.last(), Some(Halt)));
+        for block in &main.blocks {
+            for target in block.next.into_iter().chain(block.branch) {
+                assert!(target < main.blocks.len());
+            }
+        }
+        assert_eq!(
+            ir.functions["f"].blocks[0].instructions,
+            vec![Push(Value::UInt(1)), Return]
+        );
+    }
+
+    #[test]
+    fn test_remove_unused_functions() {
+        let mut ir = compile(
+            "func a() { return b(); }\nfunc b() { return 1; }\nfunc c() { return d(); }\nfunc d() { return c(); }\nprint(a());",
+        );
+        optimize(&mut ir, 2);
+
+        let mut names: Vec<_> = ir.functions.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["a", "b", "main"]);
+
+        // level 1 keeps them
+        let mut ir = compile("func unused() { return 1; }");
+        optimize(&mut ir, 1);
+        assert!(ir.functions.contains_key("unused"));
+    }
+
+    #[test]
+    fn test_remove_dead_stores() {
+        use IrInstruction::*;
+
+        assert_eq!(
+            optimized_at("x = 1;\ny = 2;\nprint(y);", 2),
+            vec![
+                Push(Value::UInt(2)),
+                Store("y".to_owned()),
+                Load("y".to_owned()),
+                Print,
+                Halt
+            ]
+        );
+        // the value is still computed
+        assert_eq!(optimized_at("x = scan();", 2), vec![Scan, Pop, Halt]);
+        // a global loaded by a function is live
+        assert!(
+            optimized_at("func f() { return g; }\ng = 1;\nprint(f());", 2)
+                .contains(&Store("g".to_owned()))
+        );
+    }
+
+    /// Source of a random expression over constants and two integer
+    /// variables, `x` and `y`.
+    #[derive(Debug, Clone)]
+    struct Expression(String);
+
+    impl Expression {
+        fn generate(g: &mut Gen, depth: u32) -> String {
+            const LEAVES: [&str; 12] = [
+                "0", "1", "2", "7", "-3", "2.5", "\"ab\"", "true", "false", "null", "x", "y",
+            ];
+            const BINARY: [&str; 14] = [
+                "+", "-", "*", "/", "%", "==", "!=", "<", "<=", ">", ">=", "&&", "||", "^",
+            ];
+            match u8::arbitrary(g) % 4 {
+                _ if depth == 0 => g.choose(&LEAVES).unwrap().to_string(),
+                0 => g.choose(&LEAVES).unwrap().to_string(),
+                1 => format!(
+                    "{}({})",
+                    g.choose(&["-", "!"]).unwrap(),
+                    Self::generate(g, depth - 1)
+                ),
+                _ => format!(
+                    "({} {} {})",
+                    Self::generate(g, depth - 1),
+                    g.choose(&BINARY).unwrap(),
+                    Self::generate(g, depth - 1)
+                ),
+            }
+        }
+    }
+
+    impl Arbitrary for Expression {
+        fn arbitrary(g: &mut Gen) -> Self {
+            Expression(Self::generate(g, 4))
+        }
+    }
+
+    #[quickcheck]
+    fn folding_keeps_output(expression: Expression) -> bool {
+        let code = format!(
+            "x = 3;\ny = 0 - 2;\nprint({0});\nif ({0}) {{ print(1); }} else {{ print(2); }}",
+            expression.0
+        );
+        let reference = run(compile_to_ir(parse_source(&code).unwrap()).unwrap());
+        [1, 2].into_iter().all(|level| {
+            let mut ir = compile(&code);
+            optimize(&mut ir, level);
+            run(ir) == reference
+        })
+    }
+}
@@ -4,41 +4,129 @@ mod tests {
     use crate::types::*;
     use pest::Parser;
 
+    /*
+     *  Parsed statements and expressions with every span reset to
+     *  `Span::default()`, to compare them with hand-built trees. Spans are
+     *  checked separately.
+     */
+    fn without_span(statement: AstStatement) -> AstStatement {
+        let span = Span::default();
+        let expr = without_expr_span;
+        match statement {
+            AstStatement::Assignment(name, value, _) => {
+                AstStatement::Assignment(name, expr(value), span)
+            }
+            AstStatement::IndexAssignment(name, index, value, _) => {
+                AstStatement::IndexAssignment(name, expr(index), expr(value), span)
+            }
+            AstStatement::FunctionDefinition(name, parameters, body, _) => {
+                AstStatement::FunctionDefinition(name, parameters, without_spans(body), span)
+            }
+            AstStatement::While(condition, body, _) => {
+                AstStatement::While(expr(condition), without_spans(body), span)
+            }
+            AstStatement::For(init, condition, step, body, _) => AstStatement::For(
+                init.map(|init| Box::new(without_span(*init))),
+                condition.map(expr),
+                step.map(|step| Box::new(without_span(*step))),
+                without_spans(body),
+                span,
+            ),
+            AstStatement::Break(_) => AstStatement::Break(span),
+            AstStatement::Continue(_) => AstStatement::Continue(span),
+            AstStatement::If(condition, then_body, else_body, _) => AstStatement::If(
+                expr(condition),
+                without_spans(then_body),
+                else_body.map(without_spans),
+                span,
+            ),
+            AstStatement::Expression(value, _) => AstStatement::Expression(expr(value), span),
+            AstStatement::Return(value, _) => AstStatement::Return(value.map(expr), span),
+        }
+    }
+
+    fn without_expr_span(expr: AstExpression) -> AstExpression {
+        let span = Span::default();
+        let boxed = |expr: Box<AstExpression>| Box::new(without_expr_span(*expr));
+        let all = |exprs: Vec<AstExpression>| exprs.into_iter().map(without_expr_span).collect();
+        match expr {
+            AstExpression::BinaryOperation(operator, left, right, _) => {
+                AstExpression::BinaryOperation(operator, boxed(left), boxed(right), span)
+            }
+            AstExpression::FunctionCall(name, args, _) => {
+                AstExpression::FunctionCall(name, all(args), span)
+            }
+            AstExpression::UnaryOperation(operator, operand, _) => {
+                AstExpression::UnaryOperation(operator, boxed(operand), span)
+            }
+            AstExpression::List(items) => AstExpression::List(all(items)),
+            AstExpression::Map(entries) => AstExpression::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (without_expr_span(key), without_expr_span(value)))
+                    .collect(),
+            ),
+            AstExpression::Index(collection, index) => {
+                AstExpression::Index(boxed(collection), boxed(index))
+            }
+            AstExpression::Literal(_) | AstExpression::Variable(_) => expr,
+        }
+    }
+
+    fn without_spans(statements: Vec<AstStatement>) -> Vec<AstStatement> {
+        statements.into_iter().map(without_span).collect()
+    }
+
     #[test]
     fn test_parse_literal() {
         let number_pair = OxydeParser::parse(Rule::number, "42")
             .unwrap()
             .next()
             .unwrap();
-        let number_literal = parse_literal(number_pair);
+        let number_literal = parse_literal(number_pair).unwrap();
         assert!(matches!(number_literal, AstLiteral::UInt(42)));
 
+        let number = |code| {
+            parse_literal(
+                OxydeParser::parse(Rule::number, code)
+                    .unwrap()
+                    .next()
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        assert_eq!(number("-42"), AstLiteral::Int(-42));
+        assert_eq!(number("4294967296"), AstLiteral::Int(1 << 32));
+        assert_eq!(number("1.5"), AstLiteral::Float(1.5));
+        assert_eq!(number("-2e3"), AstLiteral::Float(-2000.0));
+        assert_eq!(number("1E-2"), AstLiteral::Float(0.01));
+
         let bool_pair = OxydeParser::parse(Rule::bool, "true")
             .unwrap()
             .next()
             .unwrap();
-        let bool_literal = parse_literal(bool_pair);
+        let bool_literal = parse_literal(bool_pair).unwrap();
         assert!(matches!(bool_literal, AstLiteral::Bool(true)));
 
         let null_pair = OxydeParser::parse(Rule::null, "null")
             .unwrap()
             .next()
             .unwrap();
-        let null_literal = parse_literal(null_pair);
+        let null_literal = parse_literal(null_pair).unwrap();
         assert!(matches!(null_literal, AstLiteral::Null));
 
         let string_pair = OxydeParser::parse(Rule::string, r#""hello""#)
             .unwrap()
             .next()
             .unwrap();
-        let string_literal = parse_literal(string_pair);
+        let string_literal = parse_literal(string_pair).unwrap();
         assert_eq!(string_literal, AstLiteral::String("hello".to_string()));
 
         let string_expr_pair = OxydeParser::parse(Rule::string, r#""hello""#)
             .unwrap()
             .next()
             .unwrap();
-        let string_expr = parse_expression(string_expr_pair);
+        let string_expr = parse_expression(string_expr_pair).unwrap();
         assert_eq!(
             string_expr,
             AstExpression::Literal(AstLiteral::String("hello".to_string()))
@@ -48,7 +136,7 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let inner_expr = parse_literal(inner_pair);
+        let inner_expr = parse_literal(inner_pair).unwrap();
         // todo: check if its possible to parse with single escape character
         assert_eq!(inner_expr, AstLiteral::String("seko\\\"mer".to_string()));
     }
@@ -59,7 +147,7 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let identifier_expr = parse_expression(identifier_pair);
+        let identifier_expr = parse_expression(identifier_pair).unwrap();
         assert!(matches!(identifier_expr, AstExpression::Variable(s) if s == "x"));
 
         /*
@@ -69,10 +157,14 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let assignment_stmt = parse_statement(assignment_pair);
+        let assignment_stmt = parse_statement(assignment_pair).unwrap();
         assert_eq!(
-            assignment_stmt,
-            AstStatement::Assignment("x".to_owned(), AstExpression::Literal(AstLiteral::UInt(42)))
+            without_span(assignment_stmt),
+            AstStatement::Assignment(
+                "x".to_owned(),
+                AstExpression::Literal(AstLiteral::UInt(42)),
+                Span::default()
+            )
         );
 
         /*
@@ -82,16 +174,17 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let function_call_stmt = parse_primary_expression(function_call_pair);
+        let function_call_stmt = parse_primary_expression(function_call_pair).unwrap();
         assert_eq!(
-            function_call_stmt,
+            without_expr_span(function_call_stmt),
             AstExpression::FunctionCall(
                 "myFunc".to_owned(),
                 vec![
                     AstExpression::Literal(AstLiteral::UInt(1)),
                     AstExpression::Literal(AstLiteral::UInt(2)),
                     AstExpression::Variable("x".to_owned()),
-                ]
+                ],
+                Span::default()
             )
         );
     }
@@ -102,9 +195,9 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let assignment_stmt = parse_statement(assignment_pair);
+        let assignment_stmt = parse_statement(assignment_pair).unwrap();
         match assignment_stmt {
-            AstStatement::Assignment(id, expr) => {
+            AstStatement::Assignment(id, expr, _) => {
                 assert_eq!(id, "x");
                 assert!(matches!(expr, AstExpression::Literal(AstLiteral::UInt(42))));
             }
@@ -124,11 +217,11 @@ mod tests {
         .unwrap()
         .next()
         .unwrap();
-        let program = parse_program(program_pair);
+        let program = parse_program(program_pair).unwrap();
         assert_eq!(program.statements.len(), 2);
 
         match &program.statements[0] {
-            AstStatement::Assignment(id, expr) => {
+            AstStatement::Assignment(id, expr, _) => {
                 assert_eq!(id, "x");
                 assert!(matches!(expr, AstExpression::Literal(AstLiteral::UInt(42))));
             }
@@ -136,7 +229,7 @@ mod tests {
         }
 
         match &program.statements[1] {
-            AstStatement::Assignment(id, expr) => {
+            AstStatement::Assignment(id, expr, _) => {
                 assert_eq!(id, "y");
                 assert!(matches!(expr, AstExpression::Literal(AstLiteral::UInt(10))));
             }
@@ -158,22 +251,25 @@ mod tests {
         .next()
         .unwrap();
 
-        let program = parse_program(program_pair);
+        let program = parse_program(program_pair).unwrap();
         assert_eq!(program.statements.len(), 3);
         assert_eq!(
-            program.statements,
+            without_spans(program.statements),
             vec![
                 AstStatement::Assignment(
                     String::from("temp"),
-                    AstExpression::Variable(String::from("a"))
+                    AstExpression::Variable(String::from("a")),
+                    Span::default()
                 ),
                 AstStatement::Assignment(
                     String::from("a"),
-                    AstExpression::Variable(String::from("b"))
+                    AstExpression::Variable(String::from("b")),
+                    Span::default()
                 ),
                 AstStatement::Assignment(
                     String::from("b"),
-                    AstExpression::Variable(String::from("temp"))
+                    AstExpression::Variable(String::from("temp")),
+                    Span::default()
                 ),
             ]
         );
@@ -194,16 +290,18 @@ mod tests {
         .next()
         .unwrap();
 
-        let while_stmt = parse_statement(while_pair);
+        let while_stmt = parse_statement(while_pair).unwrap();
 
         assert_eq!(
-            while_stmt,
+            without_span(while_stmt),
             AstStatement::While(
                 AstExpression::Literal(AstLiteral::Bool(true)),
                 vec![AstStatement::Assignment(
                     "x".to_owned(),
-                    AstExpression::Literal(AstLiteral::UInt(1))
-                )]
+                    AstExpression::Literal(AstLiteral::UInt(1)),
+                    Span::default()
+                )],
+                Span::default()
             )
         );
     }
@@ -215,13 +313,14 @@ mod tests {
             .next()
             .unwrap();
 
-        let equality_expr = parse_expression(program_pair);
+        let equality_expr = parse_expression(program_pair).unwrap();
         assert_eq!(
-            equality_expr,
+            without_expr_span(equality_expr),
             AstExpression::BinaryOperation(
                 "==".to_owned(),
                 Box::new(AstExpression::Variable("x".to_owned())),
-                Box::new(AstExpression::Literal(AstLiteral::UInt(1)))
+                Box::new(AstExpression::Literal(AstLiteral::UInt(1))),
+                Span::default()
             )
         );
     }
@@ -239,12 +338,13 @@ mod tests {
             .next()
             .unwrap();
 
-        let unary_expr = parse_expression(program_pair);
+        let unary_expr = parse_expression(program_pair).unwrap();
         assert_eq!(
-            unary_expr,
+            without_expr_span(unary_expr),
             AstExpression::UnaryOperation(
                 "-".to_owned(),
-                Box::new(AstExpression::Variable("y".to_owned()))
+                Box::new(AstExpression::Variable("y".to_owned())),
+                Span::default()
             )
         );
 
@@ -253,12 +353,13 @@ mod tests {
             .next()
             .unwrap();
 
-        let unary_expr = parse_expression(program_pair);
+        let unary_expr = parse_expression(program_pair).unwrap();
         assert_eq!(
-            unary_expr,
+            without_expr_span(unary_expr),
             AstExpression::UnaryOperation(
                 "+".to_owned(),
-                Box::new(AstExpression::Variable("x".to_owned()))
+                Box::new(AstExpression::Variable("x".to_owned())),
+                Span::default()
             )
         );
 
@@ -267,7 +368,7 @@ mod tests {
             .next()
             .unwrap();
 
-        let unary_expr = parse_unary_expression(primary_expr_pair);
+        let unary_expr = parse_unary_expression(primary_expr_pair).unwrap();
         assert_eq!(unary_expr, AstExpression::Variable("x".to_owned()));
     }
 
@@ -285,24 +386,28 @@ mod tests {
         .next()
         .unwrap();
 
-        let program = parse_program(program_pair);
+        let program = parse_program(program_pair).unwrap();
 
         assert_eq!(
-            program.statements,
+            without_spans(program.statements),
             vec![AstStatement::While(
                 AstExpression::BinaryOperation(
                     ">".to_owned(),
                     Box::new(AstExpression::Variable("x".to_owned())),
-                    Box::new(AstExpression::Literal(AstLiteral::UInt(0)))
+                    Box::new(AstExpression::Literal(AstLiteral::UInt(0))),
+                    Span::default()
                 ),
                 vec![AstStatement::Assignment(
                     "x".to_owned(),
                     AstExpression::BinaryOperation(
                         "-".to_owned(),
                         Box::new(AstExpression::Variable("x".to_owned())),
-                        Box::new(AstExpression::Literal(AstLiteral::UInt(1)))
-                    )
-                )]
+                        Box::new(AstExpression::Literal(AstLiteral::UInt(1))),
+                        Span::default()
+                    ),
+                    Span::default()
+                )],
+                Span::default()
             )]
         );
     }
@@ -324,31 +429,40 @@ mod tests {
         .next()
         .unwrap();
 
-        let program = parse_program(program_pair);
+        let program = parse_program(program_pair).unwrap();
 
         assert_eq!(
-            program.statements,
+            without_spans(program.statements),
             vec![
                 AstStatement::While(
                     AstExpression::BinaryOperation(
                         ">".to_owned(),
                         Box::new(AstExpression::Variable("x".to_owned())),
-                        Box::new(AstExpression::Literal(AstLiteral::UInt(0)))
+                        Box::new(AstExpression::Literal(AstLiteral::UInt(0))),
+                        Span::default()
                     ),
                     vec![AstStatement::Assignment(
                         "x".to_owned(),
                         AstExpression::BinaryOperation(
                             "-".to_owned(),
                             Box::new(AstExpression::Variable("x".to_owned())),
-                            Box::new(AstExpression::Literal(AstLiteral::UInt(1)))
-                        )
-                    )]
+                            Box::new(AstExpression::Literal(AstLiteral::UInt(1))),
+                            Span::default()
+                        ),
+                        Span::default()
+                    )],
+                    Span::default()
                 ),
                 AstStatement::Assignment(
                     "y".to_owned(),
-                    AstExpression::Literal(AstLiteral::UInt(10))
+                    AstExpression::Literal(AstLiteral::UInt(10)),
+                    Span::default()
                 ),
-                AstStatement::Assignment("x".to_owned(), AstExpression::Variable("y".to_owned()))
+                AstStatement::Assignment(
+                    "x".to_owned(),
+                    AstExpression::Variable("y".to_owned()),
+                    Span::default()
+                )
             ]
         );
     }
@@ -360,12 +474,12 @@ mod tests {
             .next()
             .unwrap();
 
-        let program = parse_program(program_pair);
+        let program = parse_program(program_pair).unwrap();
         assert_eq!(program.statements.len(), 1);
 
         match &program.statements[0] {
-            AstStatement::Expression(expr) => match expr {
-                AstExpression::FunctionCall(name, args) => {
+            AstStatement::Expression(expr, _) => match expr {
+                AstExpression::FunctionCall(name, args, _) => {
                     assert_eq!(name, "myFunc");
                     assert_eq!(args.len(), 3);
                     assert_eq!(
@@ -390,12 +504,12 @@ mod tests {
             .next()
             .unwrap();
 
-        let program = parse_program(program_pair);
+        let program = parse_program(program_pair).unwrap();
         assert_eq!(program.statements.len(), 1);
 
         match &program.statements[0] {
-            AstStatement::Expression(expr) => match expr {
-                AstExpression::FunctionCall(name, args) => {
+            AstStatement::Expression(expr, _) => match expr {
+                AstExpression::FunctionCall(name, args, _) => {
                     assert_eq!(name, "myFunc");
                     assert_eq!(args.len(), 0);
                 }
@@ -404,4 +518,324 @@ mod tests {
             _ => panic!("Expected expression statement"),
         }
     }
+
+    #[test]
+    fn test_parse_function_definition() {
+        let code = r#"
+            func add(a, b) {
+                return a + b;
+            }
+            func nothing() {
+                return;
+            }
+        "#;
+        let program_pair = OxydeParser::parse(Rule::program, code)
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let program = parse_program(program_pair).unwrap();
+        assert_eq!(
+            without_spans(program.statements),
+            vec![
+                AstStatement::FunctionDefinition(
+                    "add".to_owned(),
+                    vec!["a".to_owned(), "b".to_owned()],
+                    vec![AstStatement::Return(
+                        Some(AstExpression::BinaryOperation(
+                            "+".to_owned(),
+                            Box::new(AstExpression::Variable("a".to_owned())),
+                            Box::new(AstExpression::Variable("b".to_owned())),
+                            Span::default(),
+                        )),
+                        Span::default(),
+                    )],
+                    Span::default(),
+                ),
+                AstStatement::FunctionDefinition(
+                    "nothing".to_owned(),
+                    vec![],
+                    vec![AstStatement::Return(None, Span::default())],
+                    Span::default(),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_keywords_are_not_identifiers() {
+        assert!(OxydeParser::parse(Rule::program, "return = 1;").is_err());
+        assert!(OxydeParser::parse(Rule::program, "returned = 1;").is_ok());
+    }
+
+    #[test]
+    fn test_parse_if_else_if_else() {
+        let code = r#"
+            if (x) {
+                a = 1;
+            } else if (y) {
+                a = 2;
+            } else {
+                a = 3;
+            }
+            if (z) {}
+        "#;
+        let program_pair = OxydeParser::parse(Rule::program, code)
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let assign = |value| {
+            vec![AstStatement::Assignment(
+                "a".to_owned(),
+                AstExpression::Literal(AstLiteral::UInt(value)),
+                Span::default(),
+            )]
+        };
+
+        let program = parse_program(program_pair).unwrap();
+        assert_eq!(
+            without_spans(program.statements),
+            vec![
+                AstStatement::If(
+                    AstExpression::Variable("x".to_owned()),
+                    assign(1),
+                    Some(vec![AstStatement::If(
+                        AstExpression::Variable("y".to_owned()),
+                        assign(2),
+                        Some(assign(3)),
+                        Span::default(),
+                    )]),
+                    Span::default(),
+                ),
+                AstStatement::If(
+                    AstExpression::Variable("z".to_owned()),
+                    vec![],
+                    None,
+                    Span::default(),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_for_statement() {
+        let code = r#"
+            for (i = 0; i < 3; i = i + 1) {
+                break;
+            }
+            for (;;) {
+                continue;
+            }
+        "#;
+        let program_pair = OxydeParser::parse(Rule::program, code)
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let program = parse_program(program_pair).unwrap();
+        assert_eq!(
+            without_spans(program.statements),
+            vec![
+                AstStatement::For(
+                    Some(Box::new(AstStatement::Assignment(
+                        "i".to_owned(),
+                        AstExpression::Literal(AstLiteral::UInt(0)),
+                        Span::default(),
+                    ))),
+                    Some(AstExpression::BinaryOperation(
+                        "<".to_owned(),
+                        Box::new(AstExpression::Variable("i".to_owned())),
+                        Box::new(AstExpression::Literal(AstLiteral::UInt(3))),
+                        Span::default(),
+                    )),
+                    Some(Box::new(AstStatement::Assignment(
+                        "i".to_owned(),
+                        AstExpression::BinaryOperation(
+                            "+".to_owned(),
+                            Box::new(AstExpression::Variable("i".to_owned())),
+                            Box::new(AstExpression::Literal(AstLiteral::UInt(1))),
+                            Span::default(),
+                        ),
+                        Span::default(),
+                    ))),
+                    vec![AstStatement::Break(Span::default())],
+                    Span::default(),
+                ),
+                AstStatement::For(
+                    None,
+                    None,
+                    None,
+                    vec![AstStatement::Continue(Span::default())],
+                    Span::default(),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_logic_precedence() {
+        let program_pair = OxydeParser::parse(Rule::program, "x = !a || b && c ^ d == e;")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let variable = |name: &str| Box::new(AstExpression::Variable(name.to_owned()));
+        let binary = |op: &str, left, right| {
+            Box::new(AstExpression::BinaryOperation(
+                op.to_owned(),
+                left,
+                right,
+                Span::default(),
+            ))
+        };
+
+        let program = parse_program(program_pair).unwrap();
+        assert_eq!(
+            without_spans(program.statements),
+            vec![AstStatement::Assignment(
+                "x".to_owned(),
+                *binary(
+                    "||",
+                    Box::new(AstExpression::UnaryOperation(
+                        "!".to_owned(),
+                        variable("a"),
+                        Span::default()
+                    )),
+                    binary(
+                        "&&",
+                        variable("b"),
+                        binary(
+                            "^",
+                            variable("c"),
+                            binary("==", variable("d"), variable("e"))
+                        ),
+                    ),
+                ),
+                Span::default(),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_lists_and_indexing() {
+        let program_pair =
+            OxydeParser::parse(Rule::program, "xs = [1, [a]][0][i + 1]; xs[0] = [];")
+                .unwrap()
+                .next()
+                .unwrap();
+
+        let uint = |n| AstExpression::Literal(AstLiteral::UInt(n));
+        let variable = |name: &str| AstExpression::Variable(name.to_owned());
+
+        let program = parse_program(program_pair).unwrap();
+        assert_eq!(
+            without_spans(program.statements),
+            vec![
+                AstStatement::Assignment(
+                    "xs".to_owned(),
+                    AstExpression::Index(
+                        Box::new(AstExpression::Index(
+                            Box::new(AstExpression::List(vec![
+                                uint(1),
+                                AstExpression::List(vec![variable("a")]),
+                            ])),
+                            Box::new(uint(0)),
+                        )),
+                        Box::new(AstExpression::BinaryOperation(
+                            "+".to_owned(),
+                            Box::new(variable("i")),
+                            Box::new(uint(1)),
+                            Span::default(),
+                        )),
+                    ),
+                    Span::default(),
+                ),
+                AstStatement::IndexAssignment(
+                    "xs".to_owned(),
+                    uint(0),
+                    AstExpression::List(vec![]),
+                    Span::default(),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_map_literal() {
+        let program_pair = OxydeParser::parse(Rule::program, r#"m = {"a": 1, 2: {}}; e = {};"#)
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let uint = |n| AstExpression::Literal(AstLiteral::UInt(n));
+
+        let program = parse_program(program_pair).unwrap();
+        assert_eq!(
+            without_spans(program.statements),
+            vec![
+                AstStatement::Assignment(
+                    "m".to_owned(),
+                    AstExpression::Map(vec![
+                        (
+                            AstExpression::Literal(AstLiteral::String("a".to_owned())),
+                            uint(1),
+                        ),
+                        (uint(2), AstExpression::Map(vec![])),
+                    ]),
+                    Span::default(),
+                ),
+                AstStatement::Assignment(
+                    "e".to_owned(),
+                    AstExpression::Map(vec![]),
+                    Span::default(),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_statement_spans() {
+        let program = parse_source("x = 1;\nwhile (x < 3) {\n    x = x + 1;\n}").unwrap();
+        let span = |start, end, line, column| Span {
+            start,
+            end,
+            line,
+            column,
+        };
+
+        assert_eq!(program.statements[0].span(), span(0, 5, 1, 1));
+        let AstStatement::While(_, body, while_span) = &program.statements[1] else {
+            panic!("expected a while loop");
+        };
+        assert_eq!(*while_span, span(7, 39, 2, 1));
+        assert_eq!(body[0].span(), span(27, 36, 3, 5));
+        assert_ne!(body[0].span(), program.statements[0].span());
+    }
+
+    #[test]
+    fn test_expression_spans() {
+        let program = parse_source("x = a + b * f(1) - 2;").unwrap();
+        let AstStatement::Assignment(_, AstExpression::BinaryOperation(_, sum, _, difference), _) =
+            &program.statements[0]
+        else {
+            panic!("expected a binary operation");
+        };
+        let AstExpression::BinaryOperation(_, _, product, sum) = &**sum else {
+            panic!("expected a binary operation");
+        };
+        let AstExpression::BinaryOperation(_, _, call, product) = &**product else {
+            panic!("expected a binary operation");
+        };
+        let AstExpression::FunctionCall(_, _, call) = &**call else {
+            panic!("expected a function call");
+        };
+
+        // operations span from their first operand to their last one
+        let range = |span: &Span| (span.start, span.end, span.column);
+        assert_eq!(range(difference), (4, 20, 5));
+        assert_eq!(range(sum), (4, 16, 5));
+        assert_eq!(range(product), (8, 16, 9));
+        assert_eq!(range(call), (12, 16, 13));
+    }
 }
@@ -0,0 +1,272 @@
+#[cfg(test)]
+mod tests {
+    use crate::ir_compiler::*;
+    use crate::parser::parse_source;
+    use crate::peephole::*;
+    use quickcheck::{Arbitrary, Gen, TestResult};
+    use quickcheck_macros::quickcheck;
+    use std::collections::HashMap;
+    use vmo2_types::bytecode::ByteCode;
+    use vmo2_types::opcode::{Opcode::*, *};
+    use vmo2_types::source_map::{SourceLocation, SourceMap};
+    use vmo2_types::value::Value;
+    use vmo2_vm::io::BufferIo;
+    use vmo2_vm::limits::Limits;
+    use vmo2_vm::vm::{VM, VMErrorKind};
+
+    fn key(name: &str) -> Opcode {
+        Literal(Value::String(name.to_owned()))
+    }
+
+    fn optimized(opcodes: Vec<Opcode>) -> Vec<Opcode> {
+        optimize(ByteCode::from(opcodes)).opcodes
+    }
+
+    /// Output, final heap and stack, and error of running `bytecode`.
+    type Outcome = (
+        Vec<String>,
+        HashMap<String, Value>,
+        Vec<Value>,
+        Option<VMErrorKind>,
+    );
+
+    fn run(bytecode: ByteCode) -> Outcome {
+        let io = BufferIo::new();
+        let mut vm = VM::new(bytecode).with_io(io.clone()).with_limits(Limits {
+            max_instructions: Some(2_000),
+            ..Limits::unlimited()
+        });
+        let error = vm.run().err().map(|error| error.kind);
+        (io.output(), vm.heap, vm.stack, error)
+    }
+
+    #[test]
+    fn test_remove_redundant_pairs() {
+        assert_eq!(
+            optimized(vec![
+                Literal(Value::UInt(1)),
+                Dup,
+                Pop,
+                Swap,
+                Swap,
+                Literal(Value::Bool(true)),
+                Pop,
+                IO(IOOpcode::Print),
+                Halt,
+            ]),
+            vec![Literal(Value::UInt(1)), IO(IOOpcode::Print), Halt]
+        );
+        // removing one pair can expose another
+        assert_eq!(optimized(vec![Swap, Dup, Pop, Swap, Halt]), vec![Halt]);
+    }
+
+    #[test]
+    fn test_store_then_load() {
+        assert_eq!(
+            optimized(vec![
+                Literal(Value::UInt(1)),
+                key("x"),
+                Memory(MemoryOpcode::Store),
+                key("x"),
+                Memory(MemoryOpcode::Load),
+                IO(IOOpcode::Print),
+                Halt,
+            ]),
+            vec![
+                Literal(Value::UInt(1)),
+                Dup,
+                key("x"),
+                Memory(MemoryOpcode::Store),
+                IO(IOOpcode::Print),
+                Halt,
+            ]
+        );
+        // a different variable is loaded
+        let other = vec![
+            key("x"),
+            Memory(MemoryOpcode::Store),
+            key("y"),
+            Memory(MemoryOpcode::Load),
+            Halt,
+        ];
+        assert_eq!(optimized(other.clone()), other);
+    }
+
+    #[test]
+    fn test_jumps_are_retargeted() {
+        assert_eq!(
+            optimized(vec![
+                Flow(FlowOpcode::Jump(1)),
+                Dup,
+                Pop,
+                Flow(FlowOpcode::JumpIfFalse(6)),
+                Literal(Value::UInt(1)),
+                Pop,
+                IO(IOOpcode::Print),
+                Flow(FlowOpcode::Jump(3)),
+                Flow(FlowOpcode::Call(10)),
+                Halt,
+            ]),
+            vec![
+                Flow(FlowOpcode::JumpIfFalse(1)),
+                IO(IOOpcode::Print),
+                Flow(FlowOpcode::Jump(0)),
+                Flow(FlowOpcode::Call(5)),
+                Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jumps_are_threaded() {
+        assert_eq!(
+            optimized(vec![
+                Flow(FlowOpcode::JumpIfTrue(2)),
+                Halt,
+                Flow(FlowOpcode::Jump(3)),
+                Flow(FlowOpcode::Jump(5)),
+                Halt,
+                IO(IOOpcode::Print),
+                Flow(FlowOpcode::Jump(6)),
+            ]),
+            vec![
+                Flow(FlowOpcode::JumpIfTrue(5)),
+                Halt,
+                Flow(FlowOpcode::Jump(5)),
+                Flow(FlowOpcode::Jump(5)),
+                Halt,
+                IO(IOOpcode::Print),
+                // a loop of jumps is left alone
+                Flow(FlowOpcode::Jump(6)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_target_inside_pattern_is_kept() {
+        let code = vec![
+            Literal(Value::UInt(1)),
+            Dup,
+            Pop,
+            Flow(FlowOpcode::JumpIfTrue(2)),
+            Halt,
+        ];
+        assert_eq!(optimized(code.clone()), code);
+
+        let code = vec![
+            Literal(Value::UInt(1)),
+            key("x"),
+            Memory(MemoryOpcode::Store),
+            key("x"),
+            Memory(MemoryOpcode::Load),
+            Flow(FlowOpcode::Jump(3)),
+        ];
+        assert_eq!(optimized(code.clone()), code);
+    }
+
+    #[test]
+    fn test_source_map_follows_opcodes() {
+        let at = |line| Some(SourceLocation { line, column: 1 });
+        let (bytecode, source_map) = optimize_with_source_map(
+            ByteCode::from(vec![
+                Literal(Value::UInt(1)),
+                key("x"),
+                Memory(MemoryOpcode::Store),
+                key("x"),
+                Memory(MemoryOpcode::Load),
+                Halt,
+            ]),
+            SourceMap {
+                locations: vec![at(1), at(2), at(2), at(3), at(3), at(4)],
+            },
+        );
+        assert_eq!(bytecode.opcodes.len(), 5);
+        assert_eq!(
+            source_map.locations,
+            vec![at(1), at(2), at(2), at(2), at(4)]
+        );
+    }
+
+    #[test]
+    fn test_compiled_program_keeps_output() {
+        let code = r#"
+            func sum(n) {
+                total = 0;
+                while (n > 0) {
+                    total = total + n;
+                    n = n - 1;
+                }
+                return total;
+            }
+            x = 4;
+            x = x * 2;
+            if (x >= 8) { print(sum(x)); } else { print(0); }
+            for (i = 0; i < 3; i = i + 1) { print(i); }
+        "#;
+        let bytecode = ir_to_bytecode(compile_to_ir(parse_source(code).unwrap()).unwrap()).unwrap();
+        let peephole = optimize(bytecode.clone());
+
+        assert!(peephole.opcodes.len() < bytecode.opcodes.len());
+        assert_eq!(run(peephole), run(bytecode));
+    }
+
+    /// Straight-line chunks biased towards the rewritten patterns, linked
+    /// with jumps into the program.
+    #[derive(Clone, Debug)]
+    struct Program(ByteCode);
+
+    impl Arbitrary for Program {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let keys = ["a", "b"];
+            let mut opcodes = vec![];
+            for _ in 0..u8::arbitrary(g) % 24 {
+                let chunk = match u8::arbitrary(g) % 12 {
+                    0 => vec![Dup, Pop],
+                    1 => vec![Swap, Swap],
+                    2 => vec![Literal(Value::UInt(u32::arbitrary(g) % 4)), Pop],
+                    3 => {
+                        let name = g.choose(&keys).unwrap();
+                        vec![
+                            key(name),
+                            Memory(MemoryOpcode::Store),
+                            key(name),
+                            Memory(MemoryOpcode::Load),
+                        ]
+                    }
+                    4 => vec![Flow(FlowOpcode::Jump(u32::arbitrary(g)))],
+                    5 => vec![Flow(FlowOpcode::JumpIfFalse(u32::arbitrary(g)))],
+                    6 => vec![key(g.choose(&keys).unwrap()), Memory(MemoryOpcode::Load)],
+                    7 => vec![key(g.choose(&keys).unwrap()), Memory(MemoryOpcode::Store)],
+                    8 => vec![Arithmetic(ArithmeticOpcode::Add)],
+                    9 => vec![IO(IOOpcode::Print)],
+                    10 => vec![Literal(Value::Bool(bool::arbitrary(g)))],
+                    _ => vec![Literal(Value::UInt(u32::arbitrary(g) % 4))],
+                };
+                opcodes.extend(chunk);
+            }
+            opcodes.push(Halt);
+
+            let len = opcodes.len() as u32;
+            for opcode in &mut opcodes {
+                if let Flow(FlowOpcode::Jump(target) | FlowOpcode::JumpIfFalse(target)) = opcode {
+                    *target %= len + 1;
+                }
+            }
+            Program(ByteCode::from(opcodes))
+        }
+    }
+
+    #[quickcheck]
+    fn peephole_keeps_behaviour(program: Program) -> TestResult {
+        let reference = run(program.0.clone());
+        // the pass assumes programs that do not underflow, and looping ones
+        // may be cut off at different points
+        if matches!(
+            reference.3,
+            Some(VMErrorKind::StackUnderflow | VMErrorKind::InstructionLimitExceeded(_))
+        ) {
+            return TestResult::discard();
+        }
+        TestResult::from_bool(run(optimize(program.0)) == reference)
+    }
+}
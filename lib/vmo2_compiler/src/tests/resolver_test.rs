@@ -0,0 +1,202 @@
+#[cfg(test)]
+mod tests {
+    use crate::error::{CompileError, CompileErrorKind};
+    use crate::parser::parse_source;
+    use crate::resolver::resolve;
+
+    fn resolve_source(code: &str) -> Result<(), CompileError> {
+        resolve(&parse_source(code).unwrap())
+    }
+
+    fn undefined_variable(code: &str) -> (String, usize) {
+        let error = resolve_source(code).unwrap_err();
+        match error.kind {
+            CompileErrorKind::UndefinedVariable(name) => (name, error.span.line),
+            kind => panic!("unexpected error: {kind:?}"),
+        }
+    }
+
+    #[test]
+    fn test_use_before_assignment() {
+        assert_eq!(undefined_variable("x = y;"), ("y".to_owned(), 1));
+        assert_eq!(
+            undefined_variable("x = 1;\nprint(x + z);\nz = 2;"),
+            ("z".to_owned(), 2)
+        );
+        assert_eq!(undefined_variable("xs[0] = 1;"), ("xs".to_owned(), 1));
+        assert_eq!(
+            undefined_variable("while (true) {\n    n = n + 1;\n}"),
+            ("n".to_owned(), 2)
+        );
+
+        // a read must be preceded by an assignment on every path
+        assert_eq!(
+            undefined_variable("if (false) {\n    y = 1;\n}\nprint(y);"),
+            ("y".to_owned(), 4)
+        );
+        assert_eq!(
+            undefined_variable("if (true) { x = 1; } else { y = 2; }\nprint(x, y);"),
+            ("x".to_owned(), 2)
+        );
+        assert!(resolve_source("if (true) { x = 1; } else { x = 2; }\nprint(x);").is_ok());
+        assert!(
+            resolve_source(
+                "if (true) { x = 1; } else if (false) { x = 2; } else { x = 3; }\nprint(x);"
+            )
+            .is_ok()
+        );
+
+        // loop bodies may not run
+        assert_eq!(
+            undefined_variable("for (i = 0; i < 3; i = i + 1) { last = i; }\nprint(last);"),
+            ("last".to_owned(), 2)
+        );
+        assert_eq!(
+            undefined_variable("while (false) { y = 1; }\nprint(y);"),
+            ("y".to_owned(), 2)
+        );
+        assert!(resolve_source("for (i = 0; i < 3; i = i + 1) { last = i; }\nprint(i);").is_ok());
+        // the step does not see the body, `continue` can skip it
+        assert_eq!(
+            undefined_variable("for (i = 0; i < 3; i = j) { j = i + 1; }"),
+            ("j".to_owned(), 1)
+        );
+
+        // a branch that always leaves does not reach what follows
+        assert!(
+            resolve_source(
+                "func f(c) {\n    if (c) { return 0; } else { x = 1; }\n    return x;\n}\nprint(f(true));"
+            )
+            .is_ok()
+        );
+        assert!(
+            resolve_source(
+                "while (true) {\n    if (false) { x = 1; } else { break; }\n    print(x);\n}"
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_function_scopes() {
+        // parameters are visible, and globals assigned before every call
+        assert!(resolve_source("func f(a) { return a + g; }\ng = 1;\nprint(f(2));").is_ok());
+        assert_eq!(
+            undefined_variable("func f() {\n    return g;\n}\nx = f();\ng = 1;"),
+            ("g".to_owned(), 2)
+        );
+        assert_eq!(
+            undefined_variable(
+                "func f() {\n    return g;\n}\nif (true) {\n    g = 1;\n    print(f());\n}\nprint(f());"
+            ),
+            ("g".to_owned(), 2)
+        );
+        // a function sees what every function calling it sees
+        assert_eq!(
+            undefined_variable(
+                "func f() {\n    return h();\n}\nfunc h() {\n    return g;\n}\nx = f();\ng = 1;\nprint(h());"
+            ),
+            ("g".to_owned(), 5)
+        );
+        assert!(
+            resolve_source(
+                "func f(n) { if (n > 0) { return f(n - 1); } return g; }\ng = 1;\nf(3);"
+            )
+            .is_ok()
+        );
+        // a function the top level never calls only needs the global to be
+        // assigned somewhere, it does not run
+        assert!(resolve_source("func f() { return g; }\nfunc h() { return f(); }\ng = 1;").is_ok());
+
+        // locals of the caller are not
+        assert_eq!(
+            undefined_variable(
+                "func f() {\n    x = 1;\n    return g();\n}\nfunc g() {\n    return x;\n}"
+            ),
+            ("x".to_owned(), 6)
+        );
+        // nor are the parameters of an enclosing function
+        assert_eq!(
+            undefined_variable("func f(a) {\n    func g() {\n        return a;\n    }\n}"),
+            ("a".to_owned(), 3)
+        );
+        // locals of a function do not leak to the top level
+        assert_eq!(
+            undefined_variable("func f() { y = 1; }\nf();\nprint(y);"),
+            ("y".to_owned(), 3)
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        let error = resolve_source("func f() {}\nfunc g() {}\nfunc f(a) {}").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::DuplicateFunction("f".to_owned())
+        );
+        assert_eq!(error.span.line, 3);
+
+        let error = resolve_source("if (true) {\n    print(h());\n}").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::UndefinedFunction("h".to_owned())
+        );
+        assert_eq!(error.span.line, 2);
+
+        let error = resolve_source("func f(a) { return f(a, a); }").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::ArgumentCount {
+                name: "f".to_owned(),
+                expected: 1,
+                found: 2,
+            }
+        );
+
+        // builtins cannot be redefined, wherever the definition is
+        let error = resolve_source("func print(x) {\n    return 1;\n}\nprint(5);").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::BuiltinRedefinition("print".to_owned())
+        );
+        assert_eq!(error.span.line, 1);
+        let error = resolve_source("if (true) {\n    func len(a, b) { return a; }\n}\nlen(1, 2);")
+            .unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::BuiltinRedefinition("len".to_owned())
+        );
+        assert_eq!(error.span.line, 2);
+        assert_eq!(
+            error.to_string(),
+            "`len` is a builtin and cannot be redefined at line 2, column 5"
+        );
+
+        // builtins have a fixed arity too, except for `print`
+        let error = resolve_source("func f() { return 1; }\nx = scan(f());").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::ArgumentCount {
+                name: "scan".to_owned(),
+                expected: 0,
+                found: 1,
+            }
+        );
+        assert_eq!(error.span.line, 2);
+        let error = resolve_source("print(contains([1]));").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::ArgumentCount {
+                name: "contains".to_owned(),
+                expected: 2,
+                found: 1,
+            }
+        );
+        assert!(resolve_source("print();\nprint(1, 2, 3);").is_ok());
+
+        // builtins are not user functions
+        assert!(resolve_source("xs = [];\npush(xs, 1);\nprint(len(xs), pop(xs));").is_ok());
+        // nested definitions are global
+        assert!(resolve_source("func f() { func g() { return 1; } }\nprint(g());").is_ok());
+    }
+}
@@ -0,0 +1,158 @@
+#[cfg(test)]
+mod tests {
+    use crate::error::{CompileError, CompileErrorKind};
+    use crate::ir::IrInstruction;
+    use crate::ir_compiler::compile_to_typed_ir;
+    use crate::parser::parse_source;
+    use crate::type_checker::*;
+
+    fn check_source(code: &str) -> Result<TypeInfo, CompileError> {
+        check(&parse_source(code).unwrap())
+    }
+
+    fn variable(code: &str, function: &str, name: &str) -> Type {
+        check_source(code).unwrap().variable(function, name)
+    }
+
+    #[test]
+    fn test_type_display() {
+        assert_eq!(Type::UINT.to_string(), "uint");
+        assert_eq!(Type::UINT.union(Type::NULL).to_string(), "uint | null");
+        assert_eq!(Type::ANY.to_string(), "any");
+        assert_eq!(Type::NONE.to_string(), "nothing");
+    }
+
+    #[test]
+    fn test_inference() {
+        assert_eq!(variable("x = 1 / 2;", "main", "x"), Type::UINT);
+        assert_eq!(
+            variable("x = 1 - 2;", "main", "x"),
+            Type::UINT.union(Type::INT)
+        );
+        assert_eq!(variable("x = -1 * 2.5;", "main", "x"), Type::FLOAT);
+        assert_eq!(variable("x = \"ab\" * 3;", "main", "x"), Type::STRING);
+        assert_eq!(variable("x = [1][0];", "main", "x"), Type::ANY);
+        assert_eq!(variable("x = 1 < 2.5;", "main", "x"), Type::BOOL);
+
+        // every assignment counts, loops are followed until types settle
+        let code = "x = null;\ny = 1;\nwhile (y < 3) {\n    x = y;\n    y = y * 1.5;\n}";
+        assert_eq!(
+            variable(code, "main", "x"),
+            Type::UINT.union(Type::FLOAT).union(Type::NULL)
+        );
+
+        // a function sees its parameters, locals and the globals
+        let code = "g = \"a\";\nfunc f(p) {\n    g = 1;\n    return p;\n}";
+        assert_eq!(variable(code, "f", "p"), Type::ANY);
+        assert_eq!(variable(code, "f", "g"), Type::UINT.union(Type::STRING));
+        assert_eq!(variable(code, "main", "g"), Type::STRING);
+    }
+
+    #[test]
+    fn test_mismatches() {
+        let error = check_source("x = 1;\ny = \"a\" - x;").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::TypeMismatch {
+                operation: "-".to_owned(),
+                lhs: Type::STRING,
+                rhs: Some(Type::UINT),
+            }
+        );
+        assert_eq!(error.span.line, 2);
+        assert_eq!(
+            error.to_string(),
+            "cannot apply `-` to string and uint at line 2, column 5"
+        );
+
+        let error = check_source("print(true + 1);").unwrap_err();
+        assert_eq!(error.kind.to_string(), "cannot apply `+` to bool and uint");
+        let error = check_source("x = -\"a\";").unwrap_err();
+        assert_eq!(error.kind.to_string(), "cannot apply `-` to string");
+        let error = check_source("x = \"a\" * 1.5;").unwrap_err();
+        assert_eq!(
+            error.kind.to_string(),
+            "cannot apply `*` to string and float"
+        );
+
+        let error = check_source("x = 1;\nwhile (x) {\n}").unwrap_err();
+        assert_eq!(
+            error.kind,
+            CompileErrorKind::ExpectedType {
+                expected: Type::BOOL,
+                found: Type::UINT,
+            }
+        );
+        let error = check_source("x = {[1]: 2};").unwrap_err();
+        assert_eq!(
+            error.kind.to_string(),
+            "expected uint | int | string, found list"
+        );
+        let error = check_source("x = len(1);").unwrap_err();
+        assert_eq!(
+            error.kind.to_string(),
+            "expected string | list | map, found uint"
+        );
+        let error = check_source("m = {};\nm[true] = 1;").unwrap_err();
+        assert_eq!(error.kind.to_string(), "cannot apply `[]` to map and bool");
+
+        // errors inside function bodies are found too
+        assert!(check_source("func f() {\n    return 1 && true;\n}").is_err());
+    }
+
+    #[test]
+    fn test_possible_types_are_accepted() {
+        // any of the possible types may work at runtime
+        assert!(check_source("x = scan();\ny = x + \"!\";").is_ok());
+        assert!(check_source("func f(a) { return a - 1; }").is_ok());
+        assert!(check_source("x = 1;\nx = \"a\";\ny = x * 2;").is_ok());
+        assert!(
+            check_source("xs = [1, 2];\nif (xs[0] == 1 && contains(xs, 2)) { push(xs, 3); }")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_short_circuit_operands() {
+        // the right side only matters when the left one does not decide
+        assert!(check_source("x = false && 5;\nprint(x);").is_ok());
+        assert!(check_source("x = true || \"a\";\nprint(x);").is_ok());
+        assert!(check_source("x = scan() == \"y\";\ny = x && 5;").is_ok());
+
+        let error = check_source("x = true && 5;").unwrap_err();
+        assert_eq!(error.kind.to_string(), "cannot apply `&&` to bool and uint");
+        let error = check_source("x = false || [];").unwrap_err();
+        assert_eq!(error.kind.to_string(), "cannot apply `||` to bool and list");
+        let error = check_source("x = 1 && true;").unwrap_err();
+        assert_eq!(error.kind.to_string(), "cannot apply `&&` to uint and bool");
+    }
+
+    #[test]
+    fn test_typed_ir() {
+        let program = parse_source("x = 1;\ny = 2.5;\nz = x + x;\nw = x * y;\nv = -y;").unwrap();
+        let types = check(&program).unwrap();
+        let ir = compile_to_typed_ir(program, &types).unwrap();
+
+        let block = &ir.functions["main"].blocks[0];
+        let annotated: Vec<_> = block
+            .instructions
+            .iter()
+            .zip(&block.types)
+            .filter(|(instruction, _)| {
+                matches!(
+                    instruction,
+                    IrInstruction::Add | IrInstruction::Mul | IrInstruction::Neg
+                )
+            })
+            .map(|(_, ty)| *ty)
+            .collect();
+        assert_eq!(
+            annotated,
+            vec![
+                Some(Type::UINT),
+                Some(Type::UINT.union(Type::FLOAT)),
+                Some(Type::FLOAT)
+            ]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+#[cfg(test)]
+mod tests {
+    use crate::ir_compiler::*;
+    use crate::ir_text;
+    use crate::optimizer::optimize;
+    use crate::parser::parse_source;
+    use crate::verifier::*;
+
+    fn verify_text(text: &str) -> Result<(), VerifyError> {
+        verify(&ir_text::parse(text).unwrap())
+    }
+
+    fn error_kind(text: &str) -> VerifyErrorKind {
+        verify_text(text).unwrap_err().kind
+    }
+
+    #[test]
+    fn test_compiled_programs_verify() {
+        let code = r#"
+            func fib(n) {
+                if (n < 2) { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            x = [1, 2];
+            while (len(x) < 5 && !contains(x, 7)) {
+                push(x, fib(len(x)));
+                if (x[0] == 1 || false) { continue; }
+                break;
+            }
+            print(x);
+        "#;
+        for level in 0..=2 {
+            let mut ir = compile_to_ir(parse_source(code).unwrap()).unwrap();
+            optimize(&mut ir, level);
+            assert_eq!(verify(&ir), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_missing_block() {
+        let error = verify_text(
+            "func main() {
+            bb0:
+                push true
+                jump_if bb1, bb2
+            bb1:
+                halt
+            }",
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            VerifyError {
+                function: "main".to_owned(),
+                block: 0,
+                instruction: Some(1),
+                kind: VerifyErrorKind::MissingBlock(2),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "function `main`, bb0, instruction 1: block bb2 does not exist"
+        );
+    }
+
+    #[test]
+    fn test_undefined_function() {
+        assert_eq!(
+            error_kind("func main() {\nbb0:\n    call f\n    halt\n}"),
+            VerifyErrorKind::UndefinedFunction("f".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_missing_terminator() {
+        // falling through to the next block is fine, off the end is not
+        let error = verify_text("func main() {\nbb0:\n    push 1\nbb1:\n    pop\n}").unwrap_err();
+        assert_eq!(error.block, 1);
+        assert_eq!(error.kind, VerifyErrorKind::MissingTerminator);
+        assert_eq!(
+            error.to_string(),
+            "function `main`, bb1: last block falls off the end of the function"
+        );
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        // `f` starts with its argument on the stack
+        assert_eq!(
+            verify_text(
+                "func main() {\nbb0:\n    halt\n}\nfunc f(a) {\nbb0:\n    store a\n    load a\n    return\n}"
+            ),
+            Ok(())
+        );
+        let error =
+            verify_text("func main() {\nbb0:\n    push 1\n    add\n    halt\n}").unwrap_err();
+        assert_eq!(error.instruction, Some(1));
+        assert_eq!(
+            error.kind,
+            VerifyErrorKind::StackUnderflow { pops: 2, depth: 1 }
+        );
+        // after a terminator nothing runs
+        assert_eq!(
+            verify_text("func main() {\nbb0:\n    halt\n    pop\n}"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_stack_mismatch() {
+        let error = verify_text(
+            "func main() {
+            bb0:
+                push true
+                jump_if bb1, bb2
+            bb1:
+                push 1
+                jump bb2
+            bb2:
+                halt
+            }",
+        )
+        .unwrap_err();
+        assert_eq!(error.block, 1);
+        assert_eq!(error.instruction, Some(1));
+        assert_eq!(
+            error.kind,
+            VerifyErrorKind::StackMismatch {
+                block: 2,
+                expected: 0,
+                found: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_misaligned_annotations() {
+        let mut ir = ir_text::parse("func main() {\nbb0:\n    halt\n}").unwrap();
+        ir.functions.get_mut("main").unwrap().blocks[0]
+            .types
+            .clear();
+        assert_eq!(
+            verify(&ir).unwrap_err().kind,
+            VerifyErrorKind::MisalignedAnnotations
+        );
+    }
+}
@@ -0,0 +1,546 @@
+use crate::error::*;
+use crate::resolver::BUILTINS;
+use crate::types::*;
+use std::collections::HashMap;
+use std::fmt;
+use vmo2_types::value::Value;
+
+/// Set of the runtime types a value may have, one bit per `Value` variant.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Type(u8);
+
+impl Type {
+    pub const NONE: Type = Type(0);
+    pub const UINT: Type = Type(1 << 0);
+    pub const INT: Type = Type(1 << 1);
+    pub const FLOAT: Type = Type(1 << 2);
+    pub const BOOL: Type = Type(1 << 3);
+    pub const STRING: Type = Type(1 << 4);
+    pub const LIST: Type = Type(1 << 5);
+    pub const MAP: Type = Type(1 << 6);
+    pub const NULL: Type = Type(1 << 7);
+    pub const ANY: Type = Type(u8::MAX);
+
+    pub const NUMBER: Type = Type(Self::UINT.0 | Self::INT.0 | Self::FLOAT.0);
+    /// Types `Value::map_key` accepts.
+    pub const KEY: Type = Type(Self::UINT.0 | Self::INT.0 | Self::STRING.0);
+
+    const NAMES: [&'static str; 8] = [
+        "uint", "int", "float", "bool", "string", "list", "map", "null",
+    ];
+
+    /// Parses a type as `Display` writes it, e.g. `uint | string`.
+    pub fn from_name(text: &str) -> Option<Type> {
+        match text.trim() {
+            "any" => return Some(Type::ANY),
+            "nothing" => return Some(Type::NONE),
+            _ => {}
+        }
+        text.split('|').try_fold(Type::NONE, |ty, name| {
+            let index = Type::NAMES.iter().position(|n| *n == name.trim())?;
+            Some(ty.union(Type(1 << index)))
+        })
+    }
+
+    pub fn of(value: &Value) -> Type {
+        match value {
+            Value::UInt(_) => Type::UINT,
+            Value::Int(_) => Type::INT,
+            Value::Float(_) => Type::FLOAT,
+            Value::Bool(_) => Type::BOOL,
+            Value::String(_) => Type::STRING,
+            Value::List(_) => Type::LIST,
+            Value::Map(_) => Type::MAP,
+            Value::Null => Type::NULL,
+        }
+    }
+
+    pub fn union(self, other: Type) -> Type {
+        Type(self.0 | other.0)
+    }
+
+    pub fn intersects(self, other: Type) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every type of `self` is one of `other`.
+    pub fn is_subset(self, other: Type) -> bool {
+        self.0 & !other.0 == 0
+    }
+
+    /// Whether exactly one type is possible.
+    pub fn is_single(self) -> bool {
+        self.0.is_power_of_two()
+    }
+
+    /// The single types in the set.
+    pub fn members(self) -> impl Iterator<Item = Type> {
+        (0..8)
+            .map(|bit| Type(1 << bit))
+            .filter(move |member| self.intersects(*member))
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Type::ANY => return write!(f, "any"),
+            Type::NONE => return write!(f, "nothing"),
+            _ => {}
+        }
+        for (i, member) in self.members().enumerate() {
+            if i > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{}", Type::NAMES[member.0.trailing_zeros() as usize])?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of a binary operator on two single types, `None` when `Value`
+/// rejects the combination.
+fn binary_result(operator: &str, lhs: Type, rhs: Type) -> Option<Type> {
+    let numeric = Type::NUMBER.intersects(lhs) && Type::NUMBER.intersects(rhs);
+    // see the promotion rules of `Value`'s arithmetic
+    let promoted = |uint: Type| match (lhs, rhs) {
+        _ if lhs == Type::FLOAT || rhs == Type::FLOAT => Type::FLOAT,
+        (Type::UINT, Type::UINT) => uint,
+        _ => Type::INT,
+    };
+    match operator {
+        "+" if lhs == Type::STRING && rhs == Type::STRING => Some(Type::STRING),
+        "*" if (lhs, rhs) == (Type::STRING, Type::UINT)
+            || (lhs, rhs) == (Type::UINT, Type::STRING) =>
+        {
+            Some(Type::STRING)
+        }
+        // a sum, difference or product of uints may not fit in a uint
+        "+" | "-" | "*" if numeric => Some(promoted(Type::UINT.union(Type::INT))),
+        "/" | "%" if numeric => Some(promoted(Type::UINT)),
+        "<" | "<=" | ">" | ">="
+            if numeric || (lhs == rhs && (lhs == Type::BOOL || lhs == Type::STRING)) =>
+        {
+            Some(Type::BOOL)
+        }
+        "^" if lhs == Type::BOOL && rhs == Type::BOOL => Some(Type::BOOL),
+        "==" | "!=" => Some(Type::BOOL),
+        _ => None,
+    }
+}
+
+fn unary_result(operator: &str, operand: Type) -> Option<Type> {
+    match (operator, operand) {
+        ("+", operand) => Some(operand),
+        ("-", Type::UINT | Type::INT) => Some(Type::INT),
+        ("-", Type::FLOAT) => Some(Type::FLOAT),
+        ("!", Type::BOOL) => Some(Type::BOOL),
+        _ => None,
+    }
+}
+
+fn check_operator(operator: &str) -> Result<(), CompileErrorKind> {
+    match operator {
+        "+" | "-" | "*" | "/" | "%" | "<" | "<=" | ">" | ">=" | "&&" | "||" | "^" | "==" | "!="
+        | "!" => Ok(()),
+        _ => Err(CompileErrorKind::UnknownOperator(operator.to_string())),
+    }
+}
+
+/// Error unless a value of type `found` can be one of `expected`.
+fn expect(expected: Type, found: Type) -> Result<(), CompileErrorKind> {
+    if found.is_empty() || expected.intersects(found) {
+        return Ok(());
+    }
+    Err(CompileErrorKind::ExpectedType { expected, found })
+}
+
+/// Variable types of one function.
+type Variables = HashMap<String, Type>;
+
+/*
+ *  Types inferred for a program.
+ *
+ *  Inference is flow insensitive: a variable has every type assigned to it
+ *  anywhere in its function, computed by walking the function until the
+ *  types stop growing. Parameters and call results can be anything. A
+ *  function reading a variable may see its local or, before the local is
+ *  assigned, the global of the same name, so it gets the union of both.
+ *
+ *  Only operations that fail for every possible combination of operand
+ *  types are reported: `"a" - 1` is an error, `scan() - 1` is not.
+ */
+#[derive(Debug, Default)]
+pub struct TypeInfo {
+    /// Variables of every function, `main` standing for the top level.
+    pub functions: HashMap<String, Variables>,
+}
+
+/// Infers the types of `program` and checks every operation against them.
+pub fn check(program: &AstProgram) -> Result<TypeInfo, CompileError> {
+    let mut bodies = vec![("main".to_string(), Vec::new(), &program.statements)];
+    collect_functions(&program.statements, &mut bodies);
+
+    let mut info = TypeInfo::default();
+    for (name, parameters, body) in &bodies {
+        let variables = parameters.iter().map(|p| (p.clone(), Type::ANY)).collect();
+        info.functions.insert(name.clone(), variables);
+        // the globals are settled before the functions reading them
+        while info.assign(name, body) {}
+    }
+
+    for (name, _, body) in &bodies {
+        let mut checker = Checker {
+            info: &info,
+            function: name,
+            span: Span::default(),
+        };
+        checker.check_block(body)?;
+    }
+    Ok(info)
+}
+
+/// Every function defined in `statements`, nested ones included.
+fn collect_functions<'a>(
+    statements: &'a [AstStatement],
+    bodies: &mut Vec<(String, Vec<String>, &'a Vec<AstStatement>)>,
+) {
+    for statement in statements {
+        match statement {
+            AstStatement::FunctionDefinition(name, parameters, body, _) => {
+                bodies.push((name.clone(), parameters.clone(), body));
+                collect_functions(body, bodies);
+            }
+            AstStatement::While(_, body, _) | AstStatement::For(_, _, _, body, _) => {
+                collect_functions(body, bodies)
+            }
+            AstStatement::If(_, then_body, else_body, _) => {
+                collect_functions(then_body, bodies);
+                if let Some(else_body) = else_body {
+                    collect_functions(else_body, bodies);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl TypeInfo {
+    /// Type of variable `name` read in `function`.
+    pub fn variable(&self, function: &str, name: &str) -> Type {
+        let local = self.functions.get(function).and_then(|v| v.get(name));
+        let global = match function {
+            "main" => None,
+            _ => self.functions.get("main").and_then(|v| v.get(name)),
+        };
+        local
+            .copied()
+            .unwrap_or_default()
+            .union(global.copied().unwrap_or_default())
+    }
+
+    /// Type of `expr` evaluated in `function`.
+    pub fn expression_type(&self, function: &str, expr: &AstExpression) -> Type {
+        let checker = Checker {
+            info: self,
+            function,
+            span: Span::default(),
+        };
+        checker.infer(expr).unwrap_or(Type::ANY)
+    }
+
+    /// Types the operands of a binary operator have between them, `None`
+    /// when nothing is known about them.
+    pub fn operand_type(
+        &self,
+        function: &str,
+        lhs: &AstExpression,
+        rhs: &AstExpression,
+    ) -> Option<Type> {
+        let ty = self
+            .expression_type(function, lhs)
+            .union(self.expression_type(function, rhs));
+        (ty != Type::ANY && !ty.is_empty()).then_some(ty)
+    }
+
+    /// Widens the variables of `function` with what `statements` assign,
+    /// returning whether any of them grew.
+    fn assign(&mut self, function: &str, statements: &[AstStatement]) -> bool {
+        let mut grew = false;
+        for statement in statements {
+            match statement {
+                AstStatement::Assignment(name, expr, _) => {
+                    let assigned = self.expression_type_or_none(function, expr);
+                    let variables = self.functions.entry(function.to_string()).or_default();
+                    let current = variables.entry(name.clone()).or_default();
+                    grew |= current.union(assigned) != *current;
+                    *current = current.union(assigned);
+                }
+                AstStatement::While(_, body, _) => grew |= self.assign(function, body),
+                AstStatement::For(init, _, step, body, _) => {
+                    for clause in [init, step].into_iter().flatten() {
+                        grew |= self.assign(function, std::slice::from_ref(clause));
+                    }
+                    grew |= self.assign(function, body);
+                }
+                AstStatement::If(_, then_body, else_body, _) => {
+                    grew |= self.assign(function, then_body);
+                    if let Some(else_body) = else_body {
+                        grew |= self.assign(function, else_body);
+                    }
+                }
+                _ => {}
+            }
+        }
+        grew
+    }
+
+    /// While inferring, an operation that always fails has no result.
+    fn expression_type_or_none(&self, function: &str, expr: &AstExpression) -> Type {
+        let checker = Checker {
+            info: self,
+            function,
+            span: Span::default(),
+        };
+        checker.infer(expr).unwrap_or(Type::NONE)
+    }
+}
+
+/// Checks the statements of one function against the inferred types.
+struct Checker<'a> {
+    info: &'a TypeInfo,
+    function: &'a str,
+    span: Span,
+}
+
+impl Checker<'_> {
+    fn error(&self, kind: CompileErrorKind) -> CompileError {
+        CompileError::new(kind, self.span)
+    }
+
+    fn check_block(&mut self, statements: &[AstStatement]) -> Result<(), CompileError> {
+        for statement in statements {
+            self.check_stmt(statement)?;
+        }
+        Ok(())
+    }
+
+    /// Checks a loop or `if` condition, which must be a bool.
+    fn check_condition(&self, condition: &AstExpression) -> Result<(), CompileError> {
+        let found = self.infer(condition)?;
+        expect(Type::BOOL, found).map_err(|kind| self.error(kind))
+    }
+
+    fn check_stmt(&mut self, stmt: &AstStatement) -> Result<(), CompileError> {
+        let outer_span = std::mem::replace(&mut self.span, stmt.span());
+        match stmt {
+            AstStatement::Assignment(_, expr, _)
+            | AstStatement::Expression(expr, _)
+            | AstStatement::Return(Some(expr), _) => {
+                self.infer(expr)?;
+            }
+            AstStatement::IndexAssignment(name, index, expr, _) => {
+                let collection = self.info.variable(self.function, name);
+                let index = self.infer(index)?;
+                self.index(collection, index)
+                    .map_err(|kind| self.error(kind))?;
+                self.infer(expr)?;
+            }
+            AstStatement::While(condition, body, _) => {
+                self.check_condition(condition)?;
+                self.check_block(body)?;
+            }
+            AstStatement::For(init, condition, step, body, _) => {
+                if let Some(init) = init {
+                    self.check_stmt(init)?;
+                }
+                if let Some(condition) = condition {
+                    self.check_condition(condition)?;
+                }
+                self.check_block(body)?;
+                if let Some(step) = step {
+                    self.check_stmt(step)?;
+                }
+            }
+            AstStatement::If(condition, then_body, else_body, _) => {
+                self.check_condition(condition)?;
+                self.check_block(then_body)?;
+                if let Some(else_body) = else_body {
+                    self.check_block(else_body)?;
+                }
+            }
+            // functions are checked on their own
+            AstStatement::FunctionDefinition(..)
+            | AstStatement::Return(None, _)
+            | AstStatement::Break(_)
+            | AstStatement::Continue(_) => {}
+        }
+        self.span = outer_span;
+        Ok(())
+    }
+
+    /// Type of `collection[index]`.
+    fn index(&self, collection: Type, index: Type) -> Result<Type, CompileErrorKind> {
+        if collection.is_empty() || index.is_empty() {
+            return Ok(Type::NONE);
+        }
+        let list =
+            collection.intersects(Type::LIST) && index.intersects(Type::UINT.union(Type::INT));
+        let map = collection.intersects(Type::MAP) && index.intersects(Type::KEY);
+        if list || map {
+            return Ok(Type::ANY);
+        }
+        Err(CompileErrorKind::TypeMismatch {
+            operation: "[]".to_string(),
+            lhs: collection,
+            rhs: Some(index),
+        })
+    }
+
+    /// Errors about an operator or a call point at that expression, the
+    /// others at the statement being checked.
+    fn infer(&self, expr: &AstExpression) -> Result<Type, CompileError> {
+        match expr {
+            AstExpression::Literal(literal) => Ok(match literal {
+                AstLiteral::UInt(_) => Type::UINT,
+                AstLiteral::Int(_) => Type::INT,
+                AstLiteral::Float(_) => Type::FLOAT,
+                AstLiteral::String(_) => Type::STRING,
+                AstLiteral::Bool(_) => Type::BOOL,
+                AstLiteral::Null => Type::NULL,
+            }),
+            AstExpression::Variable(name) => Ok(self.info.variable(self.function, name)),
+            AstExpression::UnaryOperation(operator, operand, span) => {
+                let error = |kind| CompileError::new(kind, *span);
+                check_operator(operator).map_err(error)?;
+                let operand = self.infer(operand)?;
+                if operand.is_empty() {
+                    return Ok(Type::NONE);
+                }
+                let result = operand
+                    .members()
+                    .filter_map(|member| unary_result(operator, member))
+                    .fold(Type::NONE, Type::union);
+                if result.is_empty() {
+                    return Err(error(CompileErrorKind::TypeMismatch {
+                        operation: operator.clone(),
+                        lhs: operand,
+                        rhs: None,
+                    }));
+                }
+                Ok(result)
+            }
+            AstExpression::BinaryOperation(operator, lhs, rhs, span)
+                if operator == "&&" || operator == "||" =>
+            {
+                /*
+                    The left side must be a bool. The right side is only
+                    evaluated, and then has to be a bool, when the left one
+                    does not decide the result, so it is only reported when
+                    that is certain: `false && 5` is `false`, `true && 5`
+                    always fails.
+                */
+                let decides = operator == "||";
+                let always_evaluated =
+                    matches!(**lhs, AstExpression::Literal(AstLiteral::Bool(b)) if b != decides);
+                let (lhs, rhs) = (self.infer(lhs)?, self.infer(rhs)?);
+                if lhs.is_empty() || rhs.is_empty() {
+                    return Ok(Type::NONE);
+                }
+                if !lhs.intersects(Type::BOOL) || (always_evaluated && !rhs.intersects(Type::BOOL))
+                {
+                    return Err(CompileError::new(
+                        CompileErrorKind::TypeMismatch {
+                            operation: operator.clone(),
+                            lhs,
+                            rhs: Some(rhs),
+                        },
+                        *span,
+                    ));
+                }
+                Ok(Type::BOOL)
+            }
+            AstExpression::BinaryOperation(operator, lhs, rhs, span) => {
+                let error = |kind| CompileError::new(kind, *span);
+                check_operator(operator).map_err(error)?;
+                let (lhs, rhs) = (self.infer(lhs)?, self.infer(rhs)?);
+                if lhs.is_empty() || rhs.is_empty() {
+                    return Ok(Type::NONE);
+                }
+                let result = lhs
+                    .members()
+                    .flat_map(|l| rhs.members().map(move |r| (l, r)))
+                    .filter_map(|(l, r)| binary_result(operator, l, r))
+                    .fold(Type::NONE, Type::union);
+                if result.is_empty() {
+                    return Err(error(CompileErrorKind::TypeMismatch {
+                        operation: operator.clone(),
+                        lhs,
+                        rhs: Some(rhs),
+                    }));
+                }
+                Ok(result)
+            }
+            AstExpression::List(items) => {
+                for item in items {
+                    self.infer(item)?;
+                }
+                Ok(Type::LIST)
+            }
+            AstExpression::Map(entries) => {
+                for (key, value) in entries {
+                    expect(Type::KEY, self.infer(key)?).map_err(|kind| self.error(kind))?;
+                    self.infer(value)?;
+                }
+                Ok(Type::MAP)
+            }
+            AstExpression::Index(collection, index) => {
+                let (collection, index) = (self.infer(collection)?, self.infer(index)?);
+                self.index(collection, index)
+                    .map_err(|kind| self.error(kind))
+            }
+            AstExpression::FunctionCall(name, args, span) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.infer(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if !BUILTINS.contains(&name.as_str()) {
+                    return Ok(Type::ANY);
+                }
+                // the arity of builtins is checked while lowering
+                let first = args.first().copied().unwrap_or(Type::ANY);
+                let expect_first = |expected| {
+                    expect(expected, first).map_err(|kind| CompileError::new(kind, *span))
+                };
+                match name.as_str() {
+                    "print" => Ok(Type::NULL),
+                    "scan" => Ok(Type::STRING.union(Type::NULL)),
+                    "len" => {
+                        expect_first(Type::STRING.union(Type::LIST).union(Type::MAP))?;
+                        Ok(Type::UINT.union(Type::INT))
+                    }
+                    "keys" => {
+                        expect_first(Type::MAP)?;
+                        Ok(Type::LIST)
+                    }
+                    "contains" => {
+                        expect_first(Type::LIST.union(Type::MAP))?;
+                        Ok(Type::BOOL)
+                    }
+                    "push" => {
+                        expect_first(Type::LIST)?;
+                        Ok(Type::NULL)
+                    }
+                    _ => {
+                        expect_first(Type::LIST)?;
+                        Ok(Type::ANY)
+                    }
+                }
+            }
+        }
+    }
+}
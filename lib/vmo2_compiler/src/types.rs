@@ -4,32 +4,104 @@ use pest_derive::Parser;
 #[grammar = "oxyde.pest"]
 pub struct OxydeParser;
 
+/// Location of a node in the source text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    /// Byte offsets into the source.
+    pub start: usize,
+    pub end: usize,
+    /// 1-based position of `start`.
+    pub line: usize,
+    pub column: usize,
+}
+
+impl From<pest::Span<'_>> for Span {
+    fn from(span: pest::Span<'_>) -> Self {
+        let (line, column) = span.start_pos().line_col();
+        Self {
+            start: span.start(),
+            end: span.end(),
+            line,
+            column,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AstProgram {
     pub statements: Vec<AstStatement>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub enum AstStatement {
-    Assignment(String, AstExpression),
-    FunctionDefinition(String, Vec<AstStatement>),
-    While(AstExpression, Vec<AstStatement>),
-    Expression(AstExpression),
+    Assignment(String, AstExpression, Span),
+    /// `name[index] = value`.
+    IndexAssignment(String, AstExpression, AstExpression, Span),
+    /// Name, parameters and body.
+    FunctionDefinition(String, Vec<String>, Vec<AstStatement>, Span),
+    While(AstExpression, Vec<AstStatement>, Span),
+    /// Init, condition, step and body. Init and step are assignments or
+    /// expression statements.
+    For(
+        Option<Box<AstStatement>>,
+        Option<AstExpression>,
+        Option<Box<AstStatement>>,
+        Vec<AstStatement>,
+        Span,
+    ),
+    Break(Span),
+    Continue(Span),
+    /// Condition, then branch and optional else branch. `else if` is an else
+    /// branch holding a single `If`.
+    If(
+        AstExpression,
+        Vec<AstStatement>,
+        Option<Vec<AstStatement>>,
+        Span,
+    ),
+    Expression(AstExpression, Span),
+    Return(Option<AstExpression>, Span),
+}
+
+impl AstStatement {
+    pub fn span(&self) -> Span {
+        match self {
+            AstStatement::Assignment(_, _, span)
+            | AstStatement::IndexAssignment(_, _, _, span)
+            | AstStatement::FunctionDefinition(_, _, _, span)
+            | AstStatement::While(_, _, span)
+            | AstStatement::For(_, _, _, _, span)
+            | AstStatement::Break(span)
+            | AstStatement::Continue(span)
+            | AstStatement::If(_, _, _, span)
+            | AstStatement::Expression(_, span)
+            | AstStatement::Return(_, span) => *span,
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub enum AstLiteral {
     UInt(u32),
+    Int(i64),
+    Float(f64),
     String(String),
     Bool(bool),
     Null,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// Operators and calls carry their own span, so that diagnostics about them
+/// point at the expression rather than the whole statement.
+#[derive(Debug, PartialEq)]
 pub enum AstExpression {
-    BinaryOperation(String, Box<AstExpression>, Box<AstExpression>),
+    BinaryOperation(String, Box<AstExpression>, Box<AstExpression>, Span),
     Literal(AstLiteral),
-    FunctionCall(String, Vec<AstExpression>),
+    FunctionCall(String, Vec<AstExpression>, Span),
     Variable(String),
-    UnaryOperation(String, Box<AstExpression>),
+    UnaryOperation(String, Box<AstExpression>, Span),
+    List(Vec<AstExpression>),
+    /// Key and value of every entry, in source order.
+    Map(Vec<(AstExpression, AstExpression)>),
+    /// Collection and index.
+    Index(Box<AstExpression>, Box<AstExpression>),
 }
@@ -0,0 +1,219 @@
+use crate::cfg::terminator;
+use crate::ir::*;
+use crate::optimizer::stack_effect;
+use std::collections::HashMap;
+use std::fmt;
+
+/*
+ *  Well-formedness checks on `IrProgram`, meant to catch compiler bugs
+ *  before bytecode is emitted:
+ *
+ *  - `spans` and `types` are parallel to `instructions`
+ *  - jump targets and `entry_block` are blocks of the function
+ *  - `Call` names a function of the program
+ *  - the last block ends with a terminator instead of falling off the end
+ *    of the function (see `cfg` for fall-through between blocks)
+ *  - no instruction pops more values than the block has on the stack, and
+ *    every edge into a block carries the same stack depth
+ *
+ *  Stack depths are counted from the entry of the function, which starts
+ *  with its arguments on the stack, and only over blocks reachable from it.
+ *  Instructions after a terminator never run and are not checked.
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    /// `spans` or `types` are not as long as `instructions`.
+    MisalignedAnnotations,
+    MissingBlock(usize),
+    UndefinedFunction(String),
+    MissingTerminator,
+    StackUnderflow {
+        pops: usize,
+        depth: usize,
+    },
+    /// Edges into `block` disagree on the stack depth.
+    StackMismatch {
+        block: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyErrorKind::MisalignedAnnotations => {
+                write!(f, "spans or types are not parallel to the instructions")
+            }
+            VerifyErrorKind::MissingBlock(block) => write!(f, "block bb{block} does not exist"),
+            VerifyErrorKind::UndefinedFunction(name) => {
+                write!(f, "call to undefined function `{name}`")
+            }
+            VerifyErrorKind::MissingTerminator => {
+                write!(f, "last block falls off the end of the function")
+            }
+            VerifyErrorKind::StackUnderflow { pops, depth } => {
+                write!(f, "pops {pops} value(s) with {depth} on the stack")
+            }
+            VerifyErrorKind::StackMismatch {
+                block,
+                expected,
+                found,
+            } => write!(
+                f,
+                "reaches bb{block} with {found} value(s) on the stack instead of {expected}"
+            ),
+        }
+    }
+}
+
+/// A malformed `IrProgram`, located at a block and, when it is about one,
+/// an instruction of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    pub function: String,
+    pub block: usize,
+    pub instruction: Option<usize>,
+    pub kind: VerifyErrorKind,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "function `{}`, bb{}", self.function, self.block)?;
+        if let Some(instruction) = self.instruction {
+            write!(f, ", instruction {instruction}")?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Checks that `ir` is well formed, functions in layout order.
+pub fn verify(ir: &IrProgram) -> Result<(), VerifyError> {
+    let arities: HashMap<String, usize> = ir
+        .functions
+        .values()
+        .map(|function| (function.name.clone(), function.parameters.len()))
+        .collect();
+
+    for function in crate::ir_compiler::layout_order(ir) {
+        verify_function(function, &arities)?;
+    }
+    Ok(())
+}
+
+/// Panics with the error when `ir` is malformed after `stage`, in debug
+/// builds only.
+pub(crate) fn debug_verify(ir: &IrProgram, stage: &str) {
+    if cfg!(debug_assertions)
+        && let Err(error) = verify(ir)
+    {
+        panic!("malformed IR after {stage}: {error}");
+    }
+}
+
+fn verify_function(
+    function: &IrFunction,
+    arities: &HashMap<String, usize>,
+) -> Result<(), VerifyError> {
+    let error = |block, instruction, kind| VerifyError {
+        function: function.name.clone(),
+        block,
+        instruction,
+        kind,
+    };
+    let count = function.blocks.len();
+    if function.entry_block >= count {
+        return Err(error(
+            0,
+            None,
+            VerifyErrorKind::MissingBlock(function.entry_block),
+        ));
+    }
+
+    // structure of every block, reachable or not
+    for (index, block) in function.blocks.iter().enumerate() {
+        let length = block.instructions.len();
+        if block.spans.len() != length || block.types.len() != length {
+            return Err(error(index, None, VerifyErrorKind::MisalignedAnnotations));
+        }
+        let end = terminator(block).map_or(length, |end| end + 1);
+        for (position, instruction) in block.instructions[..end].iter().enumerate() {
+            let kind = match instruction {
+                IrInstruction::Jump(target) if *target >= count => {
+                    VerifyErrorKind::MissingBlock(*target)
+                }
+                IrInstruction::JumpIf(then_block, else_block)
+                    if *then_block >= count || *else_block >= count =>
+                {
+                    VerifyErrorKind::MissingBlock((*then_block).max(*else_block))
+                }
+                IrInstruction::Call(name) if !arities.contains_key(name) => {
+                    VerifyErrorKind::UndefinedFunction(name.clone())
+                }
+                _ => continue,
+            };
+            return Err(error(index, Some(position), kind));
+        }
+        if index + 1 == count && terminator(block).is_none() {
+            return Err(error(index, None, VerifyErrorKind::MissingTerminator));
+        }
+    }
+
+    // stack depth at the entry of each reached block
+    let mut depths: Vec<Option<usize>> = vec![None; count];
+    depths[function.entry_block] = Some(function.parameters.len());
+    let mut pending = vec![function.entry_block];
+    while let Some(index) = pending.pop() {
+        let block = &function.blocks[index];
+        let mut depth = depths[index].unwrap();
+        let end = terminator(block);
+        let live = end.map_or(block.instructions.len(), |end| end + 1);
+
+        for (position, instruction) in block.instructions[..live].iter().enumerate() {
+            let (pops, pushes) = match instruction {
+                IrInstruction::JumpIf(..) | IrInstruction::Return => (1, 0),
+                IrInstruction::Jump(_) | IrInstruction::Halt => (0, 0),
+                _ => stack_effect(instruction, arities).unwrap(),
+            };
+            if pops > depth {
+                return Err(error(
+                    index,
+                    Some(position),
+                    VerifyErrorKind::StackUnderflow { pops, depth },
+                ));
+            }
+            depth = depth - pops + pushes;
+        }
+
+        let successors = match end.map(|end| &block.instructions[end]) {
+            Some(IrInstruction::Jump(target)) => vec![*target],
+            Some(IrInstruction::JumpIf(then_block, else_block)) => vec![*then_block, *else_block],
+            Some(_) => vec![],
+            None => vec![index + 1],
+        };
+        for successor in successors {
+            match depths[successor] {
+                None => {
+                    depths[successor] = Some(depth);
+                    pending.push(successor);
+                }
+                Some(expected) if expected != depth => {
+                    return Err(error(
+                        index,
+                        end,
+                        VerifyErrorKind::StackMismatch {
+                            block: successor,
+                            expected,
+                            found: depth,
+                        },
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,20 +1,23 @@
-use crate::metadata::MAGIC;
+use crate::metadata::{MAGIC, Version};
 use crate::traits::DeserializationError;
 use crate::{traits::Deserializable, v1::deserialize::Deserializer as V1Deserializer};
 use vmo2_types::bytecode;
 
-pub fn deserialize(input: &Vec<u8>) -> Result<bytecode::ByteCode, DeserializationError> {
+pub fn deserialize(input: &[u8]) -> Result<bytecode::ByteCode, DeserializationError> {
+    if input.len() < 5 {
+        return Err(DeserializationError::InvalidMagicNumber);
+    }
     let magic_number: u32 = u32::from_le_bytes(input[0..4].try_into().unwrap());
     if magic_number != MAGIC {
         return Err(DeserializationError::InvalidMagicNumber);
     }
 
-    let version: u8 = input[4].try_into().unwrap();
+    let version: u8 = input[4];
     let code = Vec::from(&input[5..]);
 
+    // only V1 has a deserializer so far
     match version {
-        1 => V1Deserializer::new().deserialize(&code),
-        2 => todo!(),
+        Version::V1 => V1Deserializer::new().deserialize(&code),
         _ => Err(DeserializationError::InvalidVersion),
     }
 }
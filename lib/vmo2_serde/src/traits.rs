@@ -11,8 +11,27 @@ pub enum DeserializationError {
     InvalidMagicNumber,
     InvalidVersion,
     InvalidByteCode,
+    /// A list or map literal nested deeper than `MAX_LITERAL_DEPTH`.
+    NestingTooDeep,
+    /// A map literal with a key `BuildMap` would reject, or the same key
+    /// twice.
+    InvalidMapKey,
 }
 
+impl std::fmt::Display for DeserializationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeserializationError::InvalidMagicNumber => write!(f, "not a vmo2 bytecode file"),
+            DeserializationError::InvalidVersion => write!(f, "unsupported bytecode version"),
+            DeserializationError::InvalidByteCode => write!(f, "malformed bytecode"),
+            DeserializationError::NestingTooDeep => write!(f, "literal nested too deeply"),
+            DeserializationError::InvalidMapKey => write!(f, "invalid or duplicate map key"),
+        }
+    }
+}
+
+impl std::error::Error for DeserializationError {}
+
 pub trait Deserializable {
-    fn deserialize(&self, input: &Vec<u8>) -> Result<bytecode::ByteCode, DeserializationError>;
+    fn deserialize(&self, input: &[u8]) -> Result<bytecode::ByteCode, DeserializationError>;
 }
@@ -11,16 +11,23 @@ pub mod OPCODE {
     pub const DUP: u8 = 8;
     pub const POP: u8 = 9;
     pub const SWAP: u8 = 10;
+    pub const COLLECTION: u8 = 11;
 
     pub const LITERAL_UINT: u8 = 0;
     pub const LITERAL_BOOL: u8 = 1;
     pub const LITERAL_STRING: u8 = 2;
     pub const LITERAL_NULL: u8 = 3;
+    pub const LITERAL_INT: u8 = 4;
+    pub const LITERAL_FLOAT: u8 = 5;
+    pub const LITERAL_LIST: u8 = 6;
+    pub const LITERAL_MAP: u8 = 7;
 
     pub const ARITHMETIC_ADD: u8 = 0;
     pub const ARITHMETIC_SUB: u8 = 1;
     pub const ARITHMETIC_MUL: u8 = 2;
     pub const ARITHMETIC_DIV: u8 = 3;
+    pub const ARITHMETIC_MOD: u8 = 4;
+    pub const ARITHMETIC_NEG: u8 = 5;
 
     pub const LOGIC_AND: u8 = 0;
     pub const LOGIC_OR: u8 = 1;
@@ -45,4 +52,14 @@ pub mod OPCODE {
     pub const FLOW_JUMP: u8 = 2;
     pub const FLOW_CALL: u8 = 3;
     pub const FLOW_RETURN: u8 = 4;
+
+    pub const COLLECTION_BUILD_LIST: u8 = 0;
+    pub const COLLECTION_GET: u8 = 1;
+    pub const COLLECTION_SET: u8 = 2;
+    pub const COLLECTION_LEN: u8 = 3;
+    pub const COLLECTION_PUSH: u8 = 4;
+    pub const COLLECTION_POP: u8 = 5;
+    pub const COLLECTION_BUILD_MAP: u8 = 6;
+    pub const COLLECTION_KEYS: u8 = 7;
+    pub const COLLECTION_CONTAINS: u8 = 8;
 }
@@ -10,34 +10,67 @@ use nom::{
     multi::{length_count, many1},
     sequence,
 };
+use std::collections::BTreeMap;
 use vmo2_types::bytecode;
 use vmo2_types::opcode::*;
 use vmo2_types::value::Value;
 
 pub struct Deserializer {}
 
+impl Default for Deserializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Deserializer {
     pub fn new() -> Self {
         Self {}
     }
 }
 
-fn parser(input: &[u8]) -> IResult<&[u8], Opcode> {
-    // * HALT
-    let halt_parser = combinator::value(Opcode::Halt, tag([OPCODE::HALT]));
+/// How deeply list and map literals may nest. Parsing recurses once per
+/// level, so without a bound a crafted file could overflow the stack.
+pub const MAX_LITERAL_DEPTH: usize = 128;
 
-    // * LITERAL
+/// A literal value: its type byte and payload, list elements recursively.
+fn value_parser(input: &[u8]) -> IResult<&[u8], Value> {
+    nested_value_parser(input, 0)
+}
+
+/// `value_parser` for a literal nested `depth` levels inside other literals.
+fn nested_value_parser(input: &[u8], depth: usize) -> IResult<&[u8], Value> {
+    if depth > MAX_LITERAL_DEPTH {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::TooLarge,
+        )));
+    }
+    let element_parser = |input| nested_value_parser(input, depth + 1);
     let int_literal_parser = sequence::preceded(
         tag([OPCODE::LITERAL_UINT]),
         combinator::map(bytes::complete::take(4u8), |bytes: &[u8]| {
-            Opcode::Literal(Value::UInt(u32::from_le_bytes(bytes.try_into().unwrap())))
+            Value::UInt(u32::from_le_bytes(bytes.try_into().unwrap()))
+        }),
+    );
+    let signed_int_literal_parser = sequence::preceded(
+        tag([OPCODE::LITERAL_INT]),
+        combinator::map(bytes::complete::take(8u8), |bytes: &[u8]| {
+            Value::Int(i64::from_le_bytes(bytes.try_into().unwrap()))
+        }),
+    );
+    let float_literal_parser = sequence::preceded(
+        tag([OPCODE::LITERAL_FLOAT]),
+        combinator::map(bytes::complete::take(8u8), |bytes: &[u8]| {
+            let bits = u64::from_le_bytes(bytes.try_into().unwrap());
+            Value::Float(f64::from_bits(bits))
         }),
     );
     let bool_literal_parser = sequence::preceded(
         tag([OPCODE::LITERAL_BOOL]),
         branch::alt((
-            combinator::value(Opcode::Literal(Value::Bool(true)), tag([1])),
-            combinator::value(Opcode::Literal(Value::Bool(false)), tag([0])),
+            combinator::value(Value::Bool(true), tag([1])),
+            combinator::value(Value::Bool(false), tag([0])),
         )),
     );
     let string_literal_parser = sequence::preceded(
@@ -51,20 +84,63 @@ fn parser(input: &[u8]) -> IResult<&[u8], Opcode> {
             ),
             |s| {
                 let vec_u8 = s.iter().map(|c| *c as u8).collect::<Vec<_>>();
-                Opcode::Literal(Value::String(String::from_utf8(vec_u8).unwrap()))
+                Value::String(String::from_utf8(vec_u8).unwrap())
             },
         ),
     );
-    let null_literal_parser =
-        combinator::value(Opcode::Literal(Value::Null), tag([OPCODE::LITERAL_NULL]));
+    let null_literal_parser = combinator::value(Value::Null, tag([OPCODE::LITERAL_NULL]));
+    let list_literal_parser = sequence::preceded(
+        tag([OPCODE::LITERAL_LIST]),
+        combinator::map(
+            length_count(
+                combinator::map(bytes::complete::take(4u8), |bytes: &[u8]| {
+                    u32::from_le_bytes(bytes.try_into().unwrap())
+                }),
+                element_parser,
+            ),
+            Value::List,
+        ),
+    );
+    let map_literal_parser = sequence::preceded(tag([OPCODE::LITERAL_MAP]), |input| {
+        let (rest, entries) = length_count(
+            combinator::map(bytes::complete::take(4u8), |bytes: &[u8]| {
+                u32::from_le_bytes(bytes.try_into().unwrap())
+            }),
+            sequence::pair(element_parser, element_parser),
+        )(input)?;
+        // keys must be ones `BuildMap` could have produced, each only once
+        let invalid_key =
+            || nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Verify));
+        let mut map = BTreeMap::new();
+        for (key, value) in entries {
+            let key = key.map_key().map_err(|_| invalid_key())?;
+            if map.insert(key, value).is_some() {
+                return Err(invalid_key());
+            }
+        }
+        Ok((rest, Value::Map(map)))
+    });
+
+    branch::alt((
+        bool_literal_parser,
+        int_literal_parser,
+        string_literal_parser,
+        null_literal_parser,
+        signed_int_literal_parser,
+        float_literal_parser,
+        list_literal_parser,
+        map_literal_parser,
+    ))(input)
+}
+
+fn parser(input: &[u8]) -> IResult<&[u8], Opcode> {
+    // * HALT
+    let halt_parser = combinator::value(Opcode::Halt, tag([OPCODE::HALT]));
+
+    // * LITERAL
     let literal_parser = sequence::preceded(
         tag([OPCODE::LITERAL]),
-        branch::alt((
-            bool_literal_parser,
-            int_literal_parser,
-            string_literal_parser,
-            null_literal_parser,
-        )),
+        combinator::map(value_parser, Opcode::Literal),
     );
 
     // * ARITHMETIC
@@ -87,6 +163,14 @@ fn parser(input: &[u8]) -> IResult<&[u8], Opcode> {
                 Opcode::Arithmetic(ArithmeticOpcode::Div),
                 tag([OPCODE::ARITHMETIC_DIV]),
             ),
+            combinator::value(
+                Opcode::Arithmetic(ArithmeticOpcode::Mod),
+                tag([OPCODE::ARITHMETIC_MOD]),
+            ),
+            combinator::value(
+                Opcode::Arithmetic(ArithmeticOpcode::Neg),
+                tag([OPCODE::ARITHMETIC_NEG]),
+            ),
         )),
     );
 
@@ -211,6 +295,57 @@ fn parser(input: &[u8]) -> IResult<&[u8], Opcode> {
     // * SWAP
     let swap_parser = combinator::value(Opcode::Swap, tag([OPCODE::SWAP]));
 
+    // * COLLECTION
+    let collection_parser = sequence::preceded(
+        tag([OPCODE::COLLECTION]),
+        branch::alt((
+            sequence::preceded(
+                tag([OPCODE::COLLECTION_BUILD_LIST]),
+                combinator::map(bytes::complete::take(4u8), |bytes: &[u8]| {
+                    Opcode::Collection(CollectionOpcode::BuildList(u32::from_le_bytes(
+                        bytes.try_into().unwrap(),
+                    )))
+                }),
+            ),
+            combinator::value(
+                Opcode::Collection(CollectionOpcode::Get),
+                tag([OPCODE::COLLECTION_GET]),
+            ),
+            combinator::value(
+                Opcode::Collection(CollectionOpcode::Set),
+                tag([OPCODE::COLLECTION_SET]),
+            ),
+            combinator::value(
+                Opcode::Collection(CollectionOpcode::Len),
+                tag([OPCODE::COLLECTION_LEN]),
+            ),
+            combinator::value(
+                Opcode::Collection(CollectionOpcode::Push),
+                tag([OPCODE::COLLECTION_PUSH]),
+            ),
+            combinator::value(
+                Opcode::Collection(CollectionOpcode::Pop),
+                tag([OPCODE::COLLECTION_POP]),
+            ),
+            sequence::preceded(
+                tag([OPCODE::COLLECTION_BUILD_MAP]),
+                combinator::map(bytes::complete::take(4u8), |bytes: &[u8]| {
+                    Opcode::Collection(CollectionOpcode::BuildMap(u32::from_le_bytes(
+                        bytes.try_into().unwrap(),
+                    )))
+                }),
+            ),
+            combinator::value(
+                Opcode::Collection(CollectionOpcode::Keys),
+                tag([OPCODE::COLLECTION_KEYS]),
+            ),
+            combinator::value(
+                Opcode::Collection(CollectionOpcode::Contains),
+                tag([OPCODE::COLLECTION_CONTAINS]),
+            ),
+        )),
+    );
+
     branch::alt((
         halt_parser,
         arithmetic_parser,
@@ -223,13 +358,20 @@ fn parser(input: &[u8]) -> IResult<&[u8], Opcode> {
         dup_parser,
         pop_parser,
         swap_parser,
+        collection_parser,
     ))(input)
 }
 
 impl Deserializable for Deserializer {
-    fn deserialize(&self, input: &Vec<u8>) -> Result<bytecode::ByteCode, DeserializationError> {
+    fn deserialize(&self, input: &[u8]) -> Result<bytecode::ByteCode, DeserializationError> {
         match many1(parser)(input) {
             Ok((_, opcode)) => Ok(bytecode::ByteCode::from(opcode)),
+            Err(nom::Err::Failure(e)) if e.code == nom::error::ErrorKind::TooLarge => {
+                Err(DeserializationError::NestingTooDeep)
+            }
+            Err(nom::Err::Failure(e)) if e.code == nom::error::ErrorKind::Verify => {
+                Err(DeserializationError::InvalidMapKey)
+            }
             Err(e) => {
                 eprintln!("error: {:?}", e);
                 Err(DeserializationError::InvalidByteCode)
@@ -15,24 +15,45 @@ pub fn get_opcode_byte(opcode: &Opcode) -> u8 {
         Opcode::Dup => OPCODE::DUP,
         Opcode::Pop => OPCODE::POP,
         Opcode::Swap => OPCODE::SWAP,
+        Opcode::Collection(_) => OPCODE::COLLECTION,
     }
 }
 
 pub fn get_literal_opcode_byte(value: &Value) -> u8 {
     match value {
         Value::UInt(_) => OPCODE::LITERAL_UINT,
+        Value::Int(_) => OPCODE::LITERAL_INT,
+        Value::Float(_) => OPCODE::LITERAL_FLOAT,
         Value::Bool(_) => OPCODE::LITERAL_BOOL,
         Value::String(_) => OPCODE::LITERAL_STRING,
+        Value::List(_) => OPCODE::LITERAL_LIST,
+        Value::Map(_) => OPCODE::LITERAL_MAP,
         Value::Null => OPCODE::LITERAL_NULL,
     }
 }
 
+pub fn get_collection_opcode_byte(opcode: &CollectionOpcode) -> u8 {
+    match opcode {
+        CollectionOpcode::BuildList(_) => OPCODE::COLLECTION_BUILD_LIST,
+        CollectionOpcode::Get => OPCODE::COLLECTION_GET,
+        CollectionOpcode::Set => OPCODE::COLLECTION_SET,
+        CollectionOpcode::Len => OPCODE::COLLECTION_LEN,
+        CollectionOpcode::Push => OPCODE::COLLECTION_PUSH,
+        CollectionOpcode::Pop => OPCODE::COLLECTION_POP,
+        CollectionOpcode::BuildMap(_) => OPCODE::COLLECTION_BUILD_MAP,
+        CollectionOpcode::Keys => OPCODE::COLLECTION_KEYS,
+        CollectionOpcode::Contains => OPCODE::COLLECTION_CONTAINS,
+    }
+}
+
 pub fn get_arithmetic_opcode_byte(opcode: &ArithmeticOpcode) -> u8 {
     match opcode {
         ArithmeticOpcode::Add => OPCODE::ARITHMETIC_ADD,
         ArithmeticOpcode::Sub => OPCODE::ARITHMETIC_SUB,
         ArithmeticOpcode::Mul => OPCODE::ARITHMETIC_MUL,
         ArithmeticOpcode::Div => OPCODE::ARITHMETIC_DIV,
+        ArithmeticOpcode::Mod => OPCODE::ARITHMETIC_MOD,
+        ArithmeticOpcode::Neg => OPCODE::ARITHMETIC_NEG,
     }
 }
 
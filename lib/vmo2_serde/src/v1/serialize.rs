@@ -2,7 +2,7 @@ use crate::metadata::MAGIC;
 use crate::traits::Serializable;
 use crate::v1::opcode::*;
 use vmo2_types::bytecode;
-use vmo2_types::opcode::{FlowOpcode, Opcode};
+use vmo2_types::opcode::{CollectionOpcode, FlowOpcode, Opcode};
 use vmo2_types::value::Value;
 
 pub struct Serializer {
@@ -10,6 +10,12 @@ pub struct Serializer {
     version: u8,
 }
 
+impl Default for Serializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Serializer {
     pub fn new() -> Self {
         Self {
@@ -32,24 +38,7 @@ impl Serializable for Serializer {
             match opcode {
                 Opcode::Literal(value) => {
                     data.push(get_opcode_byte(opcode));
-                    match value {
-                        Value::UInt(v) => {
-                            data.push(get_literal_opcode_byte(value));
-                            data.extend(v.to_le_bytes());
-                        }
-                        Value::Bool(v) => {
-                            data.push(get_literal_opcode_byte(value));
-                            data.push(if *v { 1 } else { 0 });
-                        }
-                        Value::String(v) => {
-                            data.push(get_literal_opcode_byte(value));
-                            data.extend((v.len() as u16).to_le_bytes());
-                            data.extend(v.as_bytes());
-                        }
-                        Value::Null => {
-                            data.push(get_literal_opcode_byte(value));
-                        }
-                    }
+                    serialize_value(value, &mut data);
                 }
                 Opcode::Arithmetic(arith) => {
                     data.push(get_opcode_byte(opcode));
@@ -102,9 +91,48 @@ impl Serializable for Serializer {
                 Opcode::Swap => {
                     data.push(get_opcode_byte(opcode));
                 }
+                Opcode::Collection(collection) => {
+                    data.push(get_opcode_byte(opcode));
+                    data.push(get_collection_opcode_byte(collection));
+                    if let CollectionOpcode::BuildList(len) | CollectionOpcode::BuildMap(len) =
+                        collection
+                    {
+                        data.extend(len.to_le_bytes());
+                    }
+                }
             }
         }
 
         data
     }
 }
+
+/// Literal type byte followed by the payload, list elements and map entries
+/// recursively.
+fn serialize_value(value: &Value, data: &mut Vec<u8>) {
+    data.push(get_literal_opcode_byte(value));
+    match value {
+        Value::UInt(v) => data.extend(v.to_le_bytes()),
+        Value::Int(v) => data.extend(v.to_le_bytes()),
+        Value::Float(v) => data.extend(v.to_bits().to_le_bytes()),
+        Value::Bool(v) => data.push(if *v { 1 } else { 0 }),
+        Value::String(v) => {
+            data.extend((v.len() as u16).to_le_bytes());
+            data.extend(v.as_bytes());
+        }
+        Value::List(items) => {
+            data.extend((items.len() as u32).to_le_bytes());
+            for item in items {
+                serialize_value(item, data);
+            }
+        }
+        Value::Map(entries) => {
+            data.extend((entries.len() as u32).to_le_bytes());
+            for (key, value) in entries {
+                serialize_value(key, data);
+                serialize_value(value, data);
+            }
+        }
+        Value::Null => {}
+    }
+}
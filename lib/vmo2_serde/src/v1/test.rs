@@ -1,5 +1,5 @@
 #[cfg(test)]
-mod test {
+mod tests {
     use core::panic;
 
     use crate::deserialize::deserialize;
@@ -18,6 +18,8 @@ mod test {
             Opcode::Arithmetic(ArithmeticOpcode::Sub),
             Opcode::Arithmetic(ArithmeticOpcode::Mul),
             Opcode::Arithmetic(ArithmeticOpcode::Div),
+            Opcode::Arithmetic(ArithmeticOpcode::Mod),
+            Opcode::Arithmetic(ArithmeticOpcode::Neg),
             Opcode::Logic(LogicOpcode::And),
             Opcode::Logic(LogicOpcode::Xor),
             Opcode::Logic(LogicOpcode::Or),
@@ -32,6 +34,15 @@ mod test {
             Opcode::Memory(MemoryOpcode::Store),
             Opcode::IO(IOOpcode::Print),
             Opcode::IO(IOOpcode::Scan),
+            Opcode::Collection(CollectionOpcode::BuildList(3)),
+            Opcode::Collection(CollectionOpcode::Get),
+            Opcode::Collection(CollectionOpcode::Set),
+            Opcode::Collection(CollectionOpcode::Len),
+            Opcode::Collection(CollectionOpcode::Push),
+            Opcode::Collection(CollectionOpcode::Pop),
+            Opcode::Collection(CollectionOpcode::BuildMap(2)),
+            Opcode::Collection(CollectionOpcode::Keys),
+            Opcode::Collection(CollectionOpcode::Contains),
         ]);
 
         let serializer = Serializer::new();
@@ -51,6 +62,25 @@ mod test {
             Opcode::Literal(Value::Bool(true)),
             Opcode::Literal(Value::UInt(32)),
             Opcode::Literal(Value::String("abc".to_string())),
+            Opcode::Literal(Value::Null),
+            Opcode::Literal(Value::Int(-42)),
+            Opcode::Literal(Value::Int(i64::MAX)),
+            Opcode::Literal(Value::Float(-1.5e300)),
+            Opcode::Literal(Value::Float(f64::NAN)),
+            Opcode::Literal(Value::List(vec![])),
+            Opcode::Literal(Value::List(vec![
+                Value::UInt(1),
+                Value::String("x".to_string()),
+                Value::List(vec![Value::Null]),
+            ])),
+            Opcode::Literal(Value::Map(
+                [
+                    (Value::String("a".to_string()), Value::UInt(1)),
+                    (Value::Int(-1), Value::List(vec![])),
+                ]
+                .into_iter()
+                .collect(),
+            )),
         ]);
 
         let data = serialize(Version::V1, &bytecode);
@@ -61,6 +91,131 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_invalid_input() {
+        use crate::traits::DeserializationError;
+
+        assert_eq!(
+            deserialize(&[]),
+            Err(DeserializationError::InvalidMagicNumber)
+        );
+        assert_eq!(
+            deserialize(&[0, 0, 0, 0, 1]),
+            Err(DeserializationError::InvalidMagicNumber)
+        );
+        assert_eq!(
+            DeserializationError::InvalidByteCode.to_string(),
+            "malformed bytecode"
+        );
+
+        // a known magic number with a version that has no deserializer
+        for version in [0, 2, 255] {
+            let mut data = crate::metadata::MAGIC.to_le_bytes().to_vec();
+            data.extend([version, 0]);
+            assert_eq!(
+                deserialize(&data),
+                Err(DeserializationError::InvalidVersion)
+            );
+        }
+        assert_eq!(
+            DeserializationError::InvalidVersion.to_string(),
+            "unsupported bytecode version"
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested_literal() {
+        use crate::traits::DeserializationError;
+        use crate::v1::constants::OPCODE;
+        use crate::v1::deserialize::MAX_LITERAL_DEPTH;
+        use vmo2_types::{bytecode::ByteCode, opcode::Opcode, value::Value};
+
+        // `depth` single element lists around a null, then a halt
+        let nested = |depth: usize| {
+            let mut data = serialize(Version::V1, &ByteCode::from(vec![]));
+            data.push(OPCODE::LITERAL);
+            for _ in 0..depth {
+                data.push(OPCODE::LITERAL_LIST);
+                data.extend(1u32.to_le_bytes());
+            }
+            data.extend([OPCODE::LITERAL_NULL, OPCODE::HALT]);
+            data
+        };
+
+        let mut value = Value::Null;
+        for _ in 0..MAX_LITERAL_DEPTH {
+            value = Value::List(vec![value]);
+        }
+        assert_eq!(
+            deserialize(&nested(MAX_LITERAL_DEPTH)),
+            Ok(ByteCode::from(vec![Opcode::Literal(value), Opcode::Halt]))
+        );
+
+        assert_eq!(
+            deserialize(&nested(MAX_LITERAL_DEPTH + 1)),
+            Err(DeserializationError::NestingTooDeep)
+        );
+        // far past what the stack could take when parsed recursively
+        assert_eq!(
+            deserialize(&nested(200_000)),
+            Err(DeserializationError::NestingTooDeep)
+        );
+    }
+
+    #[test]
+    fn test_map_literal_keys() {
+        use crate::traits::DeserializationError;
+        use std::collections::BTreeMap;
+        use vmo2_types::{bytecode::ByteCode, opcode::Opcode, value::Value};
+
+        // the serializer writes whatever keys it is given
+        let roundtrip = |entries: Vec<(Value, Value)>| {
+            let map = Value::Map(entries.into_iter().collect());
+            deserialize(&serialize(
+                Version::V1,
+                &ByteCode::from(vec![Opcode::Literal(map), Opcode::Halt]),
+            ))
+        };
+
+        // integer keys are canonicalized like `BuildMap` does
+        let expected =
+            BTreeMap::from([(Value::UInt(5), Value::Null), (Value::Int(-1), Value::Null)]);
+        assert_eq!(
+            roundtrip(vec![
+                (Value::Int(5), Value::Null),
+                (Value::Int(-1), Value::Null),
+            ]),
+            Ok(ByteCode::from(vec![
+                Opcode::Literal(Value::Map(expected)),
+                Opcode::Halt
+            ]))
+        );
+
+        for key in [
+            Value::Float(1.0),
+            Value::Bool(true),
+            Value::Null,
+            Value::List(vec![]),
+        ] {
+            assert_eq!(
+                roundtrip(vec![(key, Value::Null)]),
+                Err(DeserializationError::InvalidMapKey)
+            );
+        }
+        // `Int(1)` and `UInt(1)` are the same key
+        assert_eq!(
+            roundtrip(vec![
+                (Value::UInt(1), Value::Null),
+                (Value::Int(1), Value::Bool(true)),
+            ]),
+            Err(DeserializationError::InvalidMapKey)
+        );
+        assert_eq!(
+            DeserializationError::InvalidMapKey.to_string(),
+            "invalid or duplicate map key"
+        );
+    }
+
     #[test]
     fn test_quickcheck_v1() {
         /**
@@ -7,6 +7,12 @@ pub struct ByteCode {
     pub opcodes: Vec<Opcode>,
 }
 
+impl Default for ByteCode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ByteCode {
     pub fn new() -> Self {
         Self { opcodes: vec![] }
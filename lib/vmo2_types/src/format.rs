@@ -0,0 +1,145 @@
+use std::fmt::Write;
+
+use crate::opcode::*;
+use crate::value::Value;
+
+/// Mnemonic of `opcode` as written in `.vasm`, without its operand.
+pub fn mnemonic(opcode: &Opcode) -> &'static str {
+    match opcode {
+        Opcode::Halt => "halt",
+        Opcode::Literal(_) => "push",
+        Opcode::Arithmetic(op) => match op {
+            ArithmeticOpcode::Add => "add",
+            ArithmeticOpcode::Sub => "sub",
+            ArithmeticOpcode::Mul => "mul",
+            ArithmeticOpcode::Div => "div",
+            ArithmeticOpcode::Mod => "mod",
+            ArithmeticOpcode::Neg => "neg",
+        },
+        Opcode::Logic(op) => match op {
+            LogicOpcode::And => "and",
+            LogicOpcode::Xor => "xor",
+            LogicOpcode::Or => "or",
+            LogicOpcode::Not => "not",
+        },
+        Opcode::Comparison(op) => match op {
+            ComparisonOpcode::Eq => "eq",
+            ComparisonOpcode::Ne => "ne",
+            ComparisonOpcode::Lt => "lt",
+            ComparisonOpcode::Le => "le",
+            ComparisonOpcode::Gt => "gt",
+            ComparisonOpcode::Ge => "ge",
+        },
+        Opcode::Memory(op) => match op {
+            MemoryOpcode::Load => "load",
+            MemoryOpcode::Store => "store",
+        },
+        Opcode::IO(op) => match op {
+            IOOpcode::Print => "print",
+            IOOpcode::Scan => "scan",
+        },
+        Opcode::Flow(op) => match op {
+            FlowOpcode::JumpIfFalse(_) => "jump_if_false",
+            FlowOpcode::JumpIfTrue(_) => "jump_if_true",
+            FlowOpcode::Jump(_) => "jump",
+            FlowOpcode::Call(_) => "call",
+            FlowOpcode::Return => "return",
+        },
+        Opcode::Collection(op) => match op {
+            CollectionOpcode::BuildList(_) => "build_list",
+            CollectionOpcode::Get => "get",
+            CollectionOpcode::Set => "set",
+            CollectionOpcode::Len => "len",
+            CollectionOpcode::Push => "list_push",
+            CollectionOpcode::Pop => "list_pop",
+            CollectionOpcode::BuildMap(_) => "build_map",
+            CollectionOpcode::Keys => "keys",
+            CollectionOpcode::Contains => "contains",
+        },
+        Opcode::Dup => "dup",
+        Opcode::Pop => "pop",
+        Opcode::Swap => "swap",
+    }
+}
+
+/// Address a flow opcode transfers control to.
+pub fn flow_target(opcode: &Opcode) -> Option<u32> {
+    match opcode {
+        Opcode::Flow(
+            FlowOpcode::JumpIfFalse(target)
+            | FlowOpcode::JumpIfTrue(target)
+            | FlowOpcode::Jump(target)
+            | FlowOpcode::Call(target),
+        ) => Some(*target),
+        _ => None,
+    }
+}
+
+/// Writes `value` in `.vasm` literal syntax.
+pub fn write_literal(out: &mut String, value: &Value) {
+    match value {
+        Value::UInt(v) => write!(out, "{v}").unwrap(),
+        Value::Int(v) => write!(out, "{v}i").unwrap(),
+        // `{:?}` keeps whole floats recognisable: `1.0`, `1e300`, `NaN`
+        Value::Float(v) => write!(out, "{v:?}").unwrap(),
+        Value::Bool(v) => write!(out, "{v}").unwrap(),
+        Value::String(s) => {
+            out.push('"');
+            for c in s.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c if c.is_control() => write!(out, "\\u{{{:x}}}", c as u32).unwrap(),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+        }
+        Value::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_literal(out, item);
+            }
+            out.push(']');
+        }
+        Value::Map(entries) => {
+            out.push('{');
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_literal(out, key);
+                out.push_str(": ");
+                write_literal(out, value);
+            }
+            out.push('}');
+        }
+        Value::Null => out.push_str("null"),
+    }
+}
+
+/// `opcode` as a line of `.vasm`, with flow targets as plain addresses.
+pub fn format_instruction(opcode: &Opcode) -> String {
+    let mut out = mnemonic(opcode).to_string();
+    match opcode {
+        Opcode::Literal(value) => {
+            out.push(' ');
+            write_literal(&mut out, value);
+        }
+        Opcode::Collection(CollectionOpcode::BuildList(n) | CollectionOpcode::BuildMap(n)) => {
+            write!(out, " {n}").unwrap()
+        }
+        _ => {
+            if let Some(target) = flow_target(opcode) {
+                write!(out, " {target}").unwrap();
+            }
+        }
+    }
+    out
+}
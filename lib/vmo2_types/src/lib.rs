@@ -1,4 +1,6 @@
 pub mod bytecode;
+pub mod format;
 pub mod opcode;
+pub mod source_map;
 mod test;
 pub mod value;
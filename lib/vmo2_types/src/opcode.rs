@@ -13,6 +13,7 @@ pub enum Opcode {
     Memory(MemoryOpcode),
     IO(IOOpcode),
     Flow(FlowOpcode),
+    Collection(CollectionOpcode),
     Dup,
     Pop,
     Swap,
@@ -24,6 +25,9 @@ pub enum ArithmeticOpcode {
     Sub,
     Mul,
     Div,
+    Mod,
+    /// Unary minus.
+    Neg,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -65,10 +69,40 @@ pub enum FlowOpcode {
     Return,
 }
 
+/*
+ *  Collections are values: the mutating opcodes consume the collection and
+ *  push the updated one, which the compiler stores back into the variable.
+ */
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CollectionOpcode {
+    /// Pops that many values, the first element deepest, and pushes a list.
+    BuildList(u32),
+    /// Pops the index, then the collection, and pushes the element.
+    Get,
+    /// Pops the value, the index and the collection, and pushes the updated
+    /// collection.
+    Set,
+    /// Pops a collection or string and pushes its length.
+    Len,
+    /// Pops the value, then the list, and pushes the list with the value
+    /// appended.
+    Push,
+    /// Pops a list and pushes it without its last element, then that element.
+    Pop,
+    /// Pops that many key/value pairs, the first pair deepest with its key
+    /// below its value, and pushes a map. Later duplicate keys win.
+    BuildMap(u32),
+    /// Pops a map and pushes the list of its keys.
+    Keys,
+    /// Pops the key or element, then the collection, and pushes whether the
+    /// collection contains it.
+    Contains,
+}
+
 impl Arbitrary for Opcode {
     fn arbitrary(g: &mut Gen) -> Self {
         let mut rng = thread_rng();
-        let value = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]
+        let value = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]
             .choose(&mut rng)
             .unwrap();
 
@@ -84,11 +118,31 @@ impl Arbitrary for Opcode {
             9 => Opcode::Dup,
             10 => Opcode::Pop,
             11 => Opcode::Swap,
+            12 => Opcode::Collection(CollectionOpcode::arbitrary(g)),
             _ => unreachable!(),
         }
     }
 }
 
+impl Arbitrary for CollectionOpcode {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let len = u32::arbitrary(g);
+        g.choose(&[
+            CollectionOpcode::BuildList(len),
+            CollectionOpcode::Get,
+            CollectionOpcode::Set,
+            CollectionOpcode::Len,
+            CollectionOpcode::Push,
+            CollectionOpcode::Pop,
+            CollectionOpcode::BuildMap(len),
+            CollectionOpcode::Keys,
+            CollectionOpcode::Contains,
+        ])
+        .unwrap()
+        .clone()
+    }
+}
+
 impl Arbitrary for ArithmeticOpcode {
     fn arbitrary(g: &mut Gen) -> Self {
         g.choose(&[
@@ -96,6 +150,8 @@ impl Arbitrary for ArithmeticOpcode {
             ArithmeticOpcode::Sub,
             ArithmeticOpcode::Mul,
             ArithmeticOpcode::Div,
+            ArithmeticOpcode::Mod,
+            ArithmeticOpcode::Neg,
         ])
         .unwrap()
         .clone()
@@ -0,0 +1,34 @@
+/// A position in the source a program was compiled from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Maps every opcode of a `ByteCode` back to the statement it was compiled
+/// from. `locations[pc]` is `None` for compiler-generated code such as the
+/// final `Halt`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    pub locations: Vec<Option<SourceLocation>>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn location(&self, pc: usize) -> Option<SourceLocation> {
+        self.locations.get(pc).copied().flatten()
+    }
+
+    /// Addresses where execution enters `line`: the first opcode of every run
+    /// of consecutive opcodes mapped to it.
+    pub fn line_starts(&self, line: usize) -> Vec<usize> {
+        let on_line = |pc: usize| self.location(pc).is_some_and(|loc| loc.line == line);
+
+        (0..self.locations.len())
+            .filter(|&pc| on_line(pc) && (pc == 0 || !on_line(pc - 1)))
+            .collect()
+    }
+}
@@ -2,48 +2,222 @@
 mod tests {
     use crate::bytecode::ByteCode;
     use crate::opcode::*;
-    use crate::value::Value;
+    use crate::value::{Value, ValueError};
     use quickcheck_macros::quickcheck;
 
     #[test]
     fn test_value_arithmetic() {
-        assert_eq!(Value::UInt(5) + Value::UInt(3), Value::UInt(8));
+        assert_eq!(Value::UInt(5) + Value::UInt(3), Ok(Value::UInt(8)));
         assert_eq!(
             Value::String("hello".to_string()) + Value::String(" world".to_string()),
-            Value::String("hello world".to_string())
+            Ok(Value::String("hello world".to_string()))
         );
-        assert_eq!(Value::UInt(5) - Value::UInt(3), Value::UInt(2));
-        assert_eq!(Value::UInt(5) * Value::UInt(3), Value::UInt(15));
-        assert_eq!(Value::UInt(6) / Value::UInt(2), Value::UInt(3));
+        assert_eq!(Value::UInt(5) - Value::UInt(3), Ok(Value::UInt(2)));
+        assert_eq!(Value::UInt(5) * Value::UInt(3), Ok(Value::UInt(15)));
+        assert_eq!(Value::UInt(6) / Value::UInt(2), Ok(Value::UInt(3)));
+        assert_eq!(Value::UInt(7) % Value::UInt(3), Ok(Value::UInt(1)));
+    }
+
+    #[test]
+    fn test_value_arithmetic_errors() {
+        assert_eq!(
+            Value::String("a".to_string()) - Value::UInt(1),
+            Err(ValueError::TypeMismatch {
+                operation: "sub",
+                lhs: "string",
+                rhs: Some("uint"),
+            })
+        );
+        assert_eq!(
+            Value::Bool(true) + Value::UInt(1),
+            Err(ValueError::TypeMismatch {
+                operation: "add",
+                lhs: "bool",
+                rhs: Some("uint"),
+            })
+        );
+        assert_eq!(
+            Value::UInt(1) / Value::UInt(0),
+            Err(ValueError::DivisionByZero)
+        );
+        assert_eq!(
+            Value::UInt(1) % Value::UInt(0),
+            Err(ValueError::DivisionByZero)
+        );
+        assert_eq!(
+            Value::String("a".to_string()) % Value::UInt(2),
+            Err(ValueError::TypeMismatch {
+                operation: "mod",
+                lhs: "string",
+                rhs: Some("uint"),
+            })
+        );
+        assert_eq!(
+            Value::Int(i64::MAX) + Value::UInt(1),
+            Err(ValueError::Overflow)
+        );
+        assert_eq!(
+            Value::Int(i64::MIN) / Value::Int(-1),
+            Err(ValueError::Overflow)
+        );
+        assert_eq!(-Value::Int(i64::MIN), Err(ValueError::Overflow));
+        assert_eq!(
+            Value::Int(1) % Value::Int(0),
+            Err(ValueError::DivisionByZero)
+        );
+        assert_eq!(
+            -Value::Bool(true),
+            Err(ValueError::TypeMismatch {
+                operation: "neg",
+                lhs: "bool",
+                rhs: None,
+            })
+        );
+        assert_eq!(
+            Value::Float(1.0) + Value::String("a".to_string()),
+            Err(ValueError::TypeMismatch {
+                operation: "add",
+                lhs: "float",
+                rhs: Some("string"),
+            })
+        );
+        assert_eq!(
+            !Value::Null,
+            Err(ValueError::TypeMismatch {
+                operation: "not",
+                lhs: "null",
+                rhs: None,
+            })
+        );
+        assert!(
+            Value::UInt(1)
+                .compare(&Value::String("1".to_string()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_numeric_promotion() {
+        // uint results that leave the u32 range widen to int
+        assert_eq!(Value::UInt(1) - Value::UInt(2), Ok(Value::Int(-1)));
+        assert_eq!(
+            Value::UInt(u32::MAX) + Value::UInt(1),
+            Ok(Value::Int(u32::MAX as i64 + 1))
+        );
+        assert_eq!(
+            Value::UInt(u32::MAX) * Value::UInt(2),
+            Ok(Value::Int(u32::MAX as i64 * 2))
+        );
+        // int stays int even when the result would fit a uint
+        assert_eq!(Value::Int(-1) + Value::UInt(3), Ok(Value::Int(2)));
+        assert_eq!(Value::Int(-7) / Value::Int(2), Ok(Value::Int(-3)));
+        assert_eq!(Value::Int(-7) % Value::Int(2), Ok(Value::Int(-1)));
+        // any float makes the result a float
+        assert_eq!(Value::UInt(1) + Value::Float(0.5), Ok(Value::Float(1.5)));
+        assert_eq!(Value::Float(3.0) - Value::Int(-1), Ok(Value::Float(4.0)));
+        assert_eq!(Value::UInt(1) / Value::Float(4.0), Ok(Value::Float(0.25)));
+        assert_eq!(
+            Value::Float(1.0) / Value::UInt(0),
+            Ok(Value::Float(f64::INFINITY))
+        );
+        assert_eq!(-Value::UInt(3), Ok(Value::Int(-3)));
+        assert_eq!(-Value::Float(0.5), Ok(Value::Float(-0.5)));
+    }
+
+    #[test]
+    fn test_numeric_comparison() {
+        use std::cmp::Ordering;
+
+        assert!(Value::UInt(1).equals(&Value::Float(1.0)));
+        assert!(Value::Int(-1).equals(&Value::Float(-1.0)));
+        assert!(!Value::UInt(1).equals(&Value::Int(2)));
+        assert!(!Value::Float(f64::NAN).equals(&Value::Float(f64::NAN)));
+        assert!(!Value::UInt(1).equals(&Value::String("1".to_string())));
+        assert_eq!(
+            Value::Int(-1).compare(&Value::UInt(0)),
+            Ok(Some(Ordering::Less))
+        );
+        assert_eq!(
+            Value::Float(2.5).compare(&Value::Int(2)),
+            Ok(Some(Ordering::Greater))
+        );
+        assert_eq!(Value::Float(f64::NAN).compare(&Value::UInt(0)), Ok(None));
+
+        // structural equality keeps variants apart and NaN equal to itself
+        assert_ne!(Value::UInt(1), Value::Int(1));
+        assert_eq!(Value::Float(f64::NAN), Value::Float(f64::NAN));
     }
 
     #[test]
     fn test_value_logic() {
-        assert_eq!(!Value::Bool(true), Value::Bool(false));
-        assert_eq!(!Value::Bool(false), Value::Bool(true));
-        assert_eq!(Value::Bool(true).and(Value::Bool(true)), Value::Bool(true));
+        assert_eq!(!Value::Bool(true), Ok(Value::Bool(false)));
+        assert_eq!(!Value::Bool(false), Ok(Value::Bool(true)));
+        assert_eq!(
+            Value::Bool(true).and(Value::Bool(true)),
+            Ok(Value::Bool(true))
+        );
         assert_eq!(
             Value::Bool(true).and(Value::Bool(false)),
-            Value::Bool(false)
+            Ok(Value::Bool(false))
         );
         assert_eq!(
             Value::Bool(false).and(Value::Bool(false)),
-            Value::Bool(false)
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            Value::Bool(true).or(Value::Bool(true)),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            Value::Bool(true).or(Value::Bool(false)),
+            Ok(Value::Bool(true))
         );
-        assert_eq!(Value::Bool(true).or(Value::Bool(true)), Value::Bool(true));
-        assert_eq!(Value::Bool(true).or(Value::Bool(false)), Value::Bool(true));
         assert_eq!(
             Value::Bool(false).or(Value::Bool(false)),
-            Value::Bool(false)
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            Value::Bool(true).xor(Value::Bool(true)),
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            Value::Bool(true).xor(Value::Bool(false)),
+            Ok(Value::Bool(true))
         );
-        assert_eq!(Value::Bool(true).xor(Value::Bool(true)), Value::Bool(false));
-        assert_eq!(Value::Bool(true).xor(Value::Bool(false)), Value::Bool(true));
         assert_eq!(
             Value::Bool(false).xor(Value::Bool(false)),
-            Value::Bool(false)
+            Ok(Value::Bool(false))
         );
     }
 
+    #[test]
+    fn test_value_display() {
+        assert_eq!(Value::UInt(42).to_string(), "42");
+        assert_eq!(Value::Bool(false).to_string(), "false");
+        assert_eq!(Value::String("hi".to_string()).to_string(), "hi");
+        assert_eq!(Value::Null.to_string(), "null");
+        assert_eq!(Value::Int(-3).to_string(), "-3");
+        assert_eq!(Value::Float(1.0).to_string(), "1.0");
+        assert_eq!(Value::Float(0.25).to_string(), "0.25");
+    }
+
+    #[test]
+    fn test_source_map_line_starts() {
+        use crate::source_map::{SourceLocation, SourceMap};
+
+        let at = |line| Some(SourceLocation { line, column: 1 });
+        let map = SourceMap {
+            locations: vec![at(1), at(1), at(2), at(3), at(2), at(2), None],
+        };
+
+        assert_eq!(map.line_starts(1), vec![0]);
+        assert_eq!(map.line_starts(2), vec![2, 4]);
+        assert_eq!(map.line_starts(4), Vec::<usize>::new());
+        assert_eq!(map.location(3), at(3));
+        assert_eq!(map.location(6), None);
+        assert_eq!(map.location(7), None);
+    }
+
     #[test]
     fn test_bytecode_creation() {
         let mut bytecode = ByteCode::new();
@@ -61,10 +235,101 @@ mod tests {
         assert_eq!(bytecode.opcodes, opcodes);
     }
 
+    #[test]
+    fn test_list_operations() {
+        let list = Value::List(vec![Value::UInt(1), Value::UInt(2)]);
+
+        assert_eq!(list.get(&Value::UInt(1)), Ok(Value::UInt(2)));
+        assert_eq!(
+            list.get(&Value::Int(-1)),
+            Err(ValueError::IndexOutOfBounds { index: -1, len: 2 })
+        );
+        assert_eq!(
+            list.get(&Value::UInt(2)),
+            Err(ValueError::IndexOutOfBounds { index: 2, len: 2 })
+        );
+        assert!(matches!(
+            list.get(&Value::Bool(true)),
+            Err(ValueError::TypeMismatch { .. })
+        ));
+        assert_eq!(list.len(), Ok(2));
+        assert_eq!(Value::String("héllo".to_string()).len(), Ok(5));
+
+        let list = list.set(&Value::UInt(0), Value::Null).unwrap();
+        assert_eq!(list, Value::List(vec![Value::Null, Value::UInt(2)]));
+
+        let list = list.push(Value::Bool(true)).unwrap();
+        let (list, last) = list.pop().unwrap();
+        assert_eq!(last, Value::Bool(true));
+        assert_eq!(list.len(), Ok(2));
+        assert_eq!(Value::List(vec![]).pop(), Err(ValueError::EmptyList));
+
+        assert_eq!(list.to_string(), "[null, 2]");
+    }
+
+    #[test]
+    fn test_map_operations() {
+        let key = |s: &str| Value::String(s.to_string());
+        let map = Value::Map(Default::default())
+            .set(&key("b"), Value::UInt(2))
+            .unwrap()
+            .set(&Value::Int(1), Value::UInt(1))
+            .unwrap()
+            .set(&key("a"), Value::UInt(0))
+            .unwrap();
+
+        // integer keys are canonical, `Int(1)` and `UInt(1)` are one key
+        assert_eq!(map.get(&Value::UInt(1)), Ok(Value::UInt(1)));
+        assert_eq!(map.get(&key("b")), Ok(Value::UInt(2)));
+        assert_eq!(
+            map.get(&key("c")),
+            Err(ValueError::KeyNotFound("c".to_string()))
+        );
+        assert!(matches!(
+            map.get(&Value::Float(1.0)),
+            Err(ValueError::TypeMismatch { .. })
+        ));
+        assert_eq!(map.contains(&Value::Int(1)), Ok(true));
+        assert_eq!(map.contains(&key("z")), Ok(false));
+        assert_eq!(map.len(), Ok(3));
+
+        // sorted by key whatever the insertion order
+        assert_eq!(
+            map.keys(),
+            Ok(Value::List(vec![Value::UInt(1), key("a"), key("b")]))
+        );
+        assert_eq!(map.to_string(), r#"{1: 1, "a": 0, "b": 2}"#);
+
+        // numeric values compare by value, maps rank after lists
+        let floats = map.clone().set(&key("a"), Value::Float(0.0)).unwrap();
+        assert!(map.equals(&floats));
+        assert_ne!(map, floats);
+        assert!(Value::List(vec![]) < Value::Map(Default::default()));
+        assert!(Value::Map(Default::default()) < Value::Null);
+    }
+
+    #[quickcheck]
+    fn value_rem_property(a: u32, b: u32) -> bool {
+        match b {
+            0 => Value::UInt(a) % Value::UInt(b) == Err(ValueError::DivisionByZero),
+            _ => Value::UInt(a) % Value::UInt(b) == Ok(Value::UInt(a % b)),
+        }
+    }
+
     #[quickcheck]
     fn value_arbitrary_property(value: Value) -> bool {
         match value {
-            Value::UInt(_) | Value::Bool(_) | Value::String(_) | Value::Null => true,
+            Value::UInt(_)
+            | Value::Int(_)
+            | Value::Float(_)
+            | Value::Bool(_)
+            | Value::String(_)
+            | Value::Null => true,
+            // lists are generated one level deep
+            Value::List(items) => items.iter().all(|item| !matches!(item, Value::List(_))),
+            Value::Map(entries) => entries
+                .keys()
+                .all(|key| key.clone().map_key().as_ref() == Ok(key)),
         }
     }
 
@@ -81,7 +346,8 @@ mod tests {
             | Opcode::Flow(_)
             | Opcode::Dup
             | Opcode::Pop
-            | Opcode::Swap => true,
+            | Opcode::Swap
+            | Opcode::Collection(_) => true,
         }
     }
 
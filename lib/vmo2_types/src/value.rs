@@ -1,91 +1,542 @@
 use quickcheck::{Arbitrary, Gen};
 use rand::{seq::SliceRandom, thread_rng};
+use std::collections::BTreeMap;
 
-#[derive(Debug, PartialEq, Eq, Clone, Ord, PartialOrd)]
+#[derive(Debug, Clone)]
 pub enum Value {
     UInt(u32),
+    Int(i64),
+    Float(f64),
     Bool(bool),
     String(String),
+    List(Vec<Value>),
+    /// Keys are strings or integers, see `Value::map_key`. Entries are kept
+    /// sorted by key so iterating and printing a map is deterministic.
+    Map(BTreeMap<Value, Value>),
     Null,
 }
 
+/*
+ *  Structural equality and ordering, as needed for `Opcode: Eq` and for
+ *  sorting: values of different variants are never equal and rank in
+ *  declaration order, floats compare with `f64::total_cmp` so that `NaN`
+ *  equals itself. The numeric, IEEE semantics used by the VM live in
+ *  `Value::equals` and `Value::compare`.
+ */
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self, other) {
+            (Value::UInt(a), Value::UInt(b)) => a.cmp(b),
+            (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            (Value::Float(a), Value::Float(b)) => a.total_cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::List(a), Value::List(b)) => a.cmp(b),
+            // entry by entry in key order, then by size
+            (Value::Map(a), Value::Map(b)) => a.cmp(b),
+            (Value::Null, Value::Null) => std::cmp::Ordering::Equal,
+            (a, b) => a.rank().cmp(&b.rank()),
+        }
+    }
+}
+
+/// User-facing formatting, as printed by the `Print` opcode.
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::UInt(v) => write!(f, "{v}"),
+            Value::Int(v) => write!(f, "{v}"),
+            // `{:?}` keeps the fraction of whole floats: `1.0`, not `1`
+            Value::Float(v) => write!(f, "{v:?}"),
+            Value::Bool(v) => write!(f, "{v}"),
+            Value::String(v) => write!(f, "{v}"),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_nested(f)?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.fmt_nested(f)?;
+                    write!(f, ": ")?;
+                    value.fmt_nested(f)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Null => write!(f, "null"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueError {
+    TypeMismatch {
+        operation: &'static str,
+        lhs: &'static str,
+        rhs: Option<&'static str>,
+    },
+    DivisionByZero,
+    Overflow,
+    IndexOutOfBounds {
+        index: i64,
+        len: usize,
+    },
+    EmptyList,
+    /// Display form of the missing key.
+    KeyNotFound(String),
+}
+
+impl std::fmt::Display for ValueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueError::TypeMismatch {
+                operation,
+                lhs,
+                rhs: Some(rhs),
+            } => write!(f, "cannot apply `{operation}` to {lhs} and {rhs}"),
+            ValueError::TypeMismatch {
+                operation,
+                lhs,
+                rhs: None,
+            } => write!(f, "cannot apply `{operation}` to {lhs}"),
+            ValueError::DivisionByZero => write!(f, "division by zero"),
+            ValueError::Overflow => write!(f, "arithmetic overflow"),
+            ValueError::IndexOutOfBounds { index, len } => {
+                write!(f, "index {index} out of bounds for length {len}")
+            }
+            ValueError::EmptyList => write!(f, "pop from an empty list"),
+            ValueError::KeyNotFound(key) => write!(f, "key {key} not found"),
+        }
+    }
+}
+
+impl std::error::Error for ValueError {}
+
+fn mismatch(operation: &'static str, lhs: &Value, rhs: &Value) -> ValueError {
+    ValueError::TypeMismatch {
+        operation,
+        lhs: lhs.type_name(),
+        rhs: Some(rhs.type_name()),
+    }
+}
+
+/// Position in a list of length `len` that `index` refers to.
+fn list_position(index: &Value, len: usize) -> Result<usize, ValueError> {
+    let Some(i) = index.as_i64() else {
+        return Err(ValueError::TypeMismatch {
+            operation: "index",
+            lhs: "list",
+            rhs: Some(index.type_name()),
+        });
+    };
+    usize::try_from(i)
+        .ok()
+        .filter(|i| *i < len)
+        .ok_or(ValueError::IndexOutOfBounds { index: i, len })
+}
+
+/*
+ *  Promotion rules: a `Float` on either side makes the operation a float
+ *  operation, otherwise `UInt` and `Int` are both widened to `i64`. The
+ *  result of an integer operation is a `UInt` again only when both operands
+ *  were `UInt`s and it fits, so `1 - 2` is `Int(-1)` instead of an underflow.
+ *  Integer overflow and division by zero are errors, float operations follow
+ *  IEEE 754 (`1.0 / 0.0` is infinity).
+ */
+fn arithmetic(
+    operation: &'static str,
+    lhs: Value,
+    rhs: Value,
+    int: fn(i64, i64) -> Result<i64, ValueError>,
+    float: fn(f64, f64) -> f64,
+) -> Result<Value, ValueError> {
+    let is_float = |value: &Value| matches!(value, Value::Float(_));
+
+    if is_float(&lhs) || is_float(&rhs) {
+        return match (lhs.as_f64(), rhs.as_f64()) {
+            (Some(a), Some(b)) => Ok(Value::Float(float(a, b))),
+            _ => Err(mismatch(operation, &lhs, &rhs)),
+        };
+    }
+
+    let (Some(a), Some(b)) = (lhs.as_i64(), rhs.as_i64()) else {
+        return Err(mismatch(operation, &lhs, &rhs));
+    };
+    let result = int(a, b)?;
+    match (&lhs, &rhs, u32::try_from(result)) {
+        (Value::UInt(_), Value::UInt(_), Ok(result)) => Ok(Value::UInt(result)),
+        _ => Ok(Value::Int(result)),
+    }
+}
+
 impl std::ops::Add for Value {
-    type Output = Value;
+    type Output = Result<Value, ValueError>;
 
     fn add(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Value::UInt(a), Value::UInt(b)) => Value::UInt(a + b),
-            (Value::String(a), Value::String(b)) => Value::String(a + b.as_str()),
-            _ => unreachable!(),
+            (Value::String(a), Value::String(b)) => Ok(Value::String(a + b.as_str())),
+            (a, b) => arithmetic(
+                "add",
+                a,
+                b,
+                |a, b| a.checked_add(b).ok_or(ValueError::Overflow),
+                |a, b| a + b,
+            ),
         }
     }
 }
 
 impl std::ops::Sub for Value {
-    type Output = Value;
+    type Output = Result<Value, ValueError>;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Value::UInt(a), Value::UInt(b)) => Value::UInt(a - b),
-            _ => unreachable!(),
-        }
+        arithmetic(
+            "sub",
+            self,
+            rhs,
+            |a, b| a.checked_sub(b).ok_or(ValueError::Overflow),
+            |a, b| a - b,
+        )
     }
 }
 
 impl std::ops::Mul for Value {
-    type Output = Value;
+    type Output = Result<Value, ValueError>;
 
     fn mul(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Value::UInt(a), Value::UInt(b)) => Value::UInt(a * b),
-            (Value::String(a), Value::UInt(b)) => Value::String(a.repeat(b as usize)),
-            (Value::UInt(a), Value::String(b)) => Value::String(b.repeat(a as usize)),
-            _ => unreachable!(),
+            (Value::String(a), Value::UInt(b)) => Ok(Value::String(a.repeat(b as usize))),
+            (Value::UInt(a), Value::String(b)) => Ok(Value::String(b.repeat(a as usize))),
+            (a, b) => arithmetic(
+                "mul",
+                a,
+                b,
+                |a, b| a.checked_mul(b).ok_or(ValueError::Overflow),
+                |a, b| a * b,
+            ),
         }
     }
 }
 
+impl std::ops::Rem for Value {
+    type Output = Result<Value, ValueError>;
+
+    fn rem(self, rhs: Self) -> Self::Output {
+        arithmetic(
+            "mod",
+            self,
+            rhs,
+            |a, b| match b {
+                0 => Err(ValueError::DivisionByZero),
+                _ => a.checked_rem(b).ok_or(ValueError::Overflow),
+            },
+            |a, b| a % b,
+        )
+    }
+}
+
 impl std::ops::Div for Value {
-    type Output = Value;
+    type Output = Result<Value, ValueError>;
 
     fn div(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Value::UInt(a), Value::UInt(b)) => Value::UInt(a / b),
-            _ => unreachable!(),
+        arithmetic(
+            "div",
+            self,
+            rhs,
+            |a, b| match b {
+                0 => Err(ValueError::DivisionByZero),
+                _ => a.checked_div(b).ok_or(ValueError::Overflow),
+            },
+            |a, b| a / b,
+        )
+    }
+}
+
+impl std::ops::Neg for Value {
+    type Output = Result<Value, ValueError>;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Value::UInt(a) => Ok(Value::Int(-(a as i64))),
+            Value::Int(a) => a.checked_neg().map(Value::Int).ok_or(ValueError::Overflow),
+            Value::Float(a) => Ok(Value::Float(-a)),
+            a => Err(ValueError::TypeMismatch {
+                operation: "neg",
+                lhs: a.type_name(),
+                rhs: None,
+            }),
         }
     }
 }
 
 impl std::ops::Not for Value {
-    type Output = Value;
+    type Output = Result<Value, ValueError>;
 
     fn not(self) -> Self::Output {
         match self {
-            Value::Bool(a) => Value::Bool(!a),
-            _ => unreachable!(),
+            Value::Bool(a) => Ok(Value::Bool(!a)),
+            a => Err(ValueError::TypeMismatch {
+                operation: "not",
+                lhs: a.type_name(),
+                rhs: None,
+            }),
         }
     }
 }
 
 impl Value {
-    pub fn and(self, rhs: Self) -> Self {
+    /// Formats an element of a list or map: nested strings are quoted so
+    /// that `["1"]` and `[1]` print differently.
+    fn fmt_nested(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{s:?}"),
+            value => write!(f, "{value}"),
+        }
+    }
+
+    /// Canonical form of a map key. Integers are stored as a `UInt` when
+    /// they fit and as an `Int` otherwise so that `m[1]` and `m[-(-1)]` find
+    /// the same entry.
+    pub fn map_key(self) -> Result<Value, ValueError> {
+        match self {
+            Value::String(_) | Value::UInt(_) => Ok(self),
+            Value::Int(v) => Ok(u32::try_from(v).map_or(Value::Int(v), Value::UInt)),
+            _ => Err(ValueError::TypeMismatch {
+                operation: "key",
+                lhs: self.type_name(),
+                rhs: None,
+            }),
+        }
+    }
+
+    /// Position of the variant in the structural ordering.
+    fn rank(&self) -> u8 {
+        match self {
+            Value::UInt(_) => 0,
+            Value::Int(_) => 1,
+            Value::Float(_) => 2,
+            Value::Bool(_) => 3,
+            Value::String(_) => 4,
+            Value::List(_) => 5,
+            Value::Map(_) => 6,
+            Value::Null => 7,
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Value::UInt(_) | Value::Int(_) | Value::Float(_))
+    }
+
+    fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::UInt(v) => Some(*v as i64),
+            Value::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::UInt(v) => Some(*v as f64),
+            Value::Int(v) => Some(*v as f64),
+            Value::Float(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::UInt(_) => "uint",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "bool",
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Null => "null",
+        }
+    }
+
+    pub fn and(self, rhs: Self) -> Result<Value, ValueError> {
         match (self, rhs) {
-            (Value::Bool(a), Value::Bool(b)) => Value::Bool(a && b),
-            _ => unreachable!(),
+            (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a && b)),
+            (a, b) => Err(mismatch("and", &a, &b)),
         }
     }
 
-    pub fn or(self, rhs: Self) -> Self {
+    pub fn or(self, rhs: Self) -> Result<Value, ValueError> {
         match (self, rhs) {
-            (Value::Bool(a), Value::Bool(b)) => Value::Bool(a || b),
-            _ => unreachable!(),
+            (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a || b)),
+            (a, b) => Err(mismatch("or", &a, &b)),
         }
     }
 
-    pub fn xor(self, rhs: Self) -> Self {
+    pub fn xor(self, rhs: Self) -> Result<Value, ValueError> {
         match (self, rhs) {
-            (Value::Bool(a), Value::Bool(b)) => Value::Bool(a ^ b),
-            _ => unreachable!(),
+            (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a ^ b)),
+            (a, b) => Err(mismatch("xor", &a, &b)),
+        }
+    }
+
+    /// `self[index]`.
+    pub fn get(&self, index: &Value) -> Result<Value, ValueError> {
+        match self {
+            Value::List(items) => Ok(items[list_position(index, items.len())?].clone()),
+            Value::Map(entries) => entries
+                .get(&index.clone().map_key()?)
+                .cloned()
+                .ok_or_else(|| ValueError::KeyNotFound(index.to_string())),
+            _ => Err(mismatch("index", self, index)),
+        }
+    }
+
+    /// `self[index] = value`, returning the updated collection. Setting a
+    /// missing key of a map inserts it.
+    pub fn set(self, index: &Value, value: Value) -> Result<Value, ValueError> {
+        match self {
+            Value::List(mut items) => {
+                let position = list_position(index, items.len())?;
+                items[position] = value;
+                Ok(Value::List(items))
+            }
+            Value::Map(mut entries) => {
+                entries.insert(index.clone().map_key()?, value);
+                Ok(Value::Map(entries))
+            }
+            _ => Err(mismatch("index", &self, index)),
+        }
+    }
+
+    /// Number of elements of a list, of entries of a map, or of characters
+    /// of a string.
+    pub fn len(&self) -> Result<usize, ValueError> {
+        match self {
+            Value::List(items) => Ok(items.len()),
+            Value::Map(entries) => Ok(entries.len()),
+            Value::String(s) => Ok(s.chars().count()),
+            _ => Err(ValueError::TypeMismatch {
+                operation: "len",
+                lhs: self.type_name(),
+                rhs: None,
+            }),
+        }
+    }
+
+    pub fn is_empty(&self) -> Result<bool, ValueError> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// The list with `value` appended.
+    pub fn push(self, value: Value) -> Result<Value, ValueError> {
+        match self {
+            Value::List(mut items) => {
+                items.push(value);
+                Ok(Value::List(items))
+            }
+            _ => Err(mismatch("push", &self, &value)),
+        }
+    }
+
+    /// The list without its last element, and that element.
+    pub fn pop(self) -> Result<(Value, Value), ValueError> {
+        match self {
+            Value::List(mut items) => {
+                let last = items.pop().ok_or(ValueError::EmptyList)?;
+                Ok((Value::List(items), last))
+            }
+            _ => Err(ValueError::TypeMismatch {
+                operation: "pop",
+                lhs: self.type_name(),
+                rhs: None,
+            }),
+        }
+    }
+
+    /// The keys of a map as a list, in ascending order.
+    pub fn keys(&self) -> Result<Value, ValueError> {
+        match self {
+            Value::Map(entries) => Ok(Value::List(entries.keys().cloned().collect())),
+            _ => Err(ValueError::TypeMismatch {
+                operation: "keys",
+                lhs: self.type_name(),
+                rhs: None,
+            }),
+        }
+    }
+
+    /// Whether a map has the key `needle`, or a list an element equal to
+    /// it.
+    pub fn contains(&self, needle: &Value) -> Result<bool, ValueError> {
+        match self {
+            Value::Map(entries) => Ok(entries.contains_key(&needle.clone().map_key()?)),
+            Value::List(items) => Ok(items.iter().any(|item| item.equals(needle))),
+            _ => Err(mismatch("contains", self, needle)),
+        }
+    }
+
+    /// Equality as seen by the `Eq` and `Ne` opcodes: numbers compare by
+    /// value across `UInt`, `Int` and `Float` (`1 == 1.0`, `NaN != NaN`),
+    /// everything else structurally.
+    pub fn equals(&self, rhs: &Self) -> bool {
+        match (self, rhs) {
+            (a, b) if a.is_numeric() && b.is_numeric() => {
+                a.compare(b) == Ok(Some(std::cmp::Ordering::Equal))
+            }
+            (Value::List(a), Value::List(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equals(b))
+            }
+            // keys are canonical, only the values need numeric comparison
+            (Value::Map(a), Value::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b)
+                        .all(|((ka, va), (kb, vb))| ka == kb && va.equals(vb))
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    /// Orders two numbers, bools or strings. Numbers of different types are
+    /// promoted like in arithmetic, `None` means unordered (a `NaN` operand).
+    /// Unlike `Ord`, which also ranks values of different types, ordering a
+    /// `uint` against a `string` or ordering lists is reported as a type
+    /// mismatch.
+    pub fn compare(&self, rhs: &Self) -> Result<Option<std::cmp::Ordering>, ValueError> {
+        match (self, rhs) {
+            (Value::Float(_), _) | (_, Value::Float(_))
+                if self.is_numeric() && rhs.is_numeric() =>
+            {
+                Ok(self.as_f64().unwrap().partial_cmp(&rhs.as_f64().unwrap()))
+            }
+            (a, b) if a.is_numeric() && b.is_numeric() => {
+                Ok(Some(a.as_i64().unwrap().cmp(&b.as_i64().unwrap())))
+            }
+            (Value::Bool(a), Value::Bool(b)) => Ok(Some(a.cmp(b))),
+            (Value::String(a), Value::String(b)) => Ok(Some(a.cmp(b))),
+            (a, b) => Err(mismatch("compare", a, b)),
         }
     }
 }
@@ -93,13 +544,45 @@ impl Value {
 impl Arbitrary for Value {
     fn arbitrary(g: &mut Gen) -> Self {
         let mut rng = thread_rng();
-        let value = [1, 2, 3, 4].choose(&mut rng).unwrap();
+        let value = [1, 2, 3, 4, 5, 6, 7, 8].choose(&mut rng).unwrap();
 
         match value {
             1 => Value::Bool(Arbitrary::arbitrary(g)),
             2 => Value::UInt(Arbitrary::arbitrary(g)),
             3 => Value::String(Arbitrary::arbitrary(g)),
             4 => Value::Null,
+            5 => Value::Int(Arbitrary::arbitrary(g)),
+            6 => Value::Float(Arbitrary::arbitrary(g)),
+            // one level deep, lists of scalars
+            7 => {
+                let len = usize::arbitrary(g) % 4;
+                let items = (0..len)
+                    .map(|_| {
+                        loop {
+                            match Value::arbitrary(g) {
+                                Value::List(_) => continue,
+                                value => break value,
+                            }
+                        }
+                    })
+                    .collect();
+                Value::List(items)
+            }
+            // one level deep as well, with canonical keys
+            8 => {
+                let len = usize::arbitrary(g) % 4;
+                let entries = (0..len)
+                    .map(|_| {
+                        let key = if bool::arbitrary(g) {
+                            Value::String(Arbitrary::arbitrary(g))
+                        } else {
+                            Value::Int(Arbitrary::arbitrary(g)).map_key().unwrap()
+                        };
+                        (key, Value::Bool(Arbitrary::arbitrary(g)))
+                    })
+                    .collect();
+                Value::Map(entries)
+            }
             _ => unreachable!(),
         }
     }
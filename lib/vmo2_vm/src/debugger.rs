@@ -0,0 +1,333 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+use vmo2_types::{
+    format::format_instruction,
+    opcode::Opcode,
+    source_map::{SourceLocation, SourceMap},
+};
+
+use crate::vm::{VM, VMError, VMResult};
+
+/// Why the debugger handed control back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// A single step completed.
+    Step,
+    /// Execution reached the breakpoint at this pc.
+    Breakpoint(usize),
+    Halted,
+    Error(VMError),
+}
+
+/// Drives a `VM` one instruction at a time with pc and source line
+/// breakpoints.
+pub struct Debugger {
+    pub vm: VM,
+    pub source_map: Option<SourceMap>,
+    breakpoints: BTreeSet<usize>,
+    /// The pc control was last handed back at, a breakpoint there has
+    /// already been reported.
+    stopped_at: Option<usize>,
+    finished: Option<StopReason>,
+}
+
+impl Debugger {
+    pub fn new(vm: VM) -> Self {
+        Self {
+            vm,
+            source_map: None,
+            breakpoints: BTreeSet::new(),
+            stopped_at: None,
+            finished: None,
+        }
+    }
+
+    /// Attaches the source map emitted by the compiler, enabling line
+    /// breakpoints and line information in stops.
+    pub fn with_source_map(mut self, source_map: SourceMap) -> Self {
+        self.source_map = Some(source_map);
+        self
+    }
+
+    /// Returns `false` if `pc` is outside the program.
+    pub fn add_breakpoint(&mut self, pc: usize) -> bool {
+        if pc >= self.vm.bytecode.opcodes.len() {
+            return false;
+        }
+        self.breakpoints.insert(pc);
+        true
+    }
+
+    /// Breaks wherever execution enters `line` and returns the addresses
+    /// that were added, empty if the line has no code or there is no source
+    /// map.
+    pub fn add_line_breakpoint(&mut self, line: usize) -> Vec<usize> {
+        let pcs = self
+            .source_map
+            .as_ref()
+            .map(|map| map.line_starts(line))
+            .unwrap_or_default();
+        self.breakpoints.extend(&pcs);
+        pcs
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Source location of the instruction about to execute.
+    pub fn location(&self) -> Option<SourceLocation> {
+        self.source_map.as_ref()?.location(self.vm.pc)
+    }
+
+    /// `Some` once the program halted or failed; stepping further keeps
+    /// returning the same reason.
+    pub fn finished(&self) -> Option<&StopReason> {
+        self.finished.as_ref()
+    }
+
+    /// Executes exactly one instruction.
+    pub fn step(&mut self) -> StopReason {
+        if let Some(reason) = &self.finished {
+            return reason.clone();
+        }
+
+        let reason = match self.vm.step() {
+            VMResult::Ok => {
+                self.stopped_at = Some(self.vm.pc);
+                return StopReason::Step;
+            }
+            VMResult::Halt => StopReason::Halted,
+            VMResult::Error(error) => StopReason::Error(error),
+        };
+        self.finished = Some(reason.clone());
+        reason
+    }
+
+    /// Runs until the next breakpoint or the end of the program. A
+    /// breakpoint at the current pc stops immediately, unless execution is
+    /// already stopped there, so continuing from a breakpoint moves past it.
+    pub fn resume(&mut self) -> StopReason {
+        let pc = self.vm.pc;
+        if self.finished.is_none() && self.stopped_at != Some(pc) && self.breakpoints.contains(&pc)
+        {
+            self.stopped_at = Some(pc);
+            return StopReason::Breakpoint(pc);
+        }
+
+        loop {
+            match self.step() {
+                StopReason::Step if self.breakpoints.contains(&self.vm.pc) => {
+                    return StopReason::Breakpoint(self.vm.pc);
+                }
+                StopReason::Step => continue,
+                reason => return reason,
+            }
+        }
+    }
+
+    /// The instructions within `radius` of `pc`.
+    pub fn disassemble(&self, pc: usize, radius: usize) -> Vec<(usize, &Opcode)> {
+        let opcodes = &self.vm.bytecode.opcodes;
+        let start = pc.saturating_sub(radius);
+        let end = (pc + radius + 1).min(opcodes.len());
+
+        (start..end).map(|pc| (pc, &opcodes[pc])).collect()
+    }
+
+    /*
+     *  Interactive front end, reads one command per line from `input` until
+     *  `quit` or end of input. Program output still goes through the VM's
+     *  own `VmIo`.
+     */
+    pub fn repl(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        writeln!(output, "vmo2 debugger, type `help` for commands")?;
+        self.print_location(&mut output)?;
+
+        let mut lines = input.lines();
+        loop {
+            write!(output, "(vmo2) ")?;
+            output.flush()?;
+
+            let Some(line) = lines.next().transpose()? else {
+                return Ok(());
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            match words.as_slice() {
+                [] => {}
+                ["help" | "h"] => writeln!(output, "{HELP}")?,
+                ["quit" | "q"] => return Ok(()),
+                ["break" | "b", "line", line] => match line.parse() {
+                    Ok(line) => {
+                        let pcs = self.add_line_breakpoint(line);
+                        if pcs.is_empty() {
+                            writeln!(output, "no code on line {line}")?;
+                        }
+                        for pc in pcs {
+                            writeln!(output, "breakpoint at pc {pc} (line {line})")?;
+                        }
+                    }
+                    Err(_) => writeln!(output, "invalid line `{line}`")?,
+                },
+                ["break" | "b", pc] => match pc.parse() {
+                    Ok(pc) if self.add_breakpoint(pc) => {
+                        writeln!(output, "breakpoint at pc {pc}")?;
+                    }
+                    _ => writeln!(output, "invalid pc `{pc}`")?,
+                },
+                ["break" | "b"] => {
+                    for pc in self.breakpoints() {
+                        writeln!(output, "breakpoint at pc {pc}")?;
+                    }
+                }
+                ["delete" | "d", pc] => match pc.parse() {
+                    Ok(pc) if self.remove_breakpoint(pc) => {
+                        writeln!(output, "deleted breakpoint at pc {pc}")?;
+                    }
+                    _ => writeln!(output, "no breakpoint at `{pc}`")?,
+                },
+                ["step" | "s", rest @ ..] => {
+                    let count = match rest {
+                        [] => Some(1),
+                        [count] => count.parse().ok(),
+                        _ => None,
+                    };
+                    let Some(count) = count else {
+                        writeln!(output, "usage: step [count]")?;
+                        continue;
+                    };
+                    for _ in 0..count {
+                        let reason = self.step();
+                        if reason != StopReason::Step {
+                            self.print_stop(&reason, &mut output)?;
+                            break;
+                        }
+                    }
+                    if self.finished.is_none() {
+                        self.print_location(&mut output)?;
+                    }
+                }
+                ["continue" | "c"] => {
+                    let reason = self.resume();
+                    self.print_stop(&reason, &mut output)?;
+                }
+                ["stack"] => {
+                    if self.vm.stack.is_empty() {
+                        writeln!(output, "stack is empty")?;
+                    }
+                    for (depth, value) in self.vm.stack.iter().rev().enumerate() {
+                        writeln!(output, "  {depth}: {value:?}")?;
+                    }
+                }
+                ["calls"] => {
+                    if self.vm.call_stack.is_empty() {
+                        writeln!(output, "call stack is empty")?;
+                    }
+                    for (depth, frame) in self.vm.call_stack.iter().rev().enumerate() {
+                        writeln!(output, "  {depth}: returns to pc {}", frame.return_address)?;
+                    }
+                }
+                ["locals"] => {
+                    let Some(frame) = self.vm.call_stack.last() else {
+                        writeln!(output, "not inside a call")?;
+                        continue;
+                    };
+                    if frame.locals.is_empty() {
+                        writeln!(output, "no locals")?;
+                    }
+                    let mut entries: Vec<_> = frame.locals.iter().collect();
+                    entries.sort_by(|a, b| a.0.cmp(b.0));
+                    for (name, value) in entries {
+                        writeln!(output, "  {name} = {value:?}")?;
+                    }
+                }
+                ["heap"] => {
+                    if self.vm.heap.is_empty() {
+                        writeln!(output, "heap is empty")?;
+                    }
+                    let mut entries: Vec<_> = self.vm.heap.iter().collect();
+                    entries.sort_by(|a, b| a.0.cmp(b.0));
+                    for (name, value) in entries {
+                        writeln!(output, "  {name} = {value:?}")?;
+                    }
+                }
+                ["disas", rest @ ..] => {
+                    let radius = match rest {
+                        [] => Some(5),
+                        [radius] => radius.parse().ok(),
+                        _ => None,
+                    };
+                    let Some(radius) = radius else {
+                        writeln!(output, "usage: disas [radius]")?;
+                        continue;
+                    };
+                    for (pc, opcode) in self.disassemble(self.vm.pc, radius) {
+                        let marker = if pc == self.vm.pc { "=>" } else { "  " };
+                        let breakpoint = if self.breakpoints.contains(&pc) {
+                            "*"
+                        } else {
+                            " "
+                        };
+                        writeln!(
+                            output,
+                            "{marker}{breakpoint}{pc:>5}  {}",
+                            format_instruction(opcode)
+                        )?;
+                    }
+                }
+                ["where" | "w"] => self.print_location(&mut output)?,
+                _ => writeln!(output, "unknown command `{line}`, type `help`")?,
+            }
+        }
+    }
+
+    fn print_stop(&self, reason: &StopReason, output: &mut impl Write) -> io::Result<()> {
+        match reason {
+            StopReason::Step => self.print_location(output),
+            StopReason::Breakpoint(_) => {
+                write!(output, "breakpoint: ")?;
+                self.print_location(output)
+            }
+            StopReason::Halted => writeln!(output, "program halted"),
+            StopReason::Error(error) => writeln!(output, "error: {error}"),
+        }
+    }
+
+    fn print_location(&self, output: &mut impl Write) -> io::Result<()> {
+        let pc = self.vm.pc;
+        let Some(opcode) = self.vm.bytecode.opcodes.get(pc) else {
+            return writeln!(output, "pc {pc} (end of program)");
+        };
+        let instruction = format_instruction(opcode);
+        match self.location() {
+            Some(location) => writeln!(
+                output,
+                "pc {pc} (line {}, column {}): {instruction}",
+                location.line, location.column
+            ),
+            None => writeln!(output, "pc {pc}: {instruction}"),
+        }
+    }
+}
+
+const HELP: &str = "\
+commands:
+  break <pc>        set a breakpoint at an address
+  break line <n>    set breakpoints where source line n starts
+  break             list breakpoints
+  delete <pc>       remove a breakpoint
+  step [n]          execute n instructions (default 1)
+  continue          run until the next breakpoint or the end
+  stack             print the operand stack, top first
+  calls             print the call stack
+  heap              print the global variables
+  locals            print the variables of the current call
+  disas [radius]    disassemble around the current pc
+  where             print the current location
+  quit              leave the debugger";
@@ -0,0 +1,119 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use vmo2_types::value::Value;
+
+/// Host side of the `Print` and `Scan` opcodes.
+///
+/// A `VM` owns one of these and routes every I/O instruction through it, so
+/// embedders decide where program output goes and where input comes from.
+pub trait VmIo {
+    /// Emits a value popped by `Print`.
+    fn print(&mut self, value: &Value) -> io::Result<()>;
+
+    /// Reads one line for `Scan`, `None` once the input is exhausted.
+    fn scan(&mut self) -> io::Result<Option<String>>;
+}
+
+/// Prints to stdout and reads from stdin, one value per line.
+pub struct StdIo;
+
+impl VmIo for StdIo {
+    fn print(&mut self, value: &Value) -> io::Result<()> {
+        writeln!(io::stdout().lock(), "{value}")
+    }
+
+    fn scan(&mut self) -> io::Result<Option<String>> {
+        let mut input = String::new();
+        if io::stdin().lock().read_line(&mut input)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(input.trim().to_string()))
+    }
+}
+
+#[derive(Default)]
+struct Buffers {
+    input: VecDeque<String>,
+    output: Vec<String>,
+}
+
+/// In-memory I/O for tests: input is served from a queue of lines and every
+/// printed value is recorded. Clones share the same buffers, so keep a clone
+/// around to inspect the output after handing one to the `VM`.
+#[derive(Clone, Default)]
+pub struct BufferIo {
+    buffers: Rc<RefCell<Buffers>>,
+}
+
+impl BufferIo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_input<I, S>(input: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let io = Self::new();
+        for line in input {
+            io.push_input(line);
+        }
+        io
+    }
+
+    pub fn push_input(&self, line: impl Into<String>) {
+        self.buffers.borrow_mut().input.push_back(line.into());
+    }
+
+    /// Every value printed so far, formatted with `Display`.
+    pub fn output(&self) -> Vec<String> {
+        self.buffers.borrow().output.clone()
+    }
+}
+
+impl VmIo for BufferIo {
+    fn print(&mut self, value: &Value) -> io::Result<()> {
+        self.buffers.borrow_mut().output.push(value.to_string());
+        Ok(())
+    }
+
+    fn scan(&mut self) -> io::Result<Option<String>> {
+        Ok(self.buffers.borrow_mut().input.pop_front())
+    }
+}
+
+/// Forwards I/O to a pair of closures, e.g. to route program output into the
+/// host's own logging.
+pub struct CallbackIo<P, S> {
+    print: P,
+    scan: S,
+}
+
+impl<P, S> CallbackIo<P, S>
+where
+    P: FnMut(&Value),
+    S: FnMut() -> Option<String>,
+{
+    pub fn new(print: P, scan: S) -> Self {
+        Self { print, scan }
+    }
+}
+
+impl<P, S> VmIo for CallbackIo<P, S>
+where
+    P: FnMut(&Value),
+    S: FnMut() -> Option<String>,
+{
+    fn print(&mut self, value: &Value) -> io::Result<()> {
+        (self.print)(value);
+        Ok(())
+    }
+
+    fn scan(&mut self) -> io::Result<Option<String>> {
+        Ok((self.scan)())
+    }
+}
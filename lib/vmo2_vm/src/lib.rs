@@ -1,3 +1,7 @@
+pub mod debugger;
+pub mod io;
+pub mod limits;
 mod profile;
 mod test;
+pub mod verifier;
 pub mod vm;
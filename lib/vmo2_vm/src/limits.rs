@@ -0,0 +1,42 @@
+use vmo2_types::value::Value;
+
+/// Resource limits for running untrusted programs, `None` means unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of executed instructions, checked against
+    /// `Profile::executed_instructions`.
+    pub max_instructions: Option<usize>,
+    /// Maximum number of values on the operand stack.
+    pub max_stack_depth: Option<usize>,
+    /// Maximum number of nested calls.
+    pub max_call_depth: Option<usize>,
+    /// Maximum number of distinct variables in the heap, and in the locals
+    /// of each call frame.
+    pub max_heap_entries: Option<usize>,
+    /// Maximum `value_size` of any value the VM pushes, checked before
+    /// string `+` and `*` build their result.
+    pub max_value_size: Option<usize>,
+}
+
+impl Limits {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+}
+
+/// Size of a value as accounted by `Limits::max_value_size`: the length in
+/// bytes for strings, one plus the size of the elements for lists and maps
+/// (keys included), 1 for everything else.
+pub fn value_size(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.len(),
+        Value::List(items) => 1 + items.iter().map(value_size).sum::<usize>(),
+        Value::Map(entries) => {
+            1 + entries
+                .iter()
+                .map(|(key, value)| value_size(key) + value_size(value))
+                .sum::<usize>()
+        }
+        Value::UInt(_) | Value::Int(_) | Value::Float(_) | Value::Bool(_) | Value::Null => 1,
+    }
+}
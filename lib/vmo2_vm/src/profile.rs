@@ -7,6 +7,17 @@ pub struct Profile {
     pub total_stack_pops: usize,
 }
 
+/// One counter per line, as printed by `vmo2 run --profile`.
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "executed instructions  {}", self.executed_instructions)?;
+        writeln!(f, "memory reads           {}", self.total_memory_reads)?;
+        writeln!(f, "memory writes          {}", self.total_memory_writes)?;
+        writeln!(f, "stack pushes           {}", self.total_stack_pushes)?;
+        write!(f, "stack pops             {}", self.total_stack_pops)
+    }
+}
+
 impl Profile {
     pub fn new() -> Self {
         Self {
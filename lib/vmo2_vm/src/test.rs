@@ -34,4 +34,1131 @@ mod tests {
 
         assert_eq!(vm.stack.pop().unwrap(), Value::UInt(7));
     }
+
+    #[test]
+    fn test_call_frames() {
+        use crate::vm::VM;
+        use vmo2_types::{
+            bytecode::ByteCode,
+            opcode::{ArithmeticOpcode, FlowOpcode, MemoryOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let bytecode = ByteCode::from(vec![
+            // x = 10, then f(5)
+            Literal(Value::UInt(10)),
+            Literal(Value::String("x".to_owned())),
+            Memory(MemoryOpcode::Store),
+            Literal(Value::UInt(5)),
+            Flow(FlowOpcode::Call(6)),
+            Halt,
+            // f(n): x = n; return x + global x
+            Literal(Value::String("n".to_owned())),
+            Memory(MemoryOpcode::Store),
+            Literal(Value::String("n".to_owned())),
+            Memory(MemoryOpcode::Load),
+            Literal(Value::String("x".to_owned())),
+            Memory(MemoryOpcode::Load),
+            Arithmetic(ArithmeticOpcode::Add),
+            Flow(FlowOpcode::Return),
+        ]);
+
+        let mut vm = VM::new(bytecode);
+        // stop on the `Return`
+        while vm.pc != 13 {
+            vm.step();
+        }
+        assert_eq!(vm.call_stack[0].return_address, 5);
+        assert_eq!(vm.call_stack[0].locals["n"], Value::UInt(5));
+        assert_eq!(vm.variable("n"), Some(&Value::UInt(5)));
+
+        vm.run().unwrap();
+
+        // the local `n` went away with its frame, the return value did not
+        assert_eq!(vm.stack, vec![Value::UInt(15)]);
+        assert_eq!(vm.heap.len(), 1);
+        assert!(vm.call_stack.is_empty());
+    }
+
+    #[test]
+    fn test_logic() {
+        use crate::vm::VM;
+        use vmo2_types::{
+            bytecode::ByteCode,
+            opcode::{LogicOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let bytecode = ByteCode::from(vec![
+            // not only consumes its own operand
+            Literal(Value::Bool(true)),
+            Literal(Value::Bool(false)),
+            Logic(LogicOpcode::Not),
+            Literal(Value::Bool(true)),
+            Literal(Value::Bool(false)),
+            Logic(LogicOpcode::And),
+            Literal(Value::Bool(true)),
+            Literal(Value::Bool(false)),
+            Logic(LogicOpcode::Or),
+            Literal(Value::Bool(true)),
+            Literal(Value::Bool(true)),
+            Logic(LogicOpcode::Xor),
+            Halt,
+        ]);
+
+        let mut vm = VM::new(bytecode);
+        vm.run().unwrap();
+
+        assert_eq!(
+            vm.stack,
+            vec![
+                Value::Bool(true),
+                Value::Bool(true),
+                Value::Bool(false),
+                Value::Bool(true),
+                Value::Bool(false),
+            ]
+        );
+    }
+
+    fn run_error(opcodes: Vec<vmo2_types::opcode::Opcode>) -> crate::vm::VMError {
+        let mut vm = crate::vm::VM::new(vmo2_types::bytecode::ByteCode::from(opcodes));
+        vm.run().unwrap_err()
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        use crate::vm::VMErrorKind;
+        use vmo2_types::{
+            opcode::{ArithmeticOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let error = run_error(vec![
+            Literal(Value::UInt(1)),
+            Arithmetic(ArithmeticOpcode::Add),
+            Halt,
+        ]);
+
+        assert_eq!(error.kind, VMErrorKind::StackUnderflow);
+        assert_eq!(error.pc, 1);
+        assert_eq!(error.opcode, Some(Arithmetic(ArithmeticOpcode::Add)));
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        use crate::vm::VMErrorKind;
+        use vmo2_types::{
+            opcode::{MemoryOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let error = run_error(vec![
+            Literal(Value::String("x".to_owned())),
+            Memory(MemoryOpcode::Load),
+            Halt,
+        ]);
+
+        assert_eq!(error.kind, VMErrorKind::UndefinedVariable("x".to_owned()));
+        assert_eq!(error.pc, 1);
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        use crate::vm::VMErrorKind;
+        use vmo2_types::{
+            opcode::{ArithmeticOpcode, ComparisonOpcode, FlowOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let error = run_error(vec![
+            Literal(Value::UInt(1)),
+            Literal(Value::String("a".to_owned())),
+            Arithmetic(ArithmeticOpcode::Sub),
+            Halt,
+        ]);
+        assert_eq!(
+            error.kind,
+            VMErrorKind::TypeMismatch {
+                operation: "sub",
+                lhs: "string",
+                rhs: Some("uint"),
+            }
+        );
+        assert_eq!(error.pc, 2);
+
+        let error = run_error(vec![
+            Literal(Value::UInt(1)),
+            Literal(Value::Bool(true)),
+            Comparison(ComparisonOpcode::Lt),
+            Halt,
+        ]);
+        assert_eq!(
+            error.kind,
+            VMErrorKind::TypeMismatch {
+                operation: "compare",
+                lhs: "bool",
+                rhs: Some("uint"),
+            }
+        );
+
+        let error = run_error(vec![
+            Literal(Value::UInt(1)),
+            Flow(FlowOpcode::JumpIfFalse(0)),
+            Halt,
+        ]);
+        assert_eq!(
+            error.kind,
+            VMErrorKind::TypeMismatch {
+                operation: "jump_if_false",
+                lhs: "uint",
+                rhs: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_errors() {
+        use crate::vm::VMErrorKind;
+        use vmo2_types::{
+            opcode::{ArithmeticOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let error = run_error(vec![
+            Literal(Value::UInt(0)),
+            Literal(Value::UInt(1)),
+            Arithmetic(ArithmeticOpcode::Div),
+            Halt,
+        ]);
+        assert_eq!(error.kind, VMErrorKind::DivisionByZero);
+
+        let error = run_error(vec![
+            Literal(Value::UInt(0)),
+            Literal(Value::UInt(1)),
+            Arithmetic(ArithmeticOpcode::Mod),
+            Halt,
+        ]);
+        assert_eq!(error.kind, VMErrorKind::DivisionByZero);
+        assert_eq!(error.pc, 2);
+
+        let error = run_error(vec![
+            Literal(Value::Int(i64::MIN)),
+            Arithmetic(ArithmeticOpcode::Neg),
+            Halt,
+        ]);
+        assert_eq!(error.kind, VMErrorKind::ArithmeticOverflow);
+    }
+
+    #[test]
+    fn test_numeric_values() {
+        use crate::vm::VM;
+        use vmo2_types::{
+            bytecode::ByteCode,
+            opcode::{ArithmeticOpcode, ComparisonOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let bytecode = ByteCode::from(vec![
+            // 1 - 2
+            Literal(Value::UInt(2)),
+            Literal(Value::UInt(1)),
+            Arithmetic(ArithmeticOpcode::Sub),
+            // -(0.5)
+            Literal(Value::Float(0.5)),
+            Arithmetic(ArithmeticOpcode::Neg),
+            // 1 == 1.0
+            Literal(Value::Float(1.0)),
+            Literal(Value::UInt(1)),
+            Comparison(ComparisonOpcode::Eq),
+            // NaN < 1
+            Literal(Value::UInt(1)),
+            Literal(Value::Float(f64::NAN)),
+            Comparison(ComparisonOpcode::Lt),
+            // -3 < 2
+            Literal(Value::UInt(2)),
+            Literal(Value::Int(-3)),
+            Comparison(ComparisonOpcode::Lt),
+            Halt,
+        ]);
+
+        let mut vm = VM::new(bytecode);
+        vm.run().unwrap();
+
+        assert_eq!(
+            vm.stack,
+            vec![
+                Value::Int(-1),
+                Value::Float(-0.5),
+                Value::Bool(true),
+                Value::Bool(false),
+                Value::Bool(true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collections() {
+        use crate::vm::VM;
+        use vmo2_types::{
+            bytecode::ByteCode,
+            opcode::{CollectionOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let bytecode = ByteCode::from(vec![
+            // [1, 2, 3]
+            Literal(Value::UInt(1)),
+            Literal(Value::UInt(2)),
+            Literal(Value::UInt(3)),
+            Collection(CollectionOpcode::BuildList(3)),
+            // [1, 2, 3][1] = "x"
+            Literal(Value::UInt(1)),
+            Literal(Value::String("x".to_owned())),
+            Collection(CollectionOpcode::Set),
+            // push(.., null), then pop it again
+            Literal(Value::Null),
+            Collection(CollectionOpcode::Push),
+            Collection(CollectionOpcode::Pop),
+            Pop,
+            Dup,
+            Collection(CollectionOpcode::Len),
+            // list[1]
+            Swap,
+            Literal(Value::UInt(1)),
+            Collection(CollectionOpcode::Get),
+            Halt,
+        ]);
+
+        let mut vm = VM::new(bytecode);
+        vm.run().unwrap();
+
+        assert_eq!(
+            vm.stack,
+            vec![Value::UInt(3), Value::String("x".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_maps() {
+        use crate::vm::{VM, VMErrorKind};
+        use vmo2_types::{
+            bytecode::ByteCode,
+            opcode::{CollectionOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let bytecode = ByteCode::from(vec![
+            // {"b": 1, 2: true, "b": 3}
+            Literal(Value::String("b".to_owned())),
+            Literal(Value::UInt(1)),
+            Literal(Value::Int(2)),
+            Literal(Value::Bool(true)),
+            Literal(Value::String("b".to_owned())),
+            Literal(Value::UInt(3)),
+            Collection(CollectionOpcode::BuildMap(3)),
+            Dup,
+            Collection(CollectionOpcode::Keys),
+            Swap,
+            Dup,
+            Literal(Value::UInt(2)),
+            Collection(CollectionOpcode::Contains),
+            Swap,
+            Literal(Value::String("b".to_owned())),
+            Collection(CollectionOpcode::Get),
+            Halt,
+        ]);
+
+        let mut vm = VM::new(bytecode);
+        vm.run().unwrap();
+
+        assert_eq!(
+            vm.stack,
+            vec![
+                Value::List(vec![Value::UInt(2), Value::String("b".to_owned())]),
+                Value::Bool(true),
+                Value::UInt(3),
+            ]
+        );
+
+        let error = run_error(vec![
+            Literal(Value::Null),
+            Literal(Value::UInt(1)),
+            Collection(CollectionOpcode::BuildMap(1)),
+            Halt,
+        ]);
+        assert_eq!(
+            error.kind,
+            VMErrorKind::TypeMismatch {
+                operation: "key",
+                lhs: "null",
+                rhs: None,
+            }
+        );
+
+        let error = run_error(vec![
+            Literal(Value::Map(Default::default())),
+            Literal(Value::String("x".to_owned())),
+            Collection(CollectionOpcode::Get),
+            Halt,
+        ]);
+        assert_eq!(error.kind, VMErrorKind::KeyNotFound("x".to_owned()));
+    }
+
+    #[test]
+    fn test_collection_errors() {
+        use crate::vm::VMErrorKind;
+        use vmo2_types::{
+            opcode::{CollectionOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let error = run_error(vec![
+            Literal(Value::List(vec![Value::UInt(1)])),
+            Literal(Value::UInt(1)),
+            Collection(CollectionOpcode::Get),
+            Halt,
+        ]);
+        assert_eq!(
+            error.kind,
+            VMErrorKind::IndexOutOfBounds { index: 1, len: 1 }
+        );
+        assert_eq!(error.pc, 2);
+
+        let error = run_error(vec![
+            Literal(Value::List(vec![])),
+            Collection(CollectionOpcode::Pop),
+            Halt,
+        ]);
+        assert_eq!(error.kind, VMErrorKind::EmptyList);
+
+        let error = run_error(vec![
+            Literal(Value::UInt(1)),
+            Collection(CollectionOpcode::BuildList(2)),
+            Halt,
+        ]);
+        assert_eq!(error.kind, VMErrorKind::StackUnderflow);
+    }
+
+    #[test]
+    fn test_control_flow_errors() {
+        use crate::vm::VMErrorKind;
+        use vmo2_types::{
+            opcode::{FlowOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let error = run_error(vec![Literal(Value::Null)]);
+        assert_eq!(error.kind, VMErrorKind::PcOutOfBounds);
+        assert_eq!(error.pc, 1);
+        assert_eq!(error.opcode, None);
+
+        let error = run_error(vec![Flow(FlowOpcode::Jump(42))]);
+        assert_eq!(error.kind, VMErrorKind::PcOutOfBounds);
+        assert_eq!(error.pc, 42);
+
+        let error = run_error(vec![Flow(FlowOpcode::Return), Halt]);
+        assert_eq!(error.kind, VMErrorKind::ReturnWithoutCall);
+        assert_eq!(error.pc, 0);
+        assert_eq!(
+            error.to_string(),
+            "return without call at pc 0 (Flow(Return))"
+        );
+    }
+
+    #[test]
+    fn test_buffer_io() {
+        use crate::io::BufferIo;
+        use crate::vm::VM;
+        use vmo2_types::{
+            bytecode::ByteCode,
+            opcode::{IOOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let bytecode = ByteCode::from(vec![
+            IO(IOOpcode::Scan),
+            IO(IOOpcode::Print),
+            Literal(Value::UInt(42)),
+            IO(IOOpcode::Print),
+            IO(IOOpcode::Scan),
+            Halt,
+        ]);
+
+        let io = BufferIo::with_input(["hello"]);
+        let mut vm = VM::new(bytecode).with_io(io.clone());
+        vm.run().unwrap();
+
+        assert_eq!(io.output(), vec!["hello", "42"]);
+        // input ran out on the second scan
+        assert_eq!(vm.stack, vec![Value::Null]);
+    }
+
+    #[test]
+    fn test_callback_io() {
+        use crate::io::CallbackIo;
+        use crate::vm::VM;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        use vmo2_types::{
+            bytecode::ByteCode,
+            opcode::{IOOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let bytecode = ByteCode::from(vec![
+            IO(IOOpcode::Scan),
+            IO(IOOpcode::Print),
+            Literal(Value::Bool(true)),
+            IO(IOOpcode::Print),
+            Halt,
+        ]);
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let sink = log.clone();
+        let io = CallbackIo::new(
+            move |value: &Value| sink.borrow_mut().push(format!("[program] {value}")),
+            || Some("input".to_owned()),
+        );
+
+        let mut vm = VM::new(bytecode).with_io(io);
+        vm.run().unwrap();
+
+        assert_eq!(*log.borrow(), vec!["[program] input", "[program] true"]);
+    }
+
+    fn run_limited(
+        opcodes: Vec<vmo2_types::opcode::Opcode>,
+        limits: crate::limits::Limits,
+    ) -> Result<crate::profile::Profile, crate::vm::VMError> {
+        crate::vm::VM::new(vmo2_types::bytecode::ByteCode::from(opcodes))
+            .with_limits(limits)
+            .run()
+    }
+
+    #[test]
+    fn test_instruction_limit() {
+        use crate::limits::Limits;
+        use crate::vm::VMErrorKind;
+        use vmo2_types::opcode::{FlowOpcode, Opcode::*};
+
+        let limits = Limits {
+            max_instructions: Some(100),
+            ..Limits::default()
+        };
+        let error = run_limited(vec![Flow(FlowOpcode::Jump(0))], limits.clone()).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::InstructionLimitExceeded(100));
+
+        // using up exactly the whole budget is fine
+        let mut opcodes = vec![Literal(vmo2_types::value::Value::Null)];
+        opcodes.extend(vec![Dup; 98]);
+        opcodes.push(Halt);
+        let profile = run_limited(opcodes, limits).unwrap();
+        assert_eq!(profile.executed_instructions, 100);
+    }
+
+    #[test]
+    fn test_stack_depth_limit() {
+        use crate::limits::Limits;
+        use crate::vm::VMErrorKind;
+        use vmo2_types::{
+            opcode::{FlowOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let limits = Limits {
+            max_stack_depth: Some(16),
+            ..Limits::default()
+        };
+        let error = run_limited(
+            vec![Literal(Value::UInt(1)), Flow(FlowOpcode::Jump(0))],
+            limits,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::StackOverflow(16));
+        assert_eq!(error.pc, 0);
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        use crate::limits::Limits;
+        use crate::vm::VMErrorKind;
+        use vmo2_types::opcode::{FlowOpcode, Opcode::*};
+
+        let limits = Limits {
+            max_call_depth: Some(8),
+            ..Limits::default()
+        };
+        let error = run_limited(vec![Flow(FlowOpcode::Call(0))], limits).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::CallDepthExceeded(8));
+    }
+
+    #[test]
+    fn test_heap_limits() {
+        use crate::limits::Limits;
+        use crate::vm::VMErrorKind;
+        use vmo2_types::{
+            opcode::{ArithmeticOpcode, CollectionOpcode, MemoryOpcode, Opcode::*},
+            value::Value,
+        };
+
+        let store = |value: Value, key: &str| {
+            [
+                Literal(value),
+                Literal(Value::String(key.to_owned())),
+                Memory(MemoryOpcode::Store),
+            ]
+        };
+
+        let limits = Limits {
+            max_heap_entries: Some(2),
+            ..Limits::default()
+        };
+
+        // overwriting an existing entry does not count
+        let opcodes = [
+            store(Value::UInt(1), "a"),
+            store(Value::UInt(2), "b"),
+            store(Value::UInt(3), "a"),
+        ]
+        .concat()
+        .into_iter()
+        .chain([Halt])
+        .collect();
+        assert!(run_limited(opcodes, limits.clone()).is_ok());
+
+        let opcodes = [
+            store(Value::UInt(1), "a"),
+            store(Value::UInt(2), "b"),
+            store(Value::UInt(3), "c"),
+        ]
+        .concat()
+        .into_iter()
+        .chain([Halt])
+        .collect();
+        let error = run_limited(opcodes, limits).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::HeapLimitExceeded(2));
+        assert_eq!(error.pc, 8);
+
+        let limits = Limits {
+            max_value_size: Some(4),
+            ..Limits::default()
+        };
+        let opcodes = store(Value::String("hello".to_owned()), "s")
+            .into_iter()
+            .chain([Halt])
+            .collect();
+        let error = run_limited(opcodes, limits.clone()).unwrap_err();
+        assert_eq!(error.kind, VMErrorKind::ValueTooLarge { size: 5, limit: 4 });
+
+        // repeating is checked before the string is built
+        let opcodes = vec![
+            Literal(Value::UInt(4_000_000_000)),
+            Literal(Value::String("a".to_owned())),
+            Arithmetic(ArithmeticOpcode::Mul),
+            Halt,
+        ];
+        let error = run_limited(opcodes, limits.clone()).unwrap_err();
+        assert_eq!(
+            error.kind,
+            VMErrorKind::ValueTooLarge {
+                size: 4_000_000_000,
+                limit: 4
+            }
+        );
+        assert_eq!(error.pc, 2);
+
+        // and so are concatenation and every value the VM builds, stored or not
+        let built = |opcodes: Vec<_>| {
+            let opcodes = opcodes.into_iter().chain([Halt]).collect();
+            run_limited(opcodes, limits.clone()).unwrap_err().kind
+        };
+        assert_eq!(
+            built(vec![
+                Literal(Value::String("abc".to_owned())),
+                Literal(Value::String("de".to_owned())),
+                Arithmetic(ArithmeticOpcode::Add),
+            ]),
+            VMErrorKind::ValueTooLarge { size: 5, limit: 4 }
+        );
+        let items = vec![Literal(Value::UInt(1)); 4];
+        assert_eq!(
+            built(
+                [
+                    items.clone(),
+                    vec![Collection(CollectionOpcode::BuildList(4))]
+                ]
+                .concat()
+            ),
+            VMErrorKind::ValueTooLarge { size: 5, limit: 4 }
+        );
+        assert_eq!(
+            built(
+                [
+                    items,
+                    vec![
+                        Collection(CollectionOpcode::BuildList(3)),
+                        Swap,
+                        Collection(CollectionOpcode::Push),
+                    ]
+                ]
+                .concat()
+            ),
+            VMErrorKind::ValueTooLarge { size: 5, limit: 4 }
+        );
+        assert_eq!(
+            built(vec![
+                Literal(Value::UInt(1)),
+                Literal(Value::UInt(2)),
+                Literal(Value::UInt(3)),
+                Literal(Value::UInt(4)),
+                Collection(CollectionOpcode::BuildMap(2)),
+            ]),
+            VMErrorKind::ValueTooLarge { size: 5, limit: 4 }
+        );
+    }
+
+    fn counting_loop() -> vmo2_types::bytecode::ByteCode {
+        use vmo2_types::{
+            bytecode::ByteCode,
+            opcode::{ArithmeticOpcode, ComparisonOpcode, FlowOpcode, MemoryOpcode, Opcode::*},
+            value::Value,
+        };
+
+        // n = 0; while (n < 3) { n = n + 1; }
+        ByteCode::from(vec![
+            Literal(Value::UInt(0)),
+            Literal(Value::String("n".to_owned())),
+            Memory(MemoryOpcode::Store),
+            Literal(Value::UInt(3)),
+            Literal(Value::String("n".to_owned())),
+            Memory(MemoryOpcode::Load),
+            Comparison(ComparisonOpcode::Lt),
+            Flow(FlowOpcode::JumpIfFalse(15)),
+            Literal(Value::UInt(1)),
+            Literal(Value::String("n".to_owned())),
+            Memory(MemoryOpcode::Load),
+            Arithmetic(ArithmeticOpcode::Add),
+            Literal(Value::String("n".to_owned())),
+            Memory(MemoryOpcode::Store),
+            Flow(FlowOpcode::Jump(3)),
+            Halt,
+        ])
+    }
+
+    #[test]
+    fn test_debugger_breakpoints() {
+        use crate::debugger::{Debugger, StopReason};
+        use crate::vm::VM;
+        use vmo2_types::value::Value;
+
+        let mut debugger = Debugger::new(VM::new(counting_loop()));
+        assert!(debugger.add_breakpoint(8));
+        assert!(!debugger.add_breakpoint(100));
+
+        for n in 0..3 {
+            assert_eq!(debugger.resume(), StopReason::Breakpoint(8));
+            assert_eq!(debugger.vm.heap["n"], Value::UInt(n));
+        }
+
+        assert!(debugger.remove_breakpoint(8));
+        assert_eq!(debugger.resume(), StopReason::Halted);
+        assert_eq!(debugger.vm.heap["n"], Value::UInt(3));
+
+        // stays halted
+        assert_eq!(debugger.step(), StopReason::Halted);
+    }
+
+    #[test]
+    fn test_debugger_breakpoint_at_current_pc() {
+        use crate::debugger::{Debugger, StopReason};
+        use crate::vm::VM;
+        use vmo2_types::value::Value;
+
+        let mut debugger = Debugger::new(VM::new(counting_loop()));
+        assert!(debugger.add_breakpoint(0));
+        assert!(debugger.add_breakpoint(3));
+
+        // nothing has run yet, the breakpoint at the entry still stops
+        assert_eq!(debugger.resume(), StopReason::Breakpoint(0));
+        assert_eq!(debugger.vm.pc, 0);
+        assert_eq!(debugger.resume(), StopReason::Breakpoint(3));
+
+        // stepping onto a breakpoint counts as stopping there
+        assert!(debugger.remove_breakpoint(3));
+        assert_eq!(debugger.step(), StopReason::Step);
+        assert!(debugger.add_breakpoint(4));
+        assert_eq!(debugger.resume(), StopReason::Breakpoint(4));
+        assert_eq!(debugger.vm.heap["n"], Value::UInt(1));
+    }
+
+    #[test]
+    fn test_debugger_step_and_disassemble() {
+        use crate::debugger::{Debugger, StopReason};
+        use crate::vm::VM;
+        use vmo2_types::{opcode::Opcode, value::Value};
+
+        let mut debugger = Debugger::new(VM::new(counting_loop()));
+
+        assert_eq!(debugger.step(), StopReason::Step);
+        assert_eq!(debugger.step(), StopReason::Step);
+        assert_eq!(debugger.vm.pc, 2);
+        assert_eq!(
+            debugger.vm.stack,
+            vec![Value::UInt(0), Value::String("n".to_owned())]
+        );
+
+        let listing = debugger.disassemble(2, 1);
+        assert_eq!(listing.len(), 3);
+        assert_eq!(listing[0].0, 1);
+        assert_eq!(
+            debugger.disassemble(15, 2).last(),
+            Some(&(15, &Opcode::Halt))
+        );
+    }
+
+    #[test]
+    fn test_debugger_stops_on_error() {
+        use crate::debugger::{Debugger, StopReason};
+        use crate::vm::{VM, VMErrorKind};
+        use vmo2_types::{bytecode::ByteCode, opcode::Opcode::*};
+
+        let mut debugger = Debugger::new(VM::new(ByteCode::from(vec![Dup, Halt])));
+
+        let StopReason::Error(error) = debugger.resume() else {
+            panic!("expected an error");
+        };
+        assert_eq!(error.kind, VMErrorKind::StackUnderflow);
+        assert_eq!(debugger.finished(), Some(&StopReason::Error(error)));
+    }
+
+    #[test]
+    fn test_debugger_line_breakpoints() {
+        use crate::debugger::{Debugger, StopReason};
+        use crate::vm::VM;
+        use vmo2_types::source_map::{SourceLocation, SourceMap};
+
+        let at = |line| Some(SourceLocation { line, column: 1 });
+        let mut locations = vec![at(1); 3];
+        locations.extend(vec![at(2); 5]);
+        locations.extend(vec![at(3); 6]);
+        locations.extend([at(2), None]);
+
+        let mut debugger =
+            Debugger::new(VM::new(counting_loop())).with_source_map(SourceMap { locations });
+
+        assert_eq!(debugger.add_line_breakpoint(3), vec![8]);
+        assert_eq!(debugger.add_line_breakpoint(9), Vec::<usize>::new());
+
+        assert_eq!(debugger.resume(), StopReason::Breakpoint(8));
+        assert_eq!(debugger.location(), at(3));
+    }
+
+    #[test]
+    fn test_debugger_repl() {
+        use crate::debugger::Debugger;
+        use crate::vm::VM;
+
+        let mut debugger = Debugger::new(VM::new(counting_loop()));
+        let commands =
+            "break 8\ncontinue\nstack\nheap\nstep 2\ndisas 1\nbogus\ndelete 8\ncontinue\n";
+        let mut output = Vec::new();
+        debugger.repl(commands.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("breakpoint at pc 8"));
+        assert!(output.contains("breakpoint: pc 8: push 1"));
+        assert!(output.contains("stack is empty"));
+        assert!(output.contains("  n = UInt(0)"));
+        assert!(output.contains("pc 10: load"));
+        assert!(output.contains("=>    10  load"));
+        assert!(output.contains("unknown command `bogus`"));
+        assert!(output.contains("deleted breakpoint at pc 8"));
+        assert!(output.ends_with("program halted\n(vmo2) "));
+    }
+
+    mod verifier {
+        use crate::io::BufferIo;
+        use crate::limits::Limits;
+        use crate::verifier::*;
+        use crate::vm::{VM, VMErrorKind};
+        use quickcheck::{Arbitrary, Gen, TestResult};
+        use quickcheck_macros::quickcheck;
+        use vmo2_types::bytecode::ByteCode;
+        use vmo2_types::opcode::{Opcode::*, *};
+        use vmo2_types::value::Value;
+
+        fn verify_opcodes(opcodes: Vec<Opcode>) -> Result<(), VerifyError> {
+            verify(&ByteCode::from(opcodes))
+        }
+
+        fn key(name: &str) -> Opcode {
+            Literal(Value::String(name.to_owned()))
+        }
+
+        #[test]
+        fn test_verify_loop_and_call() {
+            // n = 3; while (n > 0) { n = dec(n); } with dec(x) = x - 1
+            let bytecode = vec![
+                Literal(Value::UInt(3)),
+                key("n"),
+                Memory(MemoryOpcode::Store),
+                Literal(Value::UInt(0)),
+                key("n"),
+                Memory(MemoryOpcode::Load),
+                Comparison(ComparisonOpcode::Gt),
+                Flow(FlowOpcode::JumpIfFalse(15)),
+                key("n"),
+                Memory(MemoryOpcode::Load),
+                Flow(FlowOpcode::Call(16)),
+                key("n"),
+                Memory(MemoryOpcode::Store),
+                Flow(FlowOpcode::Jump(3)),
+                Halt,
+                Halt,
+                // dec
+                key("x"),
+                Memory(MemoryOpcode::Store),
+                Literal(Value::UInt(1)),
+                key("x"),
+                Memory(MemoryOpcode::Load),
+                Arithmetic(ArithmeticOpcode::Sub),
+                Flow(FlowOpcode::Return),
+            ];
+            assert_eq!(verify_opcodes(bytecode), Ok(()));
+        }
+
+        #[test]
+        fn test_verify_recursion() {
+            // f(n) = n > 0 ? f(n - 1) : n, the base case settles the summary
+            let bytecode = vec![
+                Literal(Value::UInt(2)),
+                Flow(FlowOpcode::Call(4)),
+                IO(IOOpcode::Print),
+                Halt,
+                Dup,
+                Literal(Value::UInt(0)),
+                Swap,
+                Comparison(ComparisonOpcode::Gt),
+                Flow(FlowOpcode::JumpIfFalse(14)),
+                Literal(Value::UInt(1)),
+                Swap,
+                Arithmetic(ArithmeticOpcode::Sub),
+                Flow(FlowOpcode::Call(4)),
+                Flow(FlowOpcode::Return),
+                Flow(FlowOpcode::Return),
+            ];
+            assert_eq!(verify_opcodes(bytecode), Ok(()));
+        }
+
+        #[test]
+        fn test_verify_errors() {
+            let error = |opcodes| verify_opcodes(opcodes).unwrap_err();
+
+            assert_eq!(
+                error(vec![Flow(FlowOpcode::Jump(2)), Halt]),
+                VerifyError {
+                    kind: VerifyErrorKind::JumpOutOfBounds(2),
+                    pc: 0,
+                }
+            );
+            assert_eq!(
+                error(vec![
+                    Literal(Value::UInt(1)),
+                    Arithmetic(ArithmeticOpcode::Add),
+                    Halt
+                ]),
+                VerifyError {
+                    kind: VerifyErrorKind::StackUnderflow,
+                    pc: 1,
+                }
+            );
+            assert_eq!(
+                error(vec![Literal(Value::Null), Pop]).to_string(),
+                "execution runs past the last opcode at pc 1"
+            );
+            assert_eq!(
+                error(vec![Flow(FlowOpcode::Return)]).kind,
+                VerifyErrorKind::ReturnWithoutCall
+            );
+            // one path pushes a value before joining the other
+            assert_eq!(
+                error(vec![
+                    Literal(Value::Bool(true)),
+                    Flow(FlowOpcode::JumpIfTrue(3)),
+                    Literal(Value::UInt(1)),
+                    Halt,
+                ]),
+                VerifyError {
+                    kind: VerifyErrorKind::StackMismatch {
+                        expected: 0,
+                        found: 1,
+                    },
+                    pc: 2,
+                }
+            );
+            // the function returns at different depths
+            assert_eq!(
+                error(vec![
+                    Flow(FlowOpcode::Call(2)),
+                    Halt,
+                    Flow(FlowOpcode::JumpIfTrue(4)),
+                    Flow(FlowOpcode::Return),
+                    Pop,
+                    Flow(FlowOpcode::Return),
+                ])
+                .kind,
+                VerifyErrorKind::StackMismatch {
+                    expected: -2,
+                    found: -1,
+                }
+            );
+            // the function needs more values than the caller has
+            assert_eq!(
+                error(vec![
+                    Flow(FlowOpcode::Call(2)),
+                    Halt,
+                    Pop,
+                    Flow(FlowOpcode::Return),
+                ]),
+                VerifyError {
+                    kind: VerifyErrorKind::StackUnderflow,
+                    pc: 0,
+                }
+            );
+            // even when it never returns
+            assert_eq!(
+                error(vec![Flow(FlowOpcode::Call(2)), Halt, Swap, Halt]),
+                VerifyError {
+                    kind: VerifyErrorKind::StackUnderflow,
+                    pc: 0,
+                }
+            );
+        }
+
+        #[test]
+        fn test_verify_types() {
+            let error = |opcodes| verify_opcodes(opcodes).unwrap_err().to_string();
+
+            assert_eq!(
+                error(vec![
+                    Literal(Value::UInt(1)),
+                    Literal(Value::String("a".to_owned())),
+                    Arithmetic(ArithmeticOpcode::Sub),
+                    Halt,
+                ]),
+                "type mismatch: cannot apply `sub` to string and uint at pc 2"
+            );
+            assert_eq!(
+                error(vec![
+                    Literal(Value::UInt(1)),
+                    Literal(Value::UInt(2)),
+                    Comparison(ComparisonOpcode::Lt),
+                    Arithmetic(ArithmeticOpcode::Neg),
+                    Halt,
+                ]),
+                "type mismatch: cannot apply `neg` to bool at pc 3"
+            );
+            assert_eq!(
+                error(vec![
+                    Literal(Value::UInt(1)),
+                    Memory(MemoryOpcode::Load),
+                    Halt,
+                ]),
+                "type mismatch: cannot apply `load` to uint at pc 1"
+            );
+            assert_eq!(
+                error(vec![
+                    Literal(Value::Float(1.5)),
+                    Literal(Value::Null),
+                    Collection(CollectionOpcode::BuildMap(1)),
+                    Halt,
+                ]),
+                "type mismatch: cannot apply `key` to float at pc 2"
+            );
+
+            // unknown types, and errors that depend on the values, are left
+            // to the VM
+            assert_eq!(
+                verify_opcodes(vec![
+                    IO(IOOpcode::Scan),
+                    Literal(Value::UInt(1)),
+                    Swap,
+                    Flow(FlowOpcode::Jump(5)),
+                    Halt,
+                    Literal(Value::UInt(0)),
+                    Swap,
+                    Arithmetic(ArithmeticOpcode::Div),
+                    Literal(Value::List(vec![])),
+                    Collection(CollectionOpcode::Pop),
+                    Halt,
+                ]),
+                Ok(())
+            );
+        }
+
+        /// Short chunks of mostly balanced stack code, linked with jumps and
+        /// calls into the program.
+        #[derive(Clone, Debug)]
+        struct Program(ByteCode);
+
+        impl Arbitrary for Program {
+            fn arbitrary(g: &mut Gen) -> Self {
+                let keys = ["a", "b"];
+                let mut opcodes = vec![];
+                for _ in 0..u8::arbitrary(g) % 24 {
+                    let chunk = match u8::arbitrary(g) % 14 {
+                        0 => vec![Dup],
+                        1 => vec![Pop],
+                        2 => vec![Swap],
+                        3 => vec![key(g.choose(&keys).unwrap()), Memory(MemoryOpcode::Load)],
+                        4 => vec![key(g.choose(&keys).unwrap()), Memory(MemoryOpcode::Store)],
+                        5 => vec![Arithmetic(ArithmeticOpcode::Add)],
+                        6 => vec![IO(IOOpcode::Print)],
+                        7 => vec![Literal(Value::Bool(bool::arbitrary(g)))],
+                        8 => vec![Flow(FlowOpcode::Jump(u32::arbitrary(g)))],
+                        9 => vec![Flow(FlowOpcode::JumpIfFalse(u32::arbitrary(g)))],
+                        10 => vec![Flow(FlowOpcode::Call(u32::arbitrary(g)))],
+                        11 => vec![Flow(FlowOpcode::Return)],
+                        12 => vec![Halt],
+                        _ => vec![Literal(Value::UInt(u32::arbitrary(g) % 4))],
+                    };
+                    opcodes.extend(chunk);
+                }
+                opcodes.push(Halt);
+
+                let len = opcodes.len() as u32;
+                for opcode in &mut opcodes {
+                    if let Flow(
+                        FlowOpcode::Jump(target)
+                        | FlowOpcode::JumpIfFalse(target)
+                        | FlowOpcode::Call(target),
+                    ) = opcode
+                    {
+                        *target %= len;
+                    }
+                }
+                Program(ByteCode::from(opcodes))
+            }
+        }
+
+        #[quickcheck]
+        fn verified_programs_do_not_underflow(program: Program) -> TestResult {
+            if verify(&program.0).is_err() {
+                return TestResult::discard();
+            }
+            let mut vm = VM::new(program.0)
+                .with_io(BufferIo::new())
+                .with_limits(Limits {
+                    max_instructions: Some(2_000),
+                    ..Limits::unlimited()
+                });
+            TestResult::from_bool(!matches!(
+                vm.run().map_err(|error| error.kind),
+                Err(VMErrorKind::StackUnderflow
+                    | VMErrorKind::PcOutOfBounds
+                    | VMErrorKind::ReturnWithoutCall)
+            ))
+        }
+    }
 }
@@ -0,0 +1,541 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use vmo2_types::bytecode::ByteCode;
+use vmo2_types::opcode::*;
+use vmo2_types::value::{Value, ValueError};
+
+/*
+ *  Static verification of bytecode before it runs.
+ *
+ *  Every flow opcode must target an address inside the program. The rest is
+ *  an abstract interpretation of the opcode stream that follows each path
+ *  from address 0 and from every function reached through a `Call`,
+ *  tracking the stack depth and, for values whose type is statically known
+ *  (literals, comparisons, built lists and maps), that type. It rejects:
+ *
+ *  - popping more values than the stack holds
+ *  - two paths reaching an address with different stack depths
+ *  - running past the last opcode instead of ending in `Halt` or `Return`
+ *  - `Return` outside of a function
+ *  - an operator applied to operand types it always fails on
+ *
+ *  The stack is shared between a caller and its callee, so a function is
+ *  summarized by the number of caller values it consumes and the depth it
+ *  returns at, relative to its entry. Summaries are computed in rounds
+ *  until they settle, a `Call` whose callee has not returned so far does
+ *  not continue past the call.
+ */
+
+/// Top stack slots whose types are tracked, deeper values are unknown.
+const TRACKED_SLOTS: usize = 64;
+
+/// A program the verifier rejects, located at the faulting opcode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    pub kind: VerifyErrorKind,
+    pub pc: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    JumpOutOfBounds(u32),
+    StackUnderflow,
+    /// Stack depths, relative to the function entry, of two paths meeting.
+    StackMismatch {
+        expected: i64,
+        found: i64,
+    },
+    FallsOffEnd,
+    ReturnWithoutCall,
+    /// The function at this address consumes a different number of values
+    /// from its caller every time its summary is recomputed.
+    UnboundedStackEffect(u32),
+    TypeMismatch {
+        operation: &'static str,
+        lhs: &'static str,
+        rhs: Option<&'static str>,
+    },
+}
+
+impl fmt::Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyErrorKind::JumpOutOfBounds(target) => {
+                write!(f, "jump target {target} is out of bounds")
+            }
+            VerifyErrorKind::StackUnderflow => write!(f, "stack underflow"),
+            VerifyErrorKind::StackMismatch { expected, found } => write!(
+                f,
+                "stack depth {found} does not match depth {expected} of another path"
+            ),
+            VerifyErrorKind::FallsOffEnd => write!(f, "execution runs past the last opcode"),
+            VerifyErrorKind::ReturnWithoutCall => write!(f, "return without call"),
+            VerifyErrorKind::UnboundedStackEffect(entry) => write!(
+                f,
+                "the function at {entry} consumes an unbounded number of values"
+            ),
+            VerifyErrorKind::TypeMismatch {
+                operation,
+                lhs,
+                rhs: Some(rhs),
+            } => write!(
+                f,
+                "type mismatch: cannot apply `{operation}` to {lhs} and {rhs}"
+            ),
+            VerifyErrorKind::TypeMismatch {
+                operation,
+                lhs,
+                rhs: None,
+            } => write!(f, "type mismatch: cannot apply `{operation}` to {lhs}"),
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at pc {}", self.kind, self.pc)
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Abstract stack at an address, relative to the entry of the function
+/// being analyzed.
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    /// Values of the caller popped so far.
+    below: u64,
+    /// Values above the lowest point reached.
+    depth: u64,
+    /// Up to `TRACKED_SLOTS` top slots, last on top: a value of the slot's
+    /// type (see `representative`) when it is statically known. Slots below
+    /// are unknown.
+    known: Vec<Option<Value>>,
+}
+
+impl State {
+    fn relative_depth(&self) -> i64 {
+        self.depth as i64 - self.below as i64
+    }
+
+    /// The `n`th slot from the top, when its type is known.
+    fn peek(&self, n: usize) -> Option<&Value> {
+        self.known.iter().rev().nth(n)?.as_ref()
+    }
+
+    /// Pops `n` values, taking them from the caller inside a function.
+    fn pop(&mut self, n: u64, in_function: bool) -> Result<(), VerifyErrorKind> {
+        if n > self.depth {
+            if !in_function {
+                return Err(VerifyErrorKind::StackUnderflow);
+            }
+            self.below += n - self.depth;
+            self.depth = 0;
+        } else {
+            self.depth -= n;
+        }
+        let n = usize::try_from(n).unwrap_or(usize::MAX);
+        self.known.truncate(self.known.len().saturating_sub(n));
+        Ok(())
+    }
+
+    fn push(&mut self, value: Option<Value>) {
+        self.depth += 1;
+        self.known.push(value);
+        self.trim();
+    }
+
+    fn push_unknown(&mut self, n: u64) {
+        self.depth += n;
+        let n = usize::try_from(n).unwrap_or(usize::MAX).min(TRACKED_SLOTS);
+        self.known.extend(std::iter::repeat_n(None, n));
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        let excess = self.known.len().saturating_sub(TRACKED_SLOTS);
+        self.known.drain(..excess);
+    }
+
+    /// Joins the state of another path into this one, returning whether it
+    /// changed.
+    fn merge(&mut self, other: &State) -> Result<bool, VerifyErrorKind> {
+        if self.relative_depth() != other.relative_depth() {
+            return Err(VerifyErrorKind::StackMismatch {
+                expected: self.relative_depth(),
+                found: other.relative_depth(),
+            });
+        }
+        let tracked = self.known.len().min(other.known.len());
+        let known = self.known[self.known.len() - tracked..]
+            .iter()
+            .zip(&other.known[other.known.len() - tracked..])
+            .map(|(a, b)| if a == b { a.clone() } else { None })
+            .collect();
+        let merged = State {
+            below: self.below.max(other.below),
+            depth: self.depth.max(other.depth),
+            known,
+        };
+
+        let changed = merged != *self;
+        *self = merged;
+        Ok(changed)
+    }
+}
+
+/// Stack effect of a function: caller values it consumes and its depth at
+/// `Return` relative to its entry, `None` while it never returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Summary {
+    needs: u64,
+    returns: Option<i64>,
+}
+
+/// Result of following one function (or the top level) to a fixpoint.
+struct Analysis {
+    states: BTreeMap<usize, State>,
+    summary: Summary,
+    calls: BTreeSet<usize>,
+}
+
+/// Checks `bytecode` as described above.
+pub fn verify(bytecode: &ByteCode) -> Result<(), VerifyError> {
+    let opcodes = &bytecode.opcodes;
+    for (pc, opcode) in opcodes.iter().enumerate() {
+        if let Some(target) = target(opcode)
+            && target as usize >= opcodes.len()
+        {
+            return Err(VerifyError {
+                kind: VerifyErrorKind::JumpOutOfBounds(target),
+                pc,
+            });
+        }
+    }
+
+    let mut summaries: HashMap<usize, Summary> = HashMap::new();
+    let mut rounds = 0;
+    let analyses = loop {
+        let mut analyses = vec![(0, analyze(opcodes, 0, false, &summaries)?)];
+        let mut entries: BTreeSet<usize> = analyses[0].1.calls.clone();
+        let mut analyzed = BTreeSet::new();
+        while let Some(&entry) = entries.difference(&analyzed).next() {
+            analyzed.insert(entry);
+            let analysis = analyze(opcodes, entry, true, &summaries)?;
+            entries.extend(&analysis.calls);
+            analyses.push((entry, analysis));
+        }
+
+        let updated: HashMap<usize, Summary> = analyses
+            .iter()
+            .skip(1)
+            .map(|(entry, analysis)| (*entry, analysis.summary))
+            .collect();
+        if updated == summaries {
+            break analyses;
+        }
+        rounds += 1;
+        // each round settles at least one more function, unless one keeps
+        // consuming more of its caller's values
+        if rounds > 2 * analyses.len() + 2 {
+            let entry = updated
+                .iter()
+                .find(|(entry, summary)| summaries.get(entry) != Some(summary))
+                .map_or(0, |(entry, _)| *entry);
+            return Err(VerifyError {
+                kind: VerifyErrorKind::UnboundedStackEffect(entry as u32),
+                pc: entry,
+            });
+        }
+        summaries = updated;
+    };
+
+    for (_, analysis) in &analyses {
+        for (&pc, state) in &analysis.states {
+            check_types(&opcodes[pc], state).map_err(|kind| VerifyError { kind, pc })?;
+        }
+    }
+    Ok(())
+}
+
+/// Address a flow opcode transfers control to.
+fn target(opcode: &Opcode) -> Option<u32> {
+    match opcode {
+        Opcode::Flow(
+            FlowOpcode::JumpIfFalse(target)
+            | FlowOpcode::JumpIfTrue(target)
+            | FlowOpcode::Jump(target)
+            | FlowOpcode::Call(target),
+        ) => Some(*target),
+        _ => None,
+    }
+}
+
+/// A value standing for every value of the type of `value`, small enough
+/// for any operation to be cheap.
+fn representative(value: &Value) -> Value {
+    match value {
+        Value::UInt(_) => Value::UInt(1),
+        Value::Int(_) => Value::Int(1),
+        Value::Float(_) => Value::Float(1.0),
+        Value::Bool(_) => Value::Bool(true),
+        Value::String(_) => Value::String(String::new()),
+        Value::List(_) => Value::List(vec![]),
+        Value::Map(_) => Value::Map(Default::default()),
+        Value::Null => Value::Null,
+    }
+}
+
+/// Follows every path of the function at `entry` (the top level when not
+/// `in_function`) until the states settle.
+fn analyze(
+    opcodes: &[Opcode],
+    entry: usize,
+    in_function: bool,
+    summaries: &HashMap<usize, Summary>,
+) -> Result<Analysis, VerifyError> {
+    let mut analysis = Analysis {
+        states: BTreeMap::new(),
+        summary: Summary {
+            needs: 0,
+            returns: None,
+        },
+        calls: BTreeSet::new(),
+    };
+    let initial = State {
+        below: 0,
+        depth: 0,
+        known: vec![],
+    };
+    let mut needs = 0;
+    let mut returns = None;
+    let mut pending = vec![entry];
+    if entry >= opcodes.len() {
+        return Err(VerifyError {
+            kind: VerifyErrorKind::FallsOffEnd,
+            pc: entry,
+        });
+    }
+    analysis.states.insert(entry, initial);
+
+    while let Some(pc) = pending.pop() {
+        let error = |kind| VerifyError { kind, pc };
+        let mut state = analysis.states[&pc].clone();
+        let opcode = &opcodes[pc];
+
+        let mut successors = vec![];
+        match opcode {
+            Opcode::Flow(FlowOpcode::Jump(target)) => successors.push(*target as usize),
+            Opcode::Flow(FlowOpcode::JumpIfTrue(target) | FlowOpcode::JumpIfFalse(target)) => {
+                state.pop(1, in_function).map_err(error)?;
+                successors.extend([pc + 1, *target as usize]);
+            }
+            Opcode::Flow(FlowOpcode::Call(target)) => {
+                let target = *target as usize;
+                analysis.calls.insert(target);
+                if let Some(summary) = summaries.get(&target) {
+                    state.pop(summary.needs, in_function).map_err(error)?;
+                    if let Some(returns) = summary.returns {
+                        state.push_unknown((summary.needs as i64 + returns) as u64);
+                        successors.push(pc + 1);
+                    }
+                }
+            }
+            Opcode::Flow(FlowOpcode::Return) => {
+                if !in_function {
+                    return Err(error(VerifyErrorKind::ReturnWithoutCall));
+                }
+                let depth = state.relative_depth();
+                match returns {
+                    Some(expected) if expected != depth => {
+                        return Err(error(VerifyErrorKind::StackMismatch {
+                            expected,
+                            found: depth,
+                        }));
+                    }
+                    _ => returns = Some(depth),
+                }
+            }
+            Opcode::Halt => {}
+            _ => {
+                apply(opcode, &mut state, in_function).map_err(error)?;
+                successors.push(pc + 1);
+            }
+        }
+        needs = needs.max(state.below);
+
+        for successor in successors {
+            if successor >= opcodes.len() {
+                return Err(error(VerifyErrorKind::FallsOffEnd));
+            }
+            let changed = match analysis.states.get_mut(&successor) {
+                Some(existing) => existing.merge(&state).map_err(error)?,
+                None => {
+                    analysis.states.insert(successor, state.clone());
+                    true
+                }
+            };
+            if changed {
+                pending.push(successor);
+            }
+        }
+    }
+
+    analysis.summary = Summary { needs, returns };
+    Ok(analysis)
+}
+
+/// Effect of a straight-line opcode on `state`.
+fn apply(opcode: &Opcode, state: &mut State, in_function: bool) -> Result<(), VerifyErrorKind> {
+    use Opcode::*;
+
+    let bool = || Some(Value::Bool(true));
+    let list = || Some(Value::List(vec![]));
+    let (pops, pushed): (u64, Vec<Option<Value>>) = match opcode {
+        Literal(value) => (0, vec![Some(representative(value))]),
+        Arithmetic(ArithmeticOpcode::Neg) => (1, vec![None]),
+        Arithmetic(_) => (2, vec![None]),
+        Logic(LogicOpcode::Not) => (1, vec![bool()]),
+        Logic(_) => (2, vec![bool()]),
+        Comparison(_) => (2, vec![bool()]),
+        Memory(MemoryOpcode::Load) => (1, vec![None]),
+        Memory(MemoryOpcode::Store) => (2, vec![]),
+        IO(IOOpcode::Print) => (1, vec![]),
+        IO(IOOpcode::Scan) => (0, vec![None]),
+        Collection(collection) => match collection {
+            CollectionOpcode::BuildList(n) => (*n as u64, vec![list()]),
+            CollectionOpcode::BuildMap(n) => {
+                (2 * *n as u64, vec![Some(Value::Map(Default::default()))])
+            }
+            CollectionOpcode::Get => (2, vec![None]),
+            CollectionOpcode::Set => (3, vec![state.peek(2).cloned()]),
+            CollectionOpcode::Len => (1, vec![None]),
+            CollectionOpcode::Push => (2, vec![list()]),
+            CollectionOpcode::Pop => (1, vec![list(), None]),
+            CollectionOpcode::Keys => (1, vec![list()]),
+            CollectionOpcode::Contains => (2, vec![bool()]),
+        },
+        Dup => (1, vec![state.peek(0).cloned(); 2]),
+        Pop => (1, vec![]),
+        Swap => (2, vec![state.peek(0).cloned(), state.peek(1).cloned()]),
+        Flow(_) | Halt => unreachable!(),
+    };
+    state.pop(pops, in_function)?;
+    for value in pushed {
+        state.push(value);
+    }
+    Ok(())
+}
+
+/// Checks that `opcode` accepts the operand types known in `state`, as the
+/// VM would evaluate it.
+fn check_types(opcode: &Opcode, state: &State) -> Result<(), VerifyErrorKind> {
+    use Opcode::*;
+
+    let mismatch = |operation, value: &Value| VerifyErrorKind::TypeMismatch {
+        operation,
+        lhs: value.type_name(),
+        rhs: None,
+    };
+    // operands as the VM pops them: `a` on top, then `b`, then `c`
+    let (a, b, c) = (state.peek(0), state.peek(1), state.peek(2));
+    let known = |a: Option<&Value>| a.cloned();
+
+    let result: Option<Result<(), ValueError>> = match opcode {
+        Arithmetic(arithmetic) => {
+            let a = known(a);
+            match arithmetic {
+                ArithmeticOpcode::Neg => a.map(|a| (-a).map(drop)),
+                _ => a.zip(known(b)).map(|(a, b)| {
+                    match arithmetic {
+                        ArithmeticOpcode::Add => a + b,
+                        ArithmeticOpcode::Sub => a - b,
+                        ArithmeticOpcode::Mul => a * b,
+                        ArithmeticOpcode::Div => a / b,
+                        ArithmeticOpcode::Mod => a % b,
+                        ArithmeticOpcode::Neg => unreachable!(),
+                    }
+                    .map(drop)
+                }),
+            }
+        }
+        Logic(logic) => {
+            let a = known(a);
+            match logic {
+                LogicOpcode::Not => a.map(|a| (!a).map(drop)),
+                _ => a.zip(known(b)).map(|(a, b)| {
+                    match logic {
+                        LogicOpcode::And => a.and(b),
+                        LogicOpcode::Or => a.or(b),
+                        LogicOpcode::Xor => a.xor(b),
+                        LogicOpcode::Not => unreachable!(),
+                    }
+                    .map(drop)
+                }),
+            }
+        }
+        Comparison(ComparisonOpcode::Eq | ComparisonOpcode::Ne) => None,
+        Comparison(_) => a.zip(b).map(|(a, b)| a.compare(b).map(drop)),
+        Memory(memory) => {
+            let operation = match memory {
+                MemoryOpcode::Load => "load",
+                MemoryOpcode::Store => "store",
+            };
+            return match a {
+                Some(key) if !matches!(key, Value::String(_)) => Err(mismatch(operation, key)),
+                _ => Ok(()),
+            };
+        }
+        Flow(FlowOpcode::JumpIfTrue(_) | FlowOpcode::JumpIfFalse(_)) => {
+            let operation = match opcode {
+                Flow(FlowOpcode::JumpIfTrue(_)) => "jump_if_true",
+                _ => "jump_if_false",
+            };
+            return match a {
+                Some(condition) if !matches!(condition, Value::Bool(_)) => {
+                    Err(mismatch(operation, condition))
+                }
+                _ => Ok(()),
+            };
+        }
+        Collection(collection) => match collection {
+            CollectionOpcode::Get => b
+                .zip(a)
+                .map(|(collection, index)| collection.get(index).map(drop)),
+            CollectionOpcode::Set => c
+                .zip(b)
+                .zip(known(a))
+                .map(|((collection, index), value)| collection.clone().set(index, value).map(drop)),
+            CollectionOpcode::Len => a.map(|a| a.len().map(drop)),
+            CollectionOpcode::Push => b
+                .zip(known(a))
+                .map(|(list, value)| list.clone().push(value).map(drop)),
+            CollectionOpcode::Pop => a.map(|a| a.clone().pop().map(drop)),
+            CollectionOpcode::Keys => a.map(|a| a.keys().map(drop)),
+            CollectionOpcode::Contains => b
+                .zip(a)
+                .map(|(collection, needle)| collection.contains(needle).map(drop)),
+            // keys are every other value, the first one deepest
+            CollectionOpcode::BuildMap(n) => (0..*n as usize)
+                .filter_map(|i| state.peek(2 * (*n as usize - i) - 1))
+                .map(|key| key.clone().map_key().map(drop))
+                .find(Result::is_err),
+            CollectionOpcode::BuildList(_) => None,
+        },
+        _ => None,
+    };
+
+    match result {
+        Some(Err(ValueError::TypeMismatch {
+            operation,
+            lhs,
+            rhs,
+        })) => Err(VerifyErrorKind::TypeMismatch {
+            operation,
+            lhs,
+            rhs,
+        }),
+        // errors that depend on the values are left to the VM
+        _ => Ok(()),
+    }
+}
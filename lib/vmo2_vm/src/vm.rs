@@ -1,19 +1,34 @@
+use crate::io::{StdIo, VmIo};
+use crate::limits::{self, Limits};
 use crate::profile;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 
 use vmo2_types::{
-    bytecode, opcode,
-    value::{self, Value},
+    bytecode,
+    opcode::{self, Opcode},
+    value::{self, Value, ValueError},
 };
 
 pub struct VM {
     pub stack: Vec<value::Value>,
     pub heap: HashMap<String, value::Value>,
     pub pc: usize,
-    pub call_stack: Vec<usize>,
+    pub call_stack: Vec<Frame>,
     pub bytecode: bytecode::ByteCode,
     pub debug: bool,
     pub profile: profile::Profile,
+    pub io: Box<dyn VmIo>,
+    pub limits: Limits,
+}
+
+/// Activation record of a function call.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Frame {
+    /// Where `Return` resumes in the caller.
+    pub return_address: usize,
+    /// Variables stored while this frame is on top.
+    pub locals: HashMap<String, Value>,
 }
 
 #[derive(Debug)]
@@ -23,14 +38,127 @@ pub enum VMResult {
     Halt,
 }
 
-#[derive(Debug)]
-pub enum VMError {
+/// A runtime error together with the instruction that raised it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VMError {
+    pub kind: VMErrorKind,
+    /// Address of the faulting instruction.
+    pub pc: usize,
+    /// The faulting instruction, `None` when `pc` itself is out of bounds.
+    pub opcode: Option<Opcode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VMErrorKind {
     StackUnderflow,
-    HeapUnderflow,
-    InvalidOpcode,
-    InvalidOpcodeArgument,
+    UndefinedVariable(String),
+    TypeMismatch {
+        operation: &'static str,
+        lhs: &'static str,
+        rhs: Option<&'static str>,
+    },
+    DivisionByZero,
+    ArithmeticOverflow,
+    IndexOutOfBounds {
+        index: i64,
+        len: usize,
+    },
+    EmptyList,
+    KeyNotFound(String),
+    PcOutOfBounds,
+    ReturnWithoutCall,
+    Io(String),
+    InstructionLimitExceeded(usize),
+    StackOverflow(usize),
+    CallDepthExceeded(usize),
+    HeapLimitExceeded(usize),
+    ValueTooLarge {
+        size: usize,
+        limit: usize,
+    },
+}
+
+impl From<ValueError> for VMErrorKind {
+    fn from(error: ValueError) -> Self {
+        match error {
+            ValueError::TypeMismatch {
+                operation,
+                lhs,
+                rhs,
+            } => VMErrorKind::TypeMismatch {
+                operation,
+                lhs,
+                rhs,
+            },
+            ValueError::DivisionByZero => VMErrorKind::DivisionByZero,
+            ValueError::Overflow => VMErrorKind::ArithmeticOverflow,
+            ValueError::IndexOutOfBounds { index, len } => {
+                VMErrorKind::IndexOutOfBounds { index, len }
+            }
+            ValueError::EmptyList => VMErrorKind::EmptyList,
+            ValueError::KeyNotFound(key) => VMErrorKind::KeyNotFound(key),
+        }
+    }
 }
 
+impl fmt::Display for VMErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VMErrorKind::StackUnderflow => write!(f, "stack underflow"),
+            VMErrorKind::UndefinedVariable(name) => write!(f, "undefined variable `{name}`"),
+            VMErrorKind::TypeMismatch {
+                operation,
+                lhs,
+                rhs: Some(rhs),
+            } => write!(
+                f,
+                "type mismatch: cannot apply `{operation}` to {lhs} and {rhs}"
+            ),
+            VMErrorKind::TypeMismatch {
+                operation,
+                lhs,
+                rhs: None,
+            } => write!(f, "type mismatch: cannot apply `{operation}` to {lhs}"),
+            VMErrorKind::DivisionByZero => write!(f, "division by zero"),
+            VMErrorKind::ArithmeticOverflow => write!(f, "arithmetic overflow"),
+            VMErrorKind::IndexOutOfBounds { index, len } => {
+                write!(f, "index {index} out of bounds for length {len}")
+            }
+            VMErrorKind::EmptyList => write!(f, "pop from an empty list"),
+            VMErrorKind::KeyNotFound(key) => write!(f, "key {key} not found"),
+            VMErrorKind::PcOutOfBounds => write!(f, "program counter out of bounds"),
+            VMErrorKind::ReturnWithoutCall => write!(f, "return without call"),
+            VMErrorKind::Io(message) => write!(f, "i/o error: {message}"),
+            VMErrorKind::InstructionLimitExceeded(limit) => {
+                write!(f, "instruction limit of {limit} exceeded")
+            }
+            VMErrorKind::StackOverflow(limit) => {
+                write!(f, "stack overflow: more than {limit} values")
+            }
+            VMErrorKind::CallDepthExceeded(limit) => {
+                write!(f, "call depth limit of {limit} exceeded")
+            }
+            VMErrorKind::HeapLimitExceeded(limit) => {
+                write!(f, "heap limit of {limit} entries exceeded")
+            }
+            VMErrorKind::ValueTooLarge { size, limit } => {
+                write!(f, "value of size {size} exceeds the limit of {limit}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for VMError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.opcode {
+            Some(opcode) => write!(f, "{} at pc {} ({:?})", self.kind, self.pc, opcode),
+            None => write!(f, "{} at pc {}", self.kind, self.pc),
+        }
+    }
+}
+
+impl std::error::Error for VMError {}
+
 impl VM {
     pub fn new(bytecode: bytecode::ByteCode) -> Self {
         Self {
@@ -41,9 +169,23 @@ impl VM {
             pc: 0,
             debug: false,
             profile: profile::Profile::new(),
+            io: Box::new(StdIo),
+            limits: Limits::unlimited(),
         }
     }
 
+    /// Replaces the I/O host, stdio by default.
+    pub fn with_io(mut self, io: impl VmIo + 'static) -> Self {
+        self.io = Box::new(io);
+        self
+    }
+
+    /// Sets the execution limits, unlimited by default.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
     pub fn run(&mut self) -> Result<profile::Profile, VMError> {
         loop {
             match self.step() {
@@ -56,88 +198,207 @@ impl VM {
         Ok(self.profile.clone())
     }
 
-    fn step(&mut self) -> VMResult {
-        let opcode = self.bytecode.opcodes[self.pc].clone();
+    /// Executes the instruction at `pc`.
+    pub fn step(&mut self) -> VMResult {
+        let pc = self.pc;
+        let Some(opcode) = self.bytecode.opcodes.get(pc).cloned() else {
+            return VMResult::Error(VMError {
+                kind: VMErrorKind::PcOutOfBounds,
+                pc,
+                opcode: None,
+            });
+        };
+        if let Some(limit) = self.limits.max_instructions
+            && self.profile.executed_instructions >= limit
+        {
+            return VMResult::Error(VMError {
+                kind: VMErrorKind::InstructionLimitExceeded(limit),
+                pc,
+                opcode: Some(opcode),
+            });
+        }
         self.pc += 1;
         self.profile.executed_instructions += 1;
 
+        match self.execute(opcode.clone()) {
+            Ok(result) => result,
+            Err(kind) => {
+                // leave the pc on the faulting instruction
+                self.pc = pc;
+                VMResult::Error(VMError {
+                    kind,
+                    pc,
+                    opcode: Some(opcode),
+                })
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Result<Value, VMErrorKind> {
+        let value = self.stack.pop().ok_or(VMErrorKind::StackUnderflow)?;
+        self.profile.total_stack_pops += 1;
+        Ok(value)
+    }
+
+    fn push(&mut self, value: Value) -> Result<(), VMErrorKind> {
+        if let Some(limit) = self.limits.max_stack_depth
+            && self.stack.len() >= limit
+        {
+            return Err(VMErrorKind::StackOverflow(limit));
+        }
+        if let Some(limit) = self.limits.max_value_size {
+            let size = limits::value_size(&value);
+            if size > limit {
+                return Err(VMErrorKind::ValueTooLarge { size, limit });
+            }
+        }
+        self.stack.push(value);
+        self.profile.total_stack_pushes += 1;
+        Ok(())
+    }
+
+    /// Fails before `a op b` builds a string over `max_value_size`: `+`
+    /// concatenates and `*` repeats up to `u32::MAX` times, so the result
+    /// can be far larger than the operands.
+    fn check_string_result(
+        &self,
+        op: &opcode::ArithmeticOpcode,
+        a: &Value,
+        b: &Value,
+    ) -> Result<(), VMErrorKind> {
+        let Some(limit) = self.limits.max_value_size else {
+            return Ok(());
+        };
+        let size = match (op, a, b) {
+            (opcode::ArithmeticOpcode::Add, Value::String(a), Value::String(b)) => {
+                a.len().checked_add(b.len())
+            }
+            (opcode::ArithmeticOpcode::Mul, Value::String(s), Value::UInt(n))
+            | (opcode::ArithmeticOpcode::Mul, Value::UInt(n), Value::String(s)) => {
+                s.len().checked_mul(*n as usize)
+            }
+            _ => return Ok(()),
+        }
+        .unwrap_or(usize::MAX);
+        if size > limit {
+            return Err(VMErrorKind::ValueTooLarge { size, limit });
+        }
+        Ok(())
+    }
+
+    fn pop_key(&mut self, operation: &'static str) -> Result<String, VMErrorKind> {
+        match self.pop()? {
+            Value::String(key) => Ok(key),
+            other => Err(VMErrorKind::TypeMismatch {
+                operation,
+                lhs: other.type_name(),
+                rhs: None,
+            }),
+        }
+    }
+
+    /// Variables written by `Store`: the top frame's locals inside a call,
+    /// the global heap otherwise.
+    fn variables_mut(&mut self) -> &mut HashMap<String, Value> {
+        match self.call_stack.last_mut() {
+            Some(frame) => &mut frame.locals,
+            None => &mut self.heap,
+        }
+    }
+
+    /// Looks `key` up in the top frame, then in the global heap.
+    pub fn variable(&self, key: &str) -> Option<&Value> {
+        self.call_stack
+            .last()
+            .and_then(|frame| frame.locals.get(key))
+            .or_else(|| self.heap.get(key))
+    }
+
+    fn pop_condition(&mut self, operation: &'static str) -> Result<bool, VMErrorKind> {
+        match self.pop()? {
+            Value::Bool(value) => Ok(value),
+            other => Err(VMErrorKind::TypeMismatch {
+                operation,
+                lhs: other.type_name(),
+                rhs: None,
+            }),
+        }
+    }
+
+    fn execute(&mut self, opcode: Opcode) -> Result<VMResult, VMErrorKind> {
         use opcode::Opcode::*;
         match opcode {
             Literal(value) => {
-                self.stack.push(value);
-                self.profile.total_stack_pushes += 1;
-                return VMResult::Ok;
+                self.push(value)?;
             }
             Arithmetic(arithmetic) => {
                 use opcode::ArithmeticOpcode::*;
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                self.profile.total_stack_pops += 2;
+                let a = self.pop()?;
+                if let Some(b) = self.stack.last() {
+                    self.check_string_result(&arithmetic, &a, b)?;
+                }
                 let result = match arithmetic {
-                    Add => a + b,
-                    Sub => a - b,
-                    Mul => a * b,
-                    Div => a / b,
-                };
-                self.stack.push(result);
-                self.profile.total_stack_pushes += 1;
-                return VMResult::Ok;
+                    Neg => -a,
+                    Add => a + self.pop()?,
+                    Sub => a - self.pop()?,
+                    Mul => a * self.pop()?,
+                    Div => a / self.pop()?,
+                    Mod => a % self.pop()?,
+                }?;
+                self.push(result)?;
             }
             Logic(logic) => {
                 use opcode::LogicOpcode::*;
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                self.profile.total_stack_pops += 2;
+                let a = self.pop()?;
                 let result = match logic {
-                    And => a.and(b),
-                    Or => a.or(b),
-                    Xor => a.xor(b),
                     Not => !a,
-                };
-                self.stack.push(result);
-                self.profile.total_stack_pushes += 1;
-                return VMResult::Ok;
+                    And => a.and(self.pop()?),
+                    Or => a.or(self.pop()?),
+                    Xor => a.xor(self.pop()?),
+                }?;
+                self.push(result)?;
             }
             Comparison(comparison) => {
                 use opcode::ComparisonOpcode::*;
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                self.profile.total_stack_pops += 2;
+                let a = self.pop()?;
+                let b = self.pop()?;
+                // an unordered comparison (NaN) is false
                 let result = match comparison {
-                    Eq => a == b,
-                    Ne => a != b,
-                    Le => a <= b,
-                    Lt => a < b,
-                    Gt => a > b,
-                    Ge => a >= b,
+                    Eq => a.equals(&b),
+                    Ne => !a.equals(&b),
+                    Le => a.compare(&b)?.is_some_and(|o| o.is_le()),
+                    Lt => a.compare(&b)?.is_some_and(|o| o.is_lt()),
+                    Gt => a.compare(&b)?.is_some_and(|o| o.is_gt()),
+                    Ge => a.compare(&b)?.is_some_and(|o| o.is_ge()),
                 };
-                self.stack.push(value::Value::Bool(result));
-                self.profile.total_stack_pushes += 1;
-                return VMResult::Ok;
+                self.push(value::Value::Bool(result))?;
             }
             Memory(memory) => {
                 use opcode::MemoryOpcode::*;
                 match memory {
                     Load => {
-                        let value::Value::String(key) = self.stack.pop().unwrap() else {
-                            return VMResult::Error(VMError::InvalidOpcodeArgument);
-                        };
-                        self.profile.total_stack_pops += 1;
-                        let value = self.heap.get(&key).unwrap();
+                        let key = self.pop_key("load")?;
+                        let value = self
+                            .variable(&key)
+                            .cloned()
+                            .ok_or(VMErrorKind::UndefinedVariable(key))?;
                         self.profile.total_memory_reads += 1;
-                        self.stack.push(value.clone());
-                        self.profile.total_stack_pushes += 1;
-                        return VMResult::Ok;
+                        self.push(value)?;
                     }
                     Store => {
-                        let value::Value::String(key) = self.stack.pop().unwrap() else {
-                            return VMResult::Error(VMError::InvalidOpcodeArgument);
-                        };
-                        let value = self.stack.pop().unwrap();
-                        self.profile.total_stack_pops += 2;
-                        self.heap.insert(key, value);
+                        let key = self.pop_key("store")?;
+                        // `push` already checked the size of the value
+                        let value = self.pop()?;
+                        let max_entries = self.limits.max_heap_entries;
+                        let variables = self.variables_mut();
+                        if let Some(limit) = max_entries
+                            && !variables.contains_key(&key)
+                            && variables.len() >= limit
+                        {
+                            return Err(VMErrorKind::HeapLimitExceeded(limit));
+                        }
+                        variables.insert(key, value);
                         self.profile.total_memory_writes += 1;
-                        return VMResult::Ok;
                     }
                 }
             }
@@ -145,18 +406,15 @@ impl VM {
                 use opcode::IOOpcode::*;
                 match io {
                     Print => {
-                        let value = self.stack.pop().unwrap();
-                        self.profile.total_stack_pops += 1;
-                        println!("{:?}", value);
-                        return VMResult::Ok;
+                        let value = self.pop()?;
+                        self.io
+                            .print(&value)
+                            .map_err(|e| VMErrorKind::Io(e.to_string()))?;
                     }
                     Scan => {
-                        let mut input = String::new();
-                        std::io::stdin().read_line(&mut input).unwrap();
-                        self.stack
-                            .push(value::Value::String(input.trim().to_string()));
-                        self.profile.total_stack_pushes += 1;
-                        return VMResult::Ok;
+                        let input = self.io.scan().map_err(|e| VMErrorKind::Io(e.to_string()))?;
+                        // end of input reads as null
+                        self.push(input.map_or(Value::Null, Value::String))?;
                     }
                 }
             }
@@ -164,53 +422,125 @@ impl VM {
                 use opcode::FlowOpcode;
                 match flow {
                     FlowOpcode::JumpIfTrue(label) => {
-                        let value = self.stack.pop().unwrap();
-                        if value == Value::Bool(true) {
+                        if self.pop_condition("jump_if_true")? {
                             self.pc = label as usize;
                         }
-                        VMResult::Ok
                     }
                     FlowOpcode::JumpIfFalse(label) => {
-                        let value = self.stack.pop().unwrap();
-                        if value == Value::Bool(false) {
+                        if !self.pop_condition("jump_if_false")? {
                             self.pc = label as usize;
                         }
-                        VMResult::Ok
                     }
                     FlowOpcode::Jump(label) => {
                         self.pc = label as usize;
-                        VMResult::Ok
                     }
                     FlowOpcode::Call(label) => {
-                        self.call_stack.push(self.pc);
+                        if let Some(limit) = self.limits.max_call_depth
+                            && self.call_stack.len() >= limit
+                        {
+                            return Err(VMErrorKind::CallDepthExceeded(limit));
+                        }
+                        self.call_stack.push(Frame {
+                            return_address: self.pc,
+                            locals: HashMap::new(),
+                        });
                         self.pc = label as usize;
-                        VMResult::Ok
                     }
                     FlowOpcode::Return => {
-                        self.pc = self.call_stack.pop().unwrap();
-                        VMResult::Ok
+                        // the return value stays on the operand stack
+                        let frame = self
+                            .call_stack
+                            .pop()
+                            .ok_or(VMErrorKind::ReturnWithoutCall)?;
+                        self.pc = frame.return_address;
+                    }
+                }
+            }
+            Collection(collection) => {
+                use opcode::CollectionOpcode;
+                match collection {
+                    CollectionOpcode::BuildList(len) => {
+                        let start = self
+                            .stack
+                            .len()
+                            .checked_sub(len as usize)
+                            .ok_or(VMErrorKind::StackUnderflow)?;
+                        let items = self.stack.split_off(start);
+                        self.profile.total_stack_pops += items.len();
+                        self.push(Value::List(items))?;
+                    }
+                    CollectionOpcode::Get => {
+                        let index = self.pop()?;
+                        let collection = self.pop()?;
+                        self.push(collection.get(&index)?)?;
+                    }
+                    CollectionOpcode::Set => {
+                        let value = self.pop()?;
+                        let index = self.pop()?;
+                        let collection = self.pop()?;
+                        self.push(collection.set(&index, value)?)?;
+                    }
+                    CollectionOpcode::Len => {
+                        let len = self.pop()?.len()?;
+                        let len = u32::try_from(len)
+                            .map(Value::UInt)
+                            .unwrap_or(Value::Int(len as i64));
+                        self.push(len)?;
+                    }
+                    CollectionOpcode::Push => {
+                        let value = self.pop()?;
+                        let list = self.pop()?;
+                        self.push(list.push(value)?)?;
+                    }
+                    CollectionOpcode::Pop => {
+                        let (list, last) = self.pop()?.pop()?;
+                        self.push(list)?;
+                        self.push(last)?;
+                    }
+                    CollectionOpcode::BuildMap(len) => {
+                        let start = self
+                            .stack
+                            .len()
+                            .checked_sub(2 * len as usize)
+                            .ok_or(VMErrorKind::StackUnderflow)?;
+                        let items = self.stack.split_off(start);
+                        self.profile.total_stack_pops += items.len();
+                        let mut entries = BTreeMap::new();
+                        let mut items = items.into_iter();
+                        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                            entries.insert(key.map_key()?, value);
+                        }
+                        self.push(Value::Map(entries))?;
+                    }
+                    CollectionOpcode::Keys => {
+                        let keys = self.pop()?.keys()?;
+                        self.push(keys)?;
+                    }
+                    CollectionOpcode::Contains => {
+                        let needle = self.pop()?;
+                        let collection = self.pop()?;
+                        self.push(Value::Bool(collection.contains(&needle)?))?;
                     }
                 }
             }
             Dup => {
-                let value = self.stack.last().unwrap();
-                self.stack.push(value.clone());
-                VMResult::Ok
+                let value = self.stack.last().ok_or(VMErrorKind::StackUnderflow)?;
+                self.push(value.clone())?;
             }
             Pop => {
-                self.stack.pop();
-                VMResult::Ok
+                self.pop()?;
             }
             Swap => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                self.stack.push(a);
-                self.stack.push(b);
-                VMResult::Ok
+                let a = self.pop()?;
+                let b = self.pop()?;
+                self.push(a)?;
+                self.push(b)?;
             }
             Halt => {
-                return VMResult::Halt;
+                return Ok(VMResult::Halt);
             }
         }
+
+        Ok(VMResult::Ok)
     }
 }
@@ -1,17 +1,183 @@
-use clap::Parser;
+use clap::Parser as _;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use vmo2_asm::disassembler;
+use vmo2_compiler::{
+    diagnostic::Diagnostic, ir_compiler, ir_text, optimizer, parser, peephole, resolver,
+    type_checker,
+};
+use vmo2_serde::{metadata::Version, serialize::serialize};
+use vmo2_types::bytecode::ByteCode;
+use vmo2_vm::vm::VM;
 
-#[derive(Parser)]
+#[derive(clap::Parser)]
 struct Compiler {
-    #[clap(short, long)]
-    verbose: bool,
+    #[clap(subcommand)]
+    command: Command,
 }
 
-fn main() {
-    let args = Compiler::parse();
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Compile an Oxyde program into a serialized bytecode file
+    Build {
+        input: PathBuf,
 
-    if args.verbose {
-        println!("Verbose mode enabled");
+        /// Output file, defaults to the input with a `.vmo2` extension.
+        /// With `--emit`, defaults to stdout
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+
+        /// Write an intermediate stage instead of the bytecode file
+        #[clap(long, value_enum)]
+        emit: Option<Emit>,
+
+        #[clap(flatten)]
+        options: CompileOptions,
+    },
+    /// Compile an Oxyde program and run it
+    Run {
+        input: PathBuf,
+
+        #[clap(flatten)]
+        options: CompileOptions,
+    },
+}
+
+#[derive(clap::Args)]
+struct CompileOptions {
+    /// Check types before lowering and annotate the IR with them
+    #[clap(long)]
+    typecheck: bool,
+
+    /// Optimization level: 0 for none, 1 folds constants and, with
+    /// `--typecheck`, simplifies identities such as `x + 0`, then runs the
+    /// bytecode peephole pass, 2 also removes dead code
+    #[clap(
+        short = 'O',
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u8).range(0..=2)
+    )]
+    opt_level: u8,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Emit {
+    /// The syntax tree
+    Ast,
+    /// The IR, function by function
+    Ir,
+    /// The bytecode as `.vasm` assembly
+    Asm,
+    /// A hex dump of the serialized bytecode
+    Bytecode,
+}
+
+/// Every stage of a compilation, kept for `--emit`.
+struct Compilation {
+    ast: String,
+    ir: String,
+    bytecode: ByteCode,
+}
+
+fn compile(path: &Path, options: &CompileOptions) -> Result<Compilation, String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    let diagnostic = |e| Diagnostic::new(path.display().to_string(), &e).render(&source);
+
+    let program = parser::parse_source(&source).map_err(diagnostic)?;
+    resolver::resolve(&program).map_err(diagnostic)?;
+    let ast = format!("{program:#?}\n");
+    let mut ir = if options.typecheck {
+        let types = type_checker::check(&program).map_err(diagnostic)?;
+        ir_compiler::compile_to_typed_ir(program, &types)
     } else {
-        println!("Verbose mode disabled");
+        ir_compiler::compile_to_ir(program)
+    }
+    .map_err(diagnostic)?;
+    optimizer::optimize(&mut ir, options.opt_level);
+    let ir_text = ir_text::print(&ir);
+    let mut bytecode = ir_compiler::ir_to_bytecode(ir).map_err(diagnostic)?;
+    if options.opt_level >= 1 {
+        bytecode = peephole::optimize(bytecode);
+    }
+    Ok(Compilation {
+        ast,
+        ir: ir_text,
+        bytecode,
+    })
+}
+
+/// `xxd`-style dump: offset, 16 bytes in hex, printable ASCII.
+fn hex_dump(data: &[u8]) -> String {
+    let mut out = String::new();
+    for (line, chunk) in data.chunks(16).enumerate() {
+        write!(out, "{:08x}:", line * 16).unwrap();
+        for byte in chunk {
+            write!(out, " {byte:02x}").unwrap();
+        }
+        let padding = 3 * (16 - chunk.len());
+        let text: String = chunk
+            .iter()
+            .map(|&byte| match byte {
+                0x20..=0x7e => byte as char,
+                _ => '.',
+            })
+            .collect();
+        writeln!(out, "{:padding$}  {text}", "").unwrap();
+    }
+    out
+}
+
+fn build(
+    input: &Path,
+    output: Option<PathBuf>,
+    emit: Option<Emit>,
+    options: &CompileOptions,
+) -> Result<(), String> {
+    let compilation = compile(input, options)?;
+    let data = match emit {
+        None => serialize(Version::V1, &compilation.bytecode),
+        Some(Emit::Ast) => compilation.ast.into_bytes(),
+        Some(Emit::Ir) => compilation.ir.into_bytes(),
+        Some(Emit::Asm) => disassembler::disassemble(&compilation.bytecode).into_bytes(),
+        Some(Emit::Bytecode) => {
+            hex_dump(&serialize(Version::V1, &compilation.bytecode)).into_bytes()
+        }
+    };
+
+    let output = match (output, emit) {
+        (Some(output), _) => output,
+        (None, None) => input.with_extension("vmo2"),
+        (None, Some(_)) => {
+            print!("{}", String::from_utf8_lossy(&data));
+            return Ok(());
+        }
+    };
+    std::fs::write(&output, data).map_err(|e| format!("cannot write {}: {e}", output.display()))
+}
+
+fn run(input: &Path, options: &CompileOptions) -> Result<(), String> {
+    let mut vm = VM::new(compile(input, options)?.bytecode);
+    vm.run().map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn main() -> ExitCode {
+    let result = match Compiler::parse().command {
+        Command::Build {
+            input,
+            output,
+            emit,
+            options,
+        } => build(&input, output, emit, &options),
+        Command::Run { input, options } => run(&input, &options),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
     }
 }
@@ -1,53 +1,212 @@
-use vmo2_types::{bytecode::*, opcode::*, value::*};
-use vmo2_vm::vm::VM;
+use clap::Parser as _;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use vmo2_asm::{assembler, disassembler};
+use vmo2_compiler::{diagnostic::Diagnostic, ir_compiler, parser, resolver};
+use vmo2_serde::metadata::MAGIC;
+use vmo2_types::{bytecode::*, source_map::SourceMap};
+use vmo2_vm::{
+    debugger::Debugger,
+    limits::Limits,
+    verifier,
+    vm::{VM, VMError, VMResult},
+};
 
-fn main() {
-    // random program
-    let mut bytecode = ByteCode::new();
+#[derive(clap::Parser)]
+struct Vmo2 {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Run a program: serialized bytecode, `.vasm` assembly or Oxyde source
+    Run {
+        file: PathBuf,
+
+        #[clap(flatten)]
+        options: RunOptions,
+    },
+    /// Run the built-in demo
+    Demo {
+        #[clap(flatten)]
+        options: RunOptions,
+    },
+}
+
+#[derive(clap::Args)]
+struct RunOptions {
+    /// Print the execution profile to stderr once the program stops
+    #[clap(short, long)]
+    profile: bool,
+
+    /// Print every instruction and the top of the stack to stderr before
+    /// executing it
+    #[clap(short, long)]
+    trace: bool,
+
+    /// Start the interactive debugger instead of running the program
+    #[clap(short, long)]
+    debug: bool,
+
+    /// Check the program with the static verifier first and refuse to run
+    /// it when that fails
+    #[clap(long)]
+    verify: bool,
+
+    /// Stop after executing this many instructions
+    #[clap(long, value_name = "N")]
+    max_instructions: Option<usize>,
+
+    /// Maximum number of values on the operand stack
+    #[clap(long, value_name = "N")]
+    max_stack_depth: Option<usize>,
+
+    /// Maximum number of nested calls
+    #[clap(long, value_name = "N")]
+    max_call_depth: Option<usize>,
 
-    /*
-       n = 0
-       while n < 10 {
-           print n
-           n++
-       }
-    */
+    /// Maximum number of variables in the heap and in each call frame
+    #[clap(long, value_name = "N")]
+    max_heap_entries: Option<usize>,
 
-    // n = 0
-    bytecode.add_opcode(Opcode::Literal(Value::UInt(0)));
-    bytecode.add_opcode(Opcode::Literal(Value::String("n".to_string())));
-    bytecode.add_opcode(Opcode::Memory(MemoryOpcode::Store));
+    /// Maximum size of any value the program builds
+    #[clap(long, value_name = "N")]
+    max_value_size: Option<usize>,
+}
 
-    // while loop
-    bytecode.add_opcode(Opcode::Literal(Value::String("n".to_string())));
-    bytecode.add_opcode(Opcode::Memory(MemoryOpcode::Load));
-    bytecode.add_opcode(Opcode::Dup);
+impl RunOptions {
+    fn limits(&self) -> Limits {
+        Limits {
+            max_instructions: self.max_instructions,
+            max_stack_depth: self.max_stack_depth,
+            max_call_depth: self.max_call_depth,
+            max_heap_entries: self.max_heap_entries,
+            max_value_size: self.max_value_size,
+        }
+    }
+}
 
-    bytecode.add_opcode(Opcode::Literal(Value::UInt(10)));
-    bytecode.add_opcode(Opcode::Comparison(ComparisonOpcode::Le));
+/// Assembles the built-in demo program.
+fn demo() -> ByteCode {
+    assembler::assemble(include_str!("demo.vasm")).unwrap()
+}
 
-    bytecode.add_opcode(Opcode::Flow(FlowOpcode::JumpIfTrue(17)));
+/*
+ *  Loads the program at `path`. Files starting with the serde magic number
+ *  are deserialized, `.vasm` files are assembled and anything else is
+ *  compiled as Oxyde source, which also gives a source map for the
+ *  debugger and error messages.
+ */
+fn load(path: &Path) -> Result<(ByteCode, Option<SourceMap>), String> {
+    let data = std::fs::read(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
 
-    bytecode.add_opcode(Opcode::Dup);
-    bytecode.add_opcode(Opcode::IO(IOOpcode::Print));
+    if data.starts_with(&MAGIC.to_le_bytes()) {
+        let bytecode = vmo2_serde::deserialize::deserialize(&data)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        return Ok((bytecode, None));
+    }
 
-    // n++ (using n thats duplicated before)
-    bytecode.add_opcode(Opcode::Literal(Value::UInt(1)));
-    bytecode.add_opcode(Opcode::Arithmetic(ArithmeticOpcode::Add));
+    let source = String::from_utf8(data)
+        .map_err(|_| format!("{}: not a bytecode or source file", path.display()))?;
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "vasm")
+    {
+        let bytecode =
+            assembler::assemble(&source).map_err(|e| format!("{}: {e}", path.display()))?;
+        return Ok((bytecode, None));
+    }
 
-    // store n
-    bytecode.add_opcode(Opcode::Literal(Value::String("n".to_string())));
-    bytecode.add_opcode(Opcode::Memory(MemoryOpcode::Store));
+    let (bytecode, source_map) = parser::parse_source(&source)
+        .and_then(|program| resolver::resolve(&program).map(|()| program))
+        .and_then(ir_compiler::compile_to_ir)
+        .and_then(ir_compiler::ir_to_bytecode_with_source_map)
+        .map_err(|e| Diagnostic::new(path.display().to_string(), &e).render(&source))?;
+    Ok((bytecode, Some(source_map)))
+}
 
-    bytecode.add_opcode(Opcode::Flow(FlowOpcode::Jump(3)));
-    // end of while loop
+/// Runs `vm` until it halts, printing each instruction first when tracing.
+fn execute(vm: &mut VM, trace: bool) -> Result<(), VMError> {
+    loop {
+        if trace && let Some(opcode) = vm.bytecode.opcodes.get(vm.pc) {
+            let top: Vec<String> = vm
+                .stack
+                .iter()
+                .rev()
+                .take(3)
+                .map(|v| v.to_string())
+                .collect();
+            let more = if vm.stack.len() > 3 { ", ..." } else { "" };
+            eprintln!(
+                "{:>6}  {:<24} [{}{more}]",
+                vm.pc,
+                disassembler::format_instruction(opcode),
+                top.join(", ")
+            );
+        }
+        match vm.step() {
+            VMResult::Ok => continue,
+            VMResult::Error(error) => return Err(error),
+            VMResult::Halt => return Ok(()),
+        }
+    }
+}
 
-    // pop unused
-    bytecode.add_opcode(Opcode::Pop);
+/// Prints `error`, raised at `pc`, with its source location when known.
+fn report(error: impl std::fmt::Display, pc: usize, source_map: Option<&SourceMap>) {
+    match source_map.and_then(|map| map.location(pc)) {
+        Some(location) => eprintln!(
+            "error: {error}, line {} column {}",
+            location.line, location.column
+        ),
+        None => eprintln!("error: {error}"),
+    }
+}
 
-    bytecode.add_opcode(Opcode::Halt);
+fn run(bytecode: ByteCode, source_map: Option<SourceMap>, options: &RunOptions) -> ExitCode {
+    if options.verify
+        && let Err(error) = verifier::verify(&bytecode)
+    {
+        report(&error, error.pc, source_map.as_ref());
+        return ExitCode::FAILURE;
+    }
+
+    let mut vm = VM::new(bytecode).with_limits(options.limits());
+
+    if options.debug {
+        let mut debugger = Debugger::new(vm);
+        if let Some(source_map) = source_map {
+            debugger = debugger.with_source_map(source_map);
+        }
+        if let Err(error) = debugger.repl(std::io::stdin().lock(), std::io::stdout()) {
+            eprintln!("error: {error}");
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let result = execute(&mut vm, options.trace);
+    if options.profile {
+        eprintln!("{}", vm.profile);
+    }
+
+    let Err(error) = result else {
+        return ExitCode::SUCCESS;
+    };
+    report(&error, error.pc, source_map.as_ref());
+    ExitCode::FAILURE
+}
 
-    let mut vm = VM::new(bytecode);
-    let profile = vm.run().unwrap();
-    println!("Profile: {:?}", profile);
+fn main() -> ExitCode {
+    match Vmo2::parse().command {
+        Command::Run { file, options } => match load(&file) {
+            Ok((bytecode, source_map)) => run(bytecode, source_map, &options),
+            Err(message) => {
+                eprintln!("error: {message}");
+                ExitCode::FAILURE
+            }
+        },
+        Command::Demo { options } => run(demo(), None, &options),
+    }
 }